@@ -0,0 +1,312 @@
+//! Minimal HTTP/1.1 client.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::io::{IoError, TcpStream, EndOfFile, BrokenPipe, ConnectionReset};
+
+use http;
+use http::parser::{ParseResponse, ParseError};
+use http::reader::{Message, MessageReader, ReadIoError, ReadParseError};
+use http::serializer::MessageWriter;
+use http::status;
+
+pub use http::split_url;
+pub use self::pool::Pool;
+
+pub mod pool;
+
+/// A list specifying categories of client errors.
+#[deriving(PartialEq, Clone, Show)]
+pub enum ClientError {
+    /// I/O error of the connection, including timeouts.
+    ClientIoError(IoError),
+    /// Invalid response.
+    ClientParseError(ParseError),
+    /// URL is not an absolute `http` URL.
+    InvalidUrl,
+    /// Redirects exceed the limit.
+    TooManyRedirects,
+}
+
+/// Result of requests.
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// Response read by `Client`.
+#[deriving(PartialEq, Clone, Show)]
+pub struct Response {
+    /// Status line and header fields.
+    pub message: Message,
+    /// Whole message body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    #[inline]
+    /// Returns the status code.
+    pub fn status(&self) -> http::StatusCode {
+        self.message.status.unwrap_or(http::StatusCode(0))
+    }
+
+    #[inline]
+    /// Find the first header value with the name.
+    pub fn header<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.message.header(name)
+    }
+}
+
+/// HTTP/1.1 client which reuses kept-alive connections.
+pub struct Client {
+    timeout: Option<u64>,
+    max_redirects: uint,
+    pool: Pool,
+}
+
+/// Connection to a server, kept in `Pool` while idle.
+pub struct Connection {
+    host: String,
+    port: u16,
+    reader: MessageReader<TcpStream>,
+}
+
+impl Client {
+    /// Create a new `Client`.
+    pub fn new() -> Client {
+        Client {
+            timeout: None,
+            max_redirects: 5,
+            pool: Pool::new(),
+        }
+    }
+
+    /// Create a new `Client` with the connection pool.
+    pub fn with_pool(pool: Pool) -> Client {
+        Client {
+            timeout: None,
+            max_redirects: 5,
+            pool: pool,
+        }
+    }
+
+    /// Gets a mutable reference to the connection pool.
+    pub fn pool<'a>(&'a mut self) -> &'a mut Pool {
+        &mut self.pool
+    }
+
+    /// Set read and write timeout in milliseconds, applied to each request.
+    pub fn set_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.timeout = timeout_ms;
+    }
+
+    /// Set the maximum number of redirects to follow. `0` disables redirects.
+    pub fn set_max_redirects(&mut self, max: uint) {
+        self.max_redirects = max;
+    }
+
+    /// Send `GET` request.
+    pub fn get(&mut self, url: &str) -> ClientResult<Response> {
+        self.request(http::HttpGet, url, [], [])
+    }
+
+    /// Send a request, and read the response following redirects.
+    ///
+    /// `303 See Other`, and `301`/`302` for `POST` are followed with `GET` without body. If a kept-alive
+    /// connection is closed by the server, idempotent requests are retried on a new connection.
+    ///
+    /// `Host` is given by the URL after a redirect, and `Authorization` and `Cookie` are not sent to
+    /// other origins.
+    pub fn request(&mut self, method: http::HttpMethod, url: &str,
+                   headers: &[(&str, &str)], body: &[u8]) -> ClientResult<Response> {
+        let (origin_host, origin_port) = match split_url(url) {
+            Some((host, port, _)) => (host, port),
+            None => return Err(InvalidUrl),
+        };
+        let mut method = method;
+        let mut url = url.to_string();
+        let mut headers = headers.to_vec();
+        let mut body = body;
+        let mut redirects = 0u;
+        loop {
+            let (host, port, target) = match split_url(url.as_slice()) {
+                Some(parts) => parts,
+                None => return Err(InvalidUrl),
+            };
+            let response = try!(self.send(method, host.as_slice(), port, target.as_slice(), headers.as_slice(), body));
+            let code = response.status();
+            let location = match code.code() {
+                301 | 302 | 303 | 307 | 308 => response.header("Location"),
+                _ => None,
+            };
+            let location = match location {
+                Some(location) => location.to_string(),
+                None => return Ok(response),
+            };
+            if redirects == self.max_redirects { return Err(TooManyRedirects) }
+            redirects += 1;
+            url = resolve_location(host.as_slice(), port, target.as_slice(), location.as_slice());
+
+            headers.retain(|&(name, _)| !name.eq_ignore_ascii_case("Host"));
+            let same_origin = match split_url(url.as_slice()) {
+                Some((host, port, _)) => {
+                    host.as_slice().eq_ignore_ascii_case(origin_host.as_slice()) && port == origin_port
+                }
+                None => false,
+            };
+            if !same_origin {
+                headers.retain(|&(name, _)| {
+                    !name.eq_ignore_ascii_case("Authorization") && !name.eq_ignore_ascii_case("Cookie")
+                });
+            }
+            if code == status::SEE_OTHER || (method == http::HttpPost &&
+                                             (code == status::MOVED_PERMANENTLY || code == status::FOUND)) {
+                method = http::HttpGet;
+                body = &[];
+                headers.retain(|&(name, _)| {
+                    !name.eq_ignore_ascii_case("Content-Length") && !name.eq_ignore_ascii_case("Content-Type") &&
+                        !name.eq_ignore_ascii_case("Transfer-Encoding")
+                });
+            }
+        }
+    }
+
+    fn send(&mut self, method: http::HttpMethod, host: &str, port: u16, target: &str,
+            headers: &[(&str, &str)], body: &[u8]) -> ClientResult<Response> {
+        let conn = self.pool.take(host, port);
+        let reused = conn.is_some();
+        match self.send_on(conn, method, host, port, target, headers, body) {
+            // kept-alive connection may be closed by the server. Non-idempotent requests may have
+            // been processed already, so they are not retried.
+            Err(ClientIoError(ref e)) if reused && method.is_idempotent() &&
+                (e.kind == EndOfFile || e.kind == BrokenPipe || e.kind == ConnectionReset) => {
+                self.send_on(None, method, host, port, target, headers, body)
+            }
+            result => result,
+        }
+    }
+
+    fn send_on(&mut self, conn: Option<Connection>, method: http::HttpMethod, host: &str, port: u16,
+               target: &str, headers: &[(&str, &str)], body: &[u8]) -> ClientResult<Response> {
+        let mut conn = match conn {
+            Some(conn) => conn,
+            None => {
+                let stream = try!(TcpStream::connect(host, port).map_err(ClientIoError));
+                Connection {
+                    host: host.to_string(),
+                    port: port,
+                    reader: MessageReader::new(stream, ParseResponse),
+                }
+            }
+        };
+
+        // timeouts are deadlines, and are not shared by cloned streams.
+        conn.reader.get_mut().set_timeout(self.timeout);
+        let mut stream = conn.reader.get_ref().clone();
+        stream.set_timeout(self.timeout);
+        try!(write_request(stream, method, host, port, target, headers, body).map_err(ClientIoError));
+
+        conn.reader.set_skip_body(method == http::HttpHead);
+        let mut message;
+        loop {
+            message = match conn.reader.read_message() {
+                Ok(message) => message,
+                Err(ReadIoError(e)) => return Err(ClientIoError(e)),
+                Err(ReadParseError(e)) => return Err(ClientParseError(e)),
+            };
+            // skip interim responses, e.g. 100 Continue
+            match message.status {
+                Some(code) if code.is_informational() && code != status::SWITCHING_PROTOCOLS => (),
+                _ => break,
+            }
+        }
+        let body = match conn.reader.body().read_to_end() {
+            Ok(body) => body,
+            Err(e) => return Err(match conn.reader.error() {
+                Some(e) => ClientParseError(e),
+                None => ClientIoError(e),
+            }),
+        };
+        if message.keep_alive && !message.upgrade {
+            self.pool.put(conn);
+        }
+        Ok(Response { message: message, body: body })
+    }
+}
+
+// Resolve `Location` header value against the request URL, see RFC 3986 section 5.2.
+fn resolve_location(host: &str, port: u16, target: &str, location: &str) -> String {
+    let location = location.trim();
+    if location.starts_with("//") { return format!("http:{}", location) }
+    if has_scheme(location) { return location.to_string() }
+
+    let (path, rest) = match location.find(|c: char| c == '?' || c == '#') {
+        Some(pos) => (location.slice_to(pos), location.slice_from(pos)),
+        None => (location, ""),
+    };
+    let base = match target.find('?') {
+        Some(pos) => target.slice_to(pos),
+        None => target,
+    };
+    let path = if path.len() == 0 {
+        // a fragment only keeps the query of the request.
+        if !rest.starts_with("?") { return format!("http://{}{}", http::authority(host, port), target) }
+        base.to_string()
+    } else if path.starts_with("/") {
+        path.to_string()
+    } else {
+        let dir = match base.rfind('/') {
+            Some(pos) => base.slice_to(pos + 1),
+            None => "/",
+        };
+        format!("{}{}", dir, path)
+    };
+    format!("http://{}{}{}", http::authority(host, port), remove_dot_segments(path.as_slice()), rest)
+}
+
+fn has_scheme(s: &str) -> bool {
+    match s.find(':') {
+        Some(pos) if pos > 0 => s.slice_to(pos).chars().enumerate().all(|(i, c)| match c {
+            'a'..'z' | 'A'..'Z' => true,
+            '0'..'9' | '+' | '-' | '.' => i > 0,
+            _ => false,
+        }),
+        _ => false,
+    }
+}
+
+// `path` starts with `/`.
+fn remove_dot_segments(path: &str) -> String {
+    let parts: Vec<&str> = path.split('/').skip(1).collect();
+    let mut segments = Vec::new();
+    for (i, &segment) in parts.iter().enumerate() {
+        let last = i == parts.len() - 1;
+        match segment {
+            "." => if last { segments.push("") },
+            ".." => {
+                segments.pop();
+                if last { segments.push("") }
+            }
+            s => segments.push(s),
+        }
+    }
+    format!("/{}", segments.as_slice().connect("/"))
+}
+
+fn write_request(stream: TcpStream, method: http::HttpMethod, host: &str, port: u16, target: &str,
+                 headers: &[(&str, &str)], body: &[u8]) -> Result<TcpStream, IoError> {
+    let mut w = MessageWriter::new(stream);
+    try!(w.request_line(method, target, http::HTTP_1_1));
+    if !headers.iter().any(|&(name, _)| name.eq_ignore_ascii_case("Host")) {
+        try!(w.header("Host", http::authority(host, port).as_slice()));
+    }
+    for &(name, value) in headers.iter() {
+        try!(w.header(name, value));
+    }
+    if body.len() > 0 || method.allows_request_body() {
+        w.body(body)
+    } else {
+        w.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests;
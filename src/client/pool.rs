@@ -0,0 +1,101 @@
+//! Keep-alive connection pool.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::collections::HashMap;
+use time;
+
+use client::Connection;
+
+/// Idle connections keyed by `host:port`.
+///
+/// Connections are reused in LIFO order, and evicted after the idle timeout. Connections with
+/// unread body or parse error are never pooled.
+pub struct Pool {
+    idle: HashMap<String, Vec<(u64, Connection)>>,
+    idle_timeout: u64,
+    max_idle_per_host: uint,
+}
+
+impl Pool {
+    /// Create a new `Pool`, with 90 seconds idle timeout and 8 connections per host.
+    pub fn new() -> Pool {
+        Pool {
+            idle: HashMap::new(),
+            idle_timeout: 90_000,
+            max_idle_per_host: 8,
+        }
+    }
+
+    /// Set idle timeout in milliseconds.
+    pub fn set_idle_timeout(&mut self, timeout_ms: u64) {
+        self.idle_timeout = timeout_ms;
+    }
+
+    /// Set the maximum number of idle connections for each host. `0` disables pooling.
+    pub fn set_max_idle_per_host(&mut self, max: uint) {
+        self.max_idle_per_host = max;
+    }
+
+    /// Take an idle connection to the host.
+    pub fn take(&mut self, host: &str, port: u16) -> Option<Connection> {
+        self.evict_expired();
+        let key = pool_key(host, port);
+        let conn = match self.idle.find_mut(&key) {
+            Some(conns) => conns.pop().map(|(_, conn)| conn),
+            None => None,
+        };
+        if self.idle.find(&key).map_or(false, |conns| conns.is_empty()) {
+            self.idle.remove(&key);
+        }
+        conn
+    }
+
+    /// Return a connection to the pool.
+    ///
+    /// Returns false and closes the connection if it is not reusable.
+    pub fn put(&mut self, conn: Connection) -> bool {
+        if !conn.reader.is_reusable() { return false }
+        let key = pool_key(conn.host.as_slice(), conn.port);
+        let max = self.max_idle_per_host;
+        let conns = self.idle.find_or_insert_with(key, |_| Vec::new());
+        if conns.len() >= max { return false }
+        conns.push((now_ms(), conn));
+        true
+    }
+
+    /// Close idle connections exceeding the idle timeout.
+    pub fn evict_expired(&mut self) {
+        let now = now_ms();
+        let timeout = self.idle_timeout;
+        for (_, conns) in self.idle.mut_iter() {
+            conns.retain(|&(since, _)| now - since < timeout);
+        }
+        let empty: Vec<String> = self.idle.iter()
+            .filter(|&(_, conns)| conns.is_empty())
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in empty.iter() {
+            self.idle.remove(key);
+        }
+    }
+
+    /// Returns the number of idle connections.
+    pub fn idle_count(&self) -> uint {
+        self.idle.values().fold(0, |sum, conns| sum + conns.len())
+    }
+
+    /// Close all idle connections.
+    pub fn clear(&mut self) {
+        self.idle.clear();
+    }
+}
+
+fn pool_key(host: &str, port: u16) -> String {
+    format!("{}:{}", host.to_ascii_lower(), port)
+}
+
+fn now_ms() -> u64 {
+    time::precise_time_ns() / 1_000_000
+}
@@ -0,0 +1,239 @@
+use http::*;
+use http::parser::{ParseRequest, ParseResponse};
+use http::reader::MessageReader;
+use client::*;
+
+use std::io::{Acceptor, Listener, TimedOut, TcpListener, TcpStream};
+use std::io::timer::sleep;
+
+/// Serve canned responses on a single connection, `{url}` is replaced with the request URL, and
+/// `{headers}` with the request header fields.
+fn serve<S: Str + Send>(responses: Vec<S>) -> u16 {
+    serve_connections(vec!(responses))
+}
+
+/// Serve canned responses on each accepted connection in order.
+fn serve_connections<S: Str + Send>(connections: Vec<Vec<S>>) -> u16 {
+    let mut listener = TcpListener::bind("127.0.0.1", 0).unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    spawn(proc() {
+        for responses in connections.iter() {
+            let mut stream = acceptor.accept().unwrap();
+            let mut reader = MessageReader::new(stream.clone(), ParseRequest);
+            for response in responses.iter() {
+                let request = match reader.read_message() {
+                    Ok(request) => request,
+                    Err(_) => break,
+                };
+                let _ = reader.body().read_to_end();
+                let url = request.url.clone().unwrap();
+                let headers: Vec<String> = request.headers.iter()
+                    .map(|&(ref name, ref value)| format!("{}: {}\n", name, value))
+                    .collect();
+                let response = response.as_slice().replace("{url}", url.as_slice())
+                    .replace("{headers}", headers.as_slice().concat().as_slice());
+                if stream.write(response.as_bytes()).is_err() { break }
+            }
+        }
+    });
+    port
+}
+
+#[test]
+fn test_get() {
+    let port = serve(vec!("HTTP/1.1 200 OK\r\nX-Url: {url}\r\nContent-Length: 3\r\n\r\nbee"));
+    let mut client = Client::new();
+    let response = client.get(format!("http://127.0.0.1:{}/get?q=1#top", port).as_slice()).unwrap();
+    assert_eq!(response.status(), status::OK);
+    assert_eq!(response.header("X-Url"), Some("/get?q=1"));
+    assert_eq!(response.body, "bee".as_bytes().to_vec());
+}
+
+#[test]
+fn test_keep_alive() {
+    let port = serve(vec!("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+                          "HTTP/1.1 200 OK\r\nX-Url: {url}\r\nContent-Length: 6\r\n\r\nsecond"));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "first".as_bytes().to_vec());
+    let response = client.request(HttpPost, format!("{}post", url).as_slice(), [], "name=bee".as_bytes()).unwrap();
+    assert_eq!(response.header("X-Url"), Some("/post"));
+    assert_eq!(response.body, "second".as_bytes().to_vec());
+}
+
+#[test]
+fn test_head_and_interim() {
+    let port = serve(vec!("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n",
+                          "HTTP/1.0 200 OK\r\n\r\nuntil eof"));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    let response = client.request(HttpHead, url.as_slice(), [], []).unwrap();
+    assert_eq!(response.status(), status::OK);
+    assert_eq!(response.body, vec!());
+    let response = client.get(url.as_slice()).unwrap();
+    assert_eq!(response.message.version, Some(HTTP_1_0));
+    assert_eq!(response.body, "until eof".as_bytes().to_vec());
+}
+
+#[test]
+fn test_body_until_eof() {
+    // HTTP/1.1 is persistent by default, but the body ends by closing the connection.
+    let port = serve_connections(vec!(vec!("HTTP/1.1 200 OK\r\n\r\nuntil eof"),
+                                      vec!("HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond")));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    let response = client.get(url.as_slice()).unwrap();
+    assert!(response.message.keep_alive);
+    assert_eq!(response.body, "until eof".as_bytes().to_vec());
+    assert_eq!(client.pool().idle_count(), 0);
+    let response = client.request(HttpPost, url.as_slice(), [], "name=bee".as_bytes()).unwrap();
+    assert_eq!(response.body, "second".as_bytes().to_vec());
+}
+
+#[test]
+fn test_redirect() {
+    let port = serve(vec!("HTTP/1.1 303 See Other\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 200 OK\r\nX-Url: {url}\r\nContent-Length: 0\r\n\r\n"));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/post", port);
+    let response = client.request(HttpPost, url.as_slice(), [], "name=bee".as_bytes()).unwrap();
+    assert_eq!(response.status(), status::OK);
+    assert_eq!(response.header("X-Url"), Some("/next"));
+}
+
+#[test]
+fn test_redirect_relative() {
+    let port = serve(vec!("HTTP/1.1 302 Found\r\nLocation: b/../c?x=1\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 302 Found\r\nLocation: ?y=2\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 302 Found\r\nLocation: ../../up\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 200 OK\r\nX-Url: {url}\r\nContent-Length: 0\r\n\r\n"));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/dir/a?q", port);
+    let response = client.get(url.as_slice()).unwrap();
+    assert_eq!(response.header("X-Url"), Some("/up"));
+}
+
+#[test]
+fn test_redirect_other_origin() {
+    let other = serve(vec!("HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n{headers}"));
+    let port = serve(vec!(format!("HTTP/1.1 302 Found\r\nLocation: //127.0.0.1:{}/next\r\n\
+                                   Content-Length: 0\r\n\r\n", other)));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    let headers = [("Host", "example.com"), ("Authorization", "Basic YTpi"), ("Cookie", "a=1"), ("Accept", "*/*")];
+    let response = client.request(HttpGet, url.as_slice(), headers, []).unwrap();
+    assert_eq!(String::from_utf8(response.body).unwrap(),
+               format!("Host: 127.0.0.1:{}\nAccept: */*\n", other));
+}
+
+#[test]
+fn test_too_many_redirects() {
+    let port = serve(vec!("HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n"));
+    let mut client = Client::new();
+    client.set_max_redirects(1);
+    let url = format!("http://127.0.0.1:{}/", port);
+    assert_eq!(client.get(url.as_slice()), Err(TooManyRedirects));
+}
+
+#[test]
+fn test_timeout() {
+    let mut listener = TcpListener::bind("127.0.0.1", 0).unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let acceptor = listener.listen().unwrap();
+    let mut client = Client::new();
+    client.set_timeout(Some(50));
+    match client.get(format!("http://127.0.0.1:{}/", port).as_slice()) {
+        Err(ClientIoError(ref e)) if e.kind == TimedOut => (),
+        other => fail!("unexpected result: {}", other),
+    }
+    drop(acceptor);
+}
+
+#[test]
+fn test_timeout_per_request() {
+    let port = serve(vec!("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+                          "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond"));
+    let mut client = Client::new();
+    client.set_timeout(Some(100));
+    let url = format!("http://127.0.0.1:{}/", port);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "first".as_bytes().to_vec());
+    // the pooled connection is reused after the timeout has passed since it was connected.
+    sleep(200);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "second".as_bytes().to_vec());
+}
+
+#[test]
+fn test_pool_reuse() {
+    let port = serve(vec!("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    client.get(url.as_slice()).unwrap();
+    assert_eq!(client.pool().idle_count(), 1);
+    client.get(url.as_slice()).unwrap();
+    assert_eq!(client.pool().idle_count(), 0);
+}
+
+#[test]
+fn test_pool_eviction() {
+    let port = serve_connections(vec!(vec!("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"),
+                                      vec!("HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond")));
+    let mut client = Client::new();
+    client.pool().set_idle_timeout(0);
+    let url = format!("http://127.0.0.1:{}/", port);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "first".as_bytes().to_vec());
+    assert_eq!(client.pool().idle_count(), 1);
+    client.pool().evict_expired();
+    assert_eq!(client.pool().idle_count(), 0);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "second".as_bytes().to_vec());
+}
+
+#[test]
+fn test_pool_stale_connection() {
+    // the server closes a kept-alive connection, the client retries on a new connection.
+    let port = serve_connections(vec!(vec!("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"),
+                                      vec!("HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond")));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "first".as_bytes().to_vec());
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "second".as_bytes().to_vec());
+}
+
+#[test]
+fn test_pool_stale_connection_not_idempotent() {
+    let port = serve_connections(vec!(vec!("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst"),
+                                      vec!("HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond")));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "first".as_bytes().to_vec());
+    assert!(client.request(HttpPost, url.as_slice(), [], "name=bee".as_bytes()).is_err());
+    assert_eq!(client.get(url.as_slice()).unwrap().body, "second".as_bytes().to_vec());
+}
+
+#[test]
+fn test_pool_discard() {
+    let port = serve(vec!("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst",
+                          "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"));
+    let stream = TcpStream::connect("127.0.0.1", port).unwrap();
+    let mut conn = Connection {
+        host: "127.0.0.1".to_string(),
+        port: port,
+        reader: MessageReader::new(stream.clone(), ParseResponse),
+    };
+    let mut pool = Pool::new();
+    let mut writer = stream.clone();
+    writer.write("GET / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+    conn.reader.read_message().unwrap();
+    assert!(!pool.put(conn)); // unread body
+
+    let port = serve(vec!("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n",
+                          "HTTP/1.1 200 OK\r\nBad Header\r\n\r\n"));
+    let mut client = Client::new();
+    let url = format!("http://127.0.0.1:{}/", port);
+    client.get(url.as_slice()).unwrap();
+    assert_eq!(client.pool().idle_count(), 1);
+    assert!(client.get(url.as_slice()).is_err());
+    assert_eq!(client.pool().idle_count(), 0);
+}
@@ -0,0 +1,296 @@
+//! HTTP caching, i.e. `Cache-Control`, `Expires` and `Age` headers, see RFC 9111.
+//!
+//! A cache decides whether to store a response with `is_cacheable`, and whether to serve a stored
+//! response with `is_fresh`. Otherwise the stored response is revalidated with the headers given
+//! by `conditional_headers`, and updated by `update_headers` on `304 Not Modified`.
+//!
+//! Times are the seconds of `Timespec` given by the caller, e.g. `time::get_time()`.
+
+#![experimental]
+
+use I64_MAX = std::i64::MAX;
+
+use std::ascii::StrAsciiExt;
+use std::cmp::max;
+use time::Timespec;
+
+use http;
+use http::date::parse_date;
+use http::proxy::is_hop_by_hop;
+use http::reader::Message;
+
+/// Parsed `Cache-Control` directives of a request or response.
+///
+/// Unknown directives are ignored, and the first one wins if a directive is repeated.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct CacheControl {
+    /// max-age in seconds.
+    pub max_age: Option<i64>,
+    /// s-maxage in seconds, for shared caches.
+    pub s_maxage: Option<i64>,
+    /// max-stale in seconds. `i64::MAX` if no value is given, i.e. any staleness is accepted.
+    pub max_stale: Option<i64>,
+    /// min-fresh in seconds.
+    pub min_fresh: Option<i64>,
+    /// no-cache, with or without field names.
+    pub no_cache: bool,
+    /// no-store
+    pub no_store: bool,
+    /// no-transform
+    pub no_transform: bool,
+    /// only-if-cached
+    pub only_if_cached: bool,
+    /// must-revalidate
+    pub must_revalidate: bool,
+    /// proxy-revalidate
+    pub proxy_revalidate: bool,
+    /// private, with or without field names.
+    pub private: bool,
+    /// public
+    pub public: bool,
+}
+
+impl CacheControl {
+    /// Create a `CacheControl` without directives.
+    pub fn new() -> CacheControl {
+        CacheControl {
+            max_age: None,
+            s_maxage: None,
+            max_stale: None,
+            min_fresh: None,
+            no_cache: false,
+            no_store: false,
+            no_transform: false,
+            only_if_cached: false,
+            must_revalidate: false,
+            proxy_revalidate: false,
+            private: false,
+            public: false,
+        }
+    }
+
+    /// Parse `Cache-Control` header value, e.g. `max-age=3600, must-revalidate`.
+    pub fn parse(value: &str) -> CacheControl {
+        let mut cc = CacheControl::new();
+        for directive in value.split(',').map(|s| s.trim()).filter(|s| s.len() > 0) {
+            let (name, arg) = match directive.find('=') {
+                Some(pos) => {
+                    let arg = directive.slice_from(pos + 1).trim();
+                    let arg = if arg.len() >= 2 && arg.starts_with("\"") && arg.ends_with("\"") {
+                        arg.slice(1, arg.len() - 1)
+                    } else {
+                        arg
+                    };
+                    (directive.slice_to(pos).trim(), Some(arg))
+                }
+                None => (directive, None),
+            };
+            let name = name.to_ascii_lower();
+            let seconds = arg.and_then(|arg| delta_seconds(arg));
+            match name.as_slice() {
+                "max-age" if cc.max_age.is_none() => cc.max_age = seconds,
+                "s-maxage" if cc.s_maxage.is_none() => cc.s_maxage = seconds,
+                "max-stale" if cc.max_stale.is_none() => {
+                    cc.max_stale = if arg.is_none() { Some(I64_MAX) } else { seconds };
+                }
+                "min-fresh" if cc.min_fresh.is_none() => cc.min_fresh = seconds,
+                "no-cache" => cc.no_cache = true,
+                "no-store" => cc.no_store = true,
+                "no-transform" => cc.no_transform = true,
+                "only-if-cached" => cc.only_if_cached = true,
+                "must-revalidate" => cc.must_revalidate = true,
+                "proxy-revalidate" => cc.proxy_revalidate = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                _ => (),
+            }
+        }
+        cc
+    }
+
+    /// Parse all `Cache-Control` headers of the message.
+    pub fn from_message(message: &Message) -> CacheControl {
+        CacheControl::parse(message.headers_named("Cache-Control").as_slice().connect(",").as_slice())
+    }
+}
+
+/// Returns true if the response to the request may be stored, see RFC 9111 section 3.
+///
+/// Only complete responses to GET and HEAD requests are stored. `shared` is true for shared caches,
+/// e.g. reverse proxies, which do not store `private` responses nor responses to requests with
+/// `Authorization` unless explicitly allowed.
+pub fn is_cacheable(request: &Message, response: &Message, shared: bool) -> bool {
+    match request.method {
+        Some(http::HttpGet) | Some(http::HttpHead) => (),
+        _ => return false,
+    }
+    let status = match response.status {
+        Some(status) if !status.is_informational() => status,
+        _ => return false,
+    };
+    if status == http::status::PARTIAL_CONTENT || status == http::status::NOT_MODIFIED { return false }
+    // `Vary: *` never matches following requests.
+    if response.headers_named("Vary").iter().any(|v| v.split(',').any(|f| f.trim() == "*")) { return false }
+
+    let req = CacheControl::from_message(request);
+    let res = CacheControl::from_message(response);
+    if req.no_store || res.no_store { return false }
+    if shared {
+        if res.private { return false }
+        if request.header("Authorization").is_some() &&
+            !(res.must_revalidate || res.public || res.s_maxage.is_some()) {
+            return false;
+        }
+    }
+    res.public || res.max_age.is_some() || shared && res.s_maxage.is_some() ||
+        response.header("Expires").is_some() || is_heuristically_cacheable(status)
+}
+
+/// Returns true if the status code is cacheable by default, i.e. freshness can be heuristic.
+pub fn is_heuristically_cacheable(status: http::StatusCode) -> bool {
+    match status.code() {
+        200 | 203 | 204 | 206 | 300 | 301 | 308 | 404 | 405 | 410 | 414 | 501 => true,
+        _ => false,
+    }
+}
+
+/// Returns freshness lifetime of the response in seconds, see RFC 9111 section 4.2.1.
+///
+/// `s-maxage` for shared caches, `max-age`, `Expires` and heuristic freshness are used in order.
+/// Heuristic freshness is 10% of the time since `Last-Modified`. `response_time` is used if `Date`
+/// is missing. Returns `None` if the response has no freshness information.
+pub fn freshness_lifetime(response: &Message, shared: bool, response_time: Timespec) -> Option<i64> {
+    let cc = CacheControl::from_message(response);
+    match (cc.s_maxage, cc.max_age) {
+        (Some(age), _) if shared => return Some(age),
+        (_, Some(age)) => return Some(age),
+        _ => (),
+    }
+    let date = date_value(response, response_time);
+    match response.header("Expires") {
+        // invalid dates, e.g. "0", represent a time in the past.
+        Some(expires) => return Some(match parse_date(expires) {
+            Some(expires) => max(expires.sec - date, 0),
+            None => 0,
+        }),
+        None => (),
+    }
+    match (response.status, response.header("Last-Modified").and_then(|v| parse_date(v))) {
+        (Some(status), Some(modified)) if is_heuristically_cacheable(status) || cc.public => {
+            Some(max(date - modified.sec, 0) / 10)
+        }
+        _ => None,
+    }
+}
+
+/// Returns current age of the stored response in seconds, see RFC 9111 section 4.2.3.
+///
+/// `request_time` and `response_time` are when the request was sent and the response was received.
+pub fn current_age(response: &Message, request_time: Timespec, response_time: Timespec, now: Timespec) -> i64 {
+    let age_value = response.header("Age").and_then(|v| delta_seconds(v.trim())).unwrap_or(0);
+    let apparent_age = max(response_time.sec - date_value(response, response_time), 0);
+    let response_delay = response_time.sec - request_time.sec;
+    let corrected_initial_age = max(apparent_age, age_value + response_delay);
+    corrected_initial_age + now.sec - response_time.sec
+}
+
+/// Returns true if the stored response can be served for the request without revalidation.
+///
+/// `no-cache` of either message, and `max-age`, `min-fresh` and `max-stale` of the request are
+/// applied. Stale responses are served only if the request allows with `max-stale`, and the
+/// response has no `must-revalidate`, or `proxy-revalidate` and `s-maxage` for shared caches.
+pub fn is_fresh(request: &Message, response: &Message, shared: bool, request_time: Timespec,
+                response_time: Timespec, now: Timespec) -> bool {
+    let req = CacheControl::from_message(request);
+    let res = CacheControl::from_message(response);
+    // `Pragma: no-cache` is used only without `Cache-Control`.
+    let pragma = request.header("Cache-Control").is_none() && request.headers_named("Pragma").iter()
+        .any(|v| v.split(',').any(|d| d.trim().eq_ignore_ascii_case("no-cache")));
+    if req.no_cache || res.no_cache || pragma { return false }
+
+    let lifetime = freshness_lifetime(response, shared, response_time).unwrap_or(0);
+    let age = current_age(response, request_time, response_time, now);
+    match req.max_age {
+        Some(max_age) if age > max_age => return false,
+        _ => (),
+    }
+    match req.min_fresh {
+        Some(min_fresh) if lifetime - age < min_fresh => return false,
+        _ => (),
+    }
+    if age < lifetime { return true }
+
+    let revalidate = res.must_revalidate || shared && (res.proxy_revalidate || res.s_maxage.is_some());
+    match req.max_stale {
+        Some(max_stale) if !revalidate => age - lifetime <= max_stale,
+        _ => false,
+    }
+}
+
+/// Returns header fields of a conditional request to revalidate the stored response.
+///
+/// Conditional headers of the request are replaced with `If-None-Match` given by `ETag`, and
+/// `If-Modified-Since` given by `Last-Modified` of the stored response. Hop-by-hop headers are
+/// removed. Returns `None` if the stored response has no validator.
+pub fn conditional_headers(request: &Message, stored: &Message) -> Option<Vec<(String, String)>> {
+    let etag = stored.header("ETag");
+    let modified = stored.header("Last-Modified");
+    if etag.is_none() && modified.is_none() { return None }
+    let mut headers: Vec<(String, String)> = request.headers.iter()
+        .filter(|&&(ref name, _)| {
+            !CONDITIONAL_HEADERS.iter().any(|n| n.eq_ignore_ascii_case(name.as_slice())) &&
+                !is_hop_by_hop(request, name.as_slice())
+        })
+        .map(|&(ref name, ref value)| (name.clone(), value.clone()))
+        .collect();
+    match etag {
+        Some(etag) => headers.push(("If-None-Match".to_string(), etag.to_string())),
+        None => (),
+    }
+    match modified {
+        Some(modified) => headers.push(("If-Modified-Since".to_string(), modified.to_string())),
+        None => (),
+    }
+    Some(headers)
+}
+
+/// Update header fields of the stored response with `304 Not Modified` response, see RFC 9111
+/// section 4.3.4.
+///
+/// Header fields given by the 304 response replace the stored ones with the same name, except
+/// `Content-Length` and hop-by-hop headers.
+pub fn update_headers(stored: &mut Message, not_modified: &Message) {
+    let updates: Vec<(String, String)> = not_modified.headers.iter()
+        .filter(|&&(ref name, _)| {
+            !name.as_slice().eq_ignore_ascii_case("Content-Length") &&
+                !is_hop_by_hop(not_modified, name.as_slice())
+        })
+        .map(|&(ref name, ref value)| (name.clone(), value.clone()))
+        .collect();
+    stored.headers.retain(|&(ref name, _)| {
+        !updates.iter().any(|&(ref n, _)| n.as_slice().eq_ignore_ascii_case(name.as_slice()))
+    });
+    stored.headers.push_all_move(updates);
+}
+
+static CONDITIONAL_HEADERS: &'static [&'static str] = &[
+    "If-Match", "If-None-Match", "If-Modified-Since", "If-Unmodified-Since", "If-Range",
+];
+
+fn date_value(response: &Message, response_time: Timespec) -> i64 {
+    match response.header("Date").and_then(|v| parse_date(v)) {
+        Some(date) => date.sec,
+        None => response_time.sec,
+    }
+}
+
+// delta-seconds, which is capped at 2^31 if too large.
+fn delta_seconds(value: &str) -> Option<i64> {
+    if value.len() == 0 || !value.chars().all(|c| c >= '0' && c <= '9') { return None }
+    match from_str::<i64>(value) {
+        Some(n) if n < MAX_DELTA_SECONDS => Some(n),
+        _ => Some(MAX_DELTA_SECONDS),
+    }
+}
+
+static MAX_DELTA_SECONDS: i64 = 2147483648;
@@ -0,0 +1,111 @@
+//! Chunked transfer coding encoder.
+
+#![experimental]
+
+use std::io::{IoError, IoResult, InvalidInput};
+
+use http;
+
+/// Writer which encodes data with chunked transfer coding.
+///
+/// Each `write` emits one chunk. Call `finish` or `finish_with_trailers` to write the last chunk.
+pub struct ChunkedWriter<W> {
+    writer: W,
+}
+
+impl<W: Writer> ChunkedWriter<W> {
+    /// Create a new `ChunkedWriter`.
+    pub fn new(writer: W) -> ChunkedWriter<W> {
+        ChunkedWriter {
+            writer: writer,
+        }
+    }
+
+    /// Write a chunk with chunk extensions.
+    ///
+    /// Extension values which are not tokens are written as quoted strings.
+    pub fn write_chunk(&mut self, data: &[u8], extensions: &[(&str, &str)]) -> IoResult<()> {
+        write_chunk(&mut self.writer, data, extensions)
+    }
+
+    /// Write the last chunk, and returns the underlying writer.
+    pub fn finish(self) -> IoResult<W> {
+        self.finish_with_trailers([])
+    }
+
+    /// Write the last chunk with trailer fields, and returns the underlying writer.
+    pub fn finish_with_trailers(mut self, trailers: &[(&str, &str)]) -> IoResult<W> {
+        try!(write_last_chunk(&mut self.writer, trailers));
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref<'a>(&'a self) -> &'a W {
+        &self.writer
+    }
+}
+
+impl<W: Writer> Writer for ChunkedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.write_chunk(buf, [])
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.writer.flush()
+    }
+}
+
+/// Write a chunk to the writer.
+///
+/// Empty data is ignored, because a chunk with zero size means the last chunk. Extensions are
+/// validated before writing, so nothing is written on error.
+pub fn write_chunk<W: Writer>(writer: &mut W, data: &[u8], extensions: &[(&str, &str)]) -> IoResult<()> {
+    if data.len() == 0 { return Ok(()) }
+    for &(name, value) in extensions.iter() {
+        if !http::is_token(name) { return Err(invalid_input("invalid chunk extension name", name)) }
+        if !value.as_bytes().iter().all(|&b| http::is_field_value_char(b)) {
+            return Err(invalid_input("invalid chunk extension value", value));
+        }
+    }
+    try!(write!(writer, "{:x}", data.len()));
+    for &(name, value) in extensions.iter() {
+        try!(write!(writer, ";{}", name));
+        if value.len() == 0 { continue }
+        if http::is_token(value) {
+            try!(write!(writer, "={}", value));
+        } else {
+            try!(write!(writer, "=\"{}\"", value.replace("\\", "\\\\").replace("\"", "\\\"")));
+        }
+    }
+    try!(writer.write("\r\n".as_bytes()));
+    try!(writer.write(data));
+    writer.write("\r\n".as_bytes())
+}
+
+/// Write the last chunk and trailer fields to the writer.
+///
+/// Trailer fields are validated before writing, so nothing is written on error.
+pub fn write_last_chunk<W: Writer>(writer: &mut W, trailers: &[(&str, &str)]) -> IoResult<()> {
+    for &(name, value) in trailers.iter() {
+        if name.len() == 0 || !name.as_bytes().iter().all(|&b| http::is_field_name_char(b)) {
+            return Err(invalid_input("invalid trailer name", name));
+        }
+        if !value.as_bytes().iter().all(|&b| http::is_field_value_char(b)) {
+            return Err(invalid_input("invalid trailer value", value));
+        }
+    }
+    try!(writer.write("0\r\n".as_bytes()));
+    for &(name, value) in trailers.iter() {
+        try!(write!(writer, "{}: {}\r\n", name, value));
+    }
+    writer.write("\r\n".as_bytes())
+}
+
+fn invalid_input(desc: &'static str, detail: &str) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: Some(detail.to_string()),
+    }
+}
@@ -0,0 +1,225 @@
+//! Cookies, i.e. `Cookie` and `Set-Cookie` headers, see RFC 6265.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::fmt::{Formatter, FormatError, Show};
+use time::Timespec;
+
+use http;
+use http::date::{parse_date, format_date};
+
+/// A list of `SameSite` attribute values.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum SameSite {
+    /// SameSite=Strict
+    SameSiteStrict,
+    /// SameSite=Lax
+    SameSiteLax,
+    /// SameSite=None
+    SameSiteNone,
+}
+
+impl SameSite {
+    /// Returns the attribute value.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SameSiteStrict => "Strict",
+            SameSiteLax    => "Lax",
+            SameSiteNone   => "None",
+        }
+    }
+}
+
+/// A cookie given by `Set-Cookie` header.
+///
+/// `Show` formats the header value as is, so check `is_valid` before formatting untrusted values.
+#[deriving(PartialEq, Eq, Clone)]
+pub struct SetCookie {
+    /// Cookie name.
+    pub name: String,
+    /// Cookie value.
+    pub value: String,
+    /// Expires attribute.
+    pub expires: Option<Timespec>,
+    /// Max-Age attribute in seconds. Zero or negative value expires the cookie immediately.
+    pub max_age: Option<i64>,
+    /// Domain attribute without leading dot, in lowercase.
+    pub domain: Option<String>,
+    /// Path attribute. `None` means the default path.
+    pub path: Option<String>,
+    /// Secure attribute.
+    pub secure: bool,
+    /// HttpOnly attribute.
+    pub http_only: bool,
+    /// SameSite attribute.
+    pub same_site: Option<SameSite>,
+}
+
+impl SetCookie {
+    /// Create a `SetCookie` without attributes.
+    pub fn new(name: &str, value: &str) -> SetCookie {
+        SetCookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            expires: None,
+            max_age: None,
+            domain: None,
+            path: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Parse `Set-Cookie` header value with the user agent algorithm of RFC 6265 section 5.2.
+    ///
+    /// Returns `None` if the name-value pair is invalid. Invalid attributes are ignored, and the
+    /// last one wins if an attribute is repeated.
+    pub fn parse(value: &str) -> Option<SetCookie> {
+        let mut parts = value.split(';');
+        let (name, value) = match parts.next().and_then(|s| split_pair(s)) {
+            Some((name, _)) if name.len() == 0 => return None,
+            Some(pair) => pair,
+            None => return None,
+        };
+        let mut cookie = SetCookie::new(name, value);
+        for attr in parts {
+            let (name, value) = match split_pair(attr) {
+                Some(pair) => pair,
+                None => (attr.trim(), ""),
+            };
+            let name = name.to_ascii_lower();
+            match name.as_slice() {
+                "expires" => match parse_date(value) {
+                    Some(time) => cookie.expires = Some(time),
+                    None => (),
+                },
+                "max-age" => {
+                    let digits = if value.starts_with("-") { value.slice_from(1) } else { value };
+                    if digits.len() > 0 && digits.chars().all(|c| c >= '0' && c <= '9') {
+                        match from_str::<i64>(value) {
+                            Some(age) => cookie.max_age = Some(age),
+                            None => (),
+                        }
+                    }
+                }
+                "domain" if value.len() > 0 => {
+                    let domain = if value.starts_with(".") { value.slice_from(1) } else { value };
+                    cookie.domain = Some(domain.to_ascii_lower());
+                }
+                "path" => {
+                    cookie.path = if value.starts_with("/") { Some(value.to_string()) } else { None };
+                }
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    let value = value.to_ascii_lower();
+                    match value.as_slice() {
+                        "strict" => cookie.same_site = Some(SameSiteStrict),
+                        "lax" => cookie.same_site = Some(SameSiteLax),
+                        "none" => cookie.same_site = Some(SameSiteNone),
+                        _ => (),
+                    }
+                }
+                _ => (),
+            }
+        }
+        Some(cookie)
+    }
+
+    /// Returns true if the cookie can be formatted as a `Set-Cookie` header value, see RFC 6265
+    /// section 4.1.1.
+    ///
+    /// The name must be a token, and the value cookie-octets, optionally in double quotes. Domain
+    /// and Path must not contain control characters or `;`, so no attribute can be injected.
+    pub fn is_valid(&self) -> bool {
+        is_cookie_pair(self.name.as_slice(), self.value.as_slice()) &&
+            self.domain.as_ref().map_or(true, |d| d.len() > 0 && is_attribute_value(d.as_slice())) &&
+            self.path.as_ref().map_or(true, |p| is_attribute_value(p.as_slice()))
+    }
+}
+
+impl Show for SetCookie {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        try!(write!(f, "{}={}", self.name, self.value));
+        match self.expires {
+            Some(time) => try!(write!(f, "; Expires={}", format_date(time))),
+            None => (),
+        }
+        match self.max_age {
+            Some(age) => try!(write!(f, "; Max-Age={}", age)),
+            None => (),
+        }
+        match self.domain {
+            Some(ref domain) => try!(write!(f, "; Domain={}", domain)),
+            None => (),
+        }
+        match self.path {
+            Some(ref path) => try!(write!(f, "; Path={}", path)),
+            None => (),
+        }
+        if self.secure { try!(write!(f, "; Secure")) }
+        if self.http_only { try!(write!(f, "; HttpOnly")) }
+        match self.same_site {
+            Some(same_site) => try!(write!(f, "; SameSite={}", same_site.name())),
+            None => (),
+        }
+        Ok(())
+    }
+}
+
+/// Parse `Cookie` header value into name-value pairs in received order.
+///
+/// Pairs without `=` are ignored, and values quoted with `"` are unquoted.
+pub fn parse_cookie(value: &str) -> Vec<(String, String)> {
+    value.split(';').filter_map(|s| split_pair(s))
+        .filter(|&(name, _)| name.len() > 0)
+        .map(|(name, value)| {
+            let value = if value.len() >= 2 && value.starts_with("\"") && value.ends_with("\"") {
+                value.slice(1, value.len() - 1)
+            } else {
+                value
+            };
+            (name.to_string(), value.to_string())
+        })
+        .collect()
+}
+
+/// Format name-value pairs as `Cookie` header value.
+///
+/// Returns `None` if a name is not a token, or a value has other than cookie-octets.
+pub fn format_cookie(cookies: &[(&str, &str)]) -> Option<String> {
+    if !cookies.iter().all(|&(name, value)| is_cookie_pair(name, value)) { return None }
+    let pairs: Vec<String> = cookies.iter().map(|&(name, value)| format!("{}={}", name, value)).collect();
+    Some(pairs.as_slice().connect("; "))
+}
+
+fn is_cookie_pair(name: &str, value: &str) -> bool {
+    let value = if value.len() >= 2 && value.starts_with("\"") && value.ends_with("\"") {
+        value.slice(1, value.len() - 1)
+    } else {
+        value
+    };
+    http::is_token(name) && value.as_bytes().iter().all(|&b| is_cookie_octet(b))
+}
+
+// US-ASCII characters excluding CTLs, whitespace, DQUOTE, comma, semicolon and backslash.
+#[inline]
+fn is_cookie_octet(b: u8) -> bool {
+    b == 0x21 || (b >= 0x23 && b <= 0x2b) || (b >= 0x2d && b <= 0x3a) ||
+        (b >= 0x3c && b <= 0x5b) || (b >= 0x5d && b <= 0x7e)
+}
+
+// any CHAR except CTLs or `;`.
+#[inline]
+fn is_attribute_value(s: &str) -> bool {
+    s.as_bytes().iter().all(|&b| b >= 0x20 && b <= 0x7e && b != 0x3b)
+}
+
+fn split_pair<'a>(s: &'a str) -> Option<(&'a str, &'a str)> {
+    match s.find('=') {
+        Some(pos) => Some((s.slice_to(pos).trim(), s.slice_from(pos + 1).trim())),
+        None => None,
+    }
+}
@@ -0,0 +1,123 @@
+//! HTTP dates, e.g. `Date`, `Expires` and `Last-Modified` headers, see RFC 7231 section 7.1.1.1.
+//!
+//! Dates are parsed with the lenient algorithm of RFC 6265, which accepts IMF-fixdate and the
+//! obsolete RFC 850 and asctime formats, and formatted as IMF-fixdate.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use time::Timespec;
+
+/// Parse a date with the algorithm of RFC 6265 section 5.1.1, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`.
+pub fn parse_date(value: &str) -> Option<Timespec> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for token in value.split(|c: char| is_date_delimiter(c)).filter(|t| t.len() > 0) {
+        if time.is_none() {
+            time = parse_time(token);
+            if time.is_some() { continue }
+        }
+        if day.is_none() {
+            day = digits(token, 1, 2).map(|(n, _)| n);
+            if day.is_some() { continue }
+        }
+        if month.is_none() && token.len() >= 3 && token.is_char_boundary(3) {
+            let prefix = token.slice_to(3).to_ascii_lower();
+            month = MONTHS.iter().position(|m| m.to_ascii_lower() == prefix).map(|m| m as i64 + 1);
+            if month.is_some() { continue }
+        }
+        if year.is_none() {
+            year = digits(token, 2, 4).map(|(n, _)| match n {
+                70..99 => n + 1900,
+                0..69 => n + 2000,
+                _ => n,
+            });
+        }
+    }
+    let ((hour, minute, second), day, month, year) = match (time, day, month, year) {
+        (Some(t), Some(d), Some(m), Some(y)) => (t, d, m, y),
+        _ => return None,
+    };
+    if year < 1601 || hour > 23 || minute > 59 || second > 59 { return None }
+    if day < 1 || day > days_in_month(year, month) { return None }
+    let days = days_from_civil(year, month, day);
+    Some(Timespec::new(days * 86400 + hour * 3600 + minute * 60 + second, 0))
+}
+
+/// Format a time as IMF-fixdate, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`.
+pub fn format_date(time: Timespec) -> String {
+    let days = div_floor(time.sec, 86400);
+    let secs = time.sec - days * 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!("{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            WEEKDAYS[((days % 7 + 11) % 7) as uint], day, MONTHS[(month - 1) as uint], year,
+            secs / 3600, secs % 3600 / 60, secs % 60)
+}
+
+#[inline]
+fn is_date_delimiter(c: char) -> bool {
+    match c {
+        '\x09' | '\x20'..'\x2f' | '\x3b'..'\x40' | '\x5b'..'\x60' | '\x7b'..'\x7e' => true,
+        _ => false,
+    }
+}
+
+/// Parse leading digits, which must be followed by a non-digit or the end.
+fn digits<'a>(s: &'a str, min: uint, max: uint) -> Option<(i64, &'a str)> {
+    let len = s.chars().take_while(|&c| c >= '0' && c <= '9').count();
+    if len < min || len > max { return None }
+    from_str::<i64>(s.slice_to(len)).map(|n| (n, s.slice_from(len)))
+}
+
+fn parse_time(token: &str) -> Option<(i64, i64, i64)> {
+    let (hour, rest) = match digits(token, 1, 2) {
+        Some((n, rest)) if rest.starts_with(":") => (n, rest.slice_from(1)),
+        _ => return None,
+    };
+    let (minute, rest) = match digits(rest, 1, 2) {
+        Some((n, rest)) if rest.starts_with(":") => (n, rest.slice_from(1)),
+        _ => return None,
+    };
+    digits(rest, 1, 2).map(|(second, _)| (hour, minute, second))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[inline]
+fn div_floor(a: i64, b: i64) -> i64 {
+    if a >= 0 { a / b } else { (a - b + 1) / b }
+}
+
+// days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = div_floor(y, 400);
+    let yoe = y - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = div_floor(z, 146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+static MONTHS: &'static [&'static str] = &["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+static WEEKDAYS: &'static [&'static str] = &["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
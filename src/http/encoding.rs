@@ -0,0 +1,1184 @@
+//! Content codings, i.e. `Content-Encoding` header.
+//!
+//! `gzip` and `deflate` are decoded and encoded. `br` is recognized in headers and for negotiation,
+//! but brotli is not implemented: decoding and encoding it fail with `UnsupportedCoding`.
+
+#![experimental]
+
+use UINT_MAX = std::uint::MAX;
+
+use std::ascii::StrAsciiExt;
+use std::cmp::min;
+use std::num::CheckedMul;
+use flate;
+
+use http;
+use http::parser::{Parser, MessageHandler};
+
+/// A list of content codings.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum ContentCoding {
+    /// identity, i.e. no encoding.
+    CodingIdentity,
+    /// gzip (or x-gzip)
+    CodingGzip,
+    /// deflate, i.e. zlib format. Raw deflate data is also accepted.
+    CodingDeflate,
+    /// br
+    CodingBrotli,
+    /// Any coding not part of this list, or multiple codings.
+    CodingOther,
+}
+
+impl ContentCoding {
+    /// Parse a content coding name.
+    pub fn find(name: &str) -> ContentCoding {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("identity") || name.len() == 0 {
+            CodingIdentity
+        } else if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            CodingGzip
+        } else if name.eq_ignore_ascii_case("deflate") {
+            CodingDeflate
+        } else if name.eq_ignore_ascii_case("br") {
+            CodingBrotli
+        } else {
+            CodingOther
+        }
+    }
+
+    /// Parse `Content-Encoding` header value.
+    ///
+    /// Multiple codings except identity result in `CodingOther`.
+    pub fn from_header(value: &str) -> ContentCoding {
+        let mut codings = value.split(',').map(|name| ContentCoding::find(name)).filter(|&c| c != CodingIdentity);
+        match (codings.next(), codings.next()) {
+            (None, _) => CodingIdentity,
+            (Some(coding), None) => coding,
+            (Some(_), Some(_)) => CodingOther,
+        }
+    }
+
+    /// Returns the coding name.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            CodingIdentity => "identity",
+            CodingGzip     => "gzip",
+            CodingDeflate  => "deflate",
+            CodingBrotli   => "br",
+            CodingOther    => "",
+        }
+    }
+}
+
+/// A list specifying categories of content decoding errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum EncodingError {
+    /// Malformed compressed data.
+    InvalidCompressedData,
+    /// CRC32 or Adler-32 checksum, or length does not match.
+    ChecksumMismatch,
+    /// Decompressed data exceeds the limit.
+    SizeLimitExceeded,
+    /// Encoding with the content coding is not supported.
+    UnsupportedCoding,
+    /// Compressing data failed.
+    CompressionFailed,
+}
+
+/// Result of content decoding.
+pub type EncodingResult<T> = Result<T, EncodingError>;
+
+/// Decode data with the content coding.
+///
+/// Decoding fails with `SizeLimitExceeded` as soon as output exceeds `limit` bytes.
+/// `br` and unknown codings are not supported, and fail with `UnsupportedCoding`.
+pub fn decode(coding: ContentCoding, data: &[u8], limit: uint) -> EncodingResult<Vec<u8>> {
+    let mut decoder = try!(Decoder::new(coding));
+    decoder.set_limit(limit);
+    let mut out = Vec::new();
+    try!(decoder.feed(data, &mut out));
+    try!(decoder.finish());
+    Ok(out)
+}
+
+/// Encode data with the content coding.
+///
+/// `deflate` is encoded in the zlib format. Only `gzip`, `deflate` and `identity` are supported.
+/// Fails with `CompressionFailed` if the compressor fails.
+pub fn encode(coding: ContentCoding, data: &[u8]) -> EncodingResult<Vec<u8>> {
+    match coding {
+        CodingGzip => {
+            // no mtime, unknown OS
+            let mut out = vec!(0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff);
+            match flate::deflate_bytes(data) {
+                Some(deflated) => out.push_all(deflated.as_slice()),
+                None => return Err(CompressionFailed),
+            }
+            push_u32_le(&mut out, crc32(data));
+            push_u32_le(&mut out, data.len() as u32);
+            Ok(out)
+        }
+        CodingDeflate => match flate::deflate_bytes_zlib(data) {
+            Some(deflated) => Ok(deflated.as_slice().to_vec()),
+            None => Err(CompressionFailed),
+        },
+        CodingIdentity => Ok(data.to_vec()),
+        CodingBrotli | CodingOther => Err(UnsupportedCoding),
+    }
+}
+
+/// Incremental encoder of a content coding.
+///
+/// Data given by `feed` is compressed as it arrives with fixed Huffman codes and back-references to
+/// the last 32 KiB. `deflate` is encoded in the zlib format.
+///
+/// `flate` only compresses a whole buffer at once, so it can neither stream a body without holding
+/// all of it nor flush what is written so far. This encoder is used for streamed bodies, and
+/// `encode` keeps using `flate` for whole bodies.
+pub struct Encoder {
+    coding: ContentCoding,
+    started: bool,
+    in_block: bool,
+    bitbuf: u32,
+    bitcnt: uint,
+    window: Vec<u8>,
+    offset: uint,
+    head: Vec<uint>,
+    check: u32,
+    size: u32,
+}
+
+impl Encoder {
+    /// Create a new `Encoder` of the content coding.
+    ///
+    /// `br` and unknown codings fail with `UnsupportedCoding`.
+    pub fn new(coding: ContentCoding) -> EncodingResult<Encoder> {
+        match coding {
+            CodingIdentity | CodingGzip | CodingDeflate => (),
+            CodingBrotli | CodingOther => return Err(UnsupportedCoding),
+        }
+        Ok(Encoder {
+            coding: coding,
+            started: false,
+            in_block: false,
+            bitbuf: 0,
+            bitcnt: 0,
+            window: Vec::new(),
+            offset: 0,
+            head: Vec::from_elem(HASH_SIZE, 0u),
+            check: if coding == CodingGzip { 0 } else { 1 },
+            size: 0,
+        })
+    }
+
+    /// Compress a piece of data, and append compressed data to `out`.
+    ///
+    /// Last bits of compressed data are kept until the next call, see `flush`.
+    pub fn feed(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        if self.coding == CodingIdentity {
+            out.push_all(data);
+            return;
+        }
+        if data.is_empty() { return }
+        self.start(out);
+        if !self.in_block {
+            // non-final block with fixed Huffman codes
+            self.put_bits(out, 0b010, 3);
+            self.in_block = true;
+        }
+        if self.coding == CodingGzip {
+            self.check = update_crc32(self.check, data);
+            self.size += data.len() as u32;
+        } else {
+            self.check = update_adler32(self.check, data);
+        }
+
+        let start = self.window.len();
+        self.window.push_all(data);
+        let end = self.window.len();
+        let mut i = start;
+        while i < end {
+            let (len, dist) = self.find_match(i, end);
+            if len >= MIN_MATCH {
+                self.put_match(out, len, dist);
+                for pos in range(i, i + len) {
+                    self.insert(pos, end);
+                }
+                i += len;
+            } else {
+                self.insert(i, end);
+                let literal = self.window.as_slice()[i] as uint;
+                self.put_symbol(out, literal);
+                i += 1;
+            }
+        }
+        if self.window.len() > 2 * WINDOW_SIZE {
+            let removed = self.window.len() - WINDOW_SIZE;
+            let window = self.window.slice_from(removed).to_vec();
+            self.window = window;
+            self.offset += removed;
+        }
+    }
+
+    /// Append all compressed data of given data to `out`, i.e. sync flush, so that the receiver
+    /// can decode them without waiting for more data.
+    pub fn flush(&mut self, out: &mut Vec<u8>) {
+        if !self.in_block { return }
+        self.put_symbol(out, END_OF_BLOCK);
+        // empty stored block aligned to a byte boundary.
+        self.put_bits(out, 0b000, 3);
+        self.align(out);
+        out.push_all([0x00, 0x00, 0xff, 0xff]);
+        self.in_block = false;
+    }
+
+    /// Finish compressed data, and append the rest with the trailer to `out`.
+    pub fn finish(mut self, out: &mut Vec<u8>) {
+        if self.coding == CodingIdentity { return }
+        self.start(out);
+        if self.in_block {
+            self.put_symbol(out, END_OF_BLOCK);
+        }
+        // empty final block with fixed Huffman codes
+        self.put_bits(out, 0b011, 3);
+        self.put_symbol(out, END_OF_BLOCK);
+        self.align(out);
+        if self.coding == CodingGzip {
+            push_u32_le(out, self.check);
+            push_u32_le(out, self.size);
+        } else {
+            push_u32_be(out, self.check);
+        }
+    }
+
+    fn start(&mut self, out: &mut Vec<u8>) {
+        if self.started { return }
+        if self.coding == CodingGzip {
+            // no mtime, unknown OS
+            out.push_all([0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff]);
+        } else {
+            out.push_all([0x78, 0x01]);
+        }
+        self.started = true;
+    }
+
+    // Returns length and distance of the longest match at the hashed position, if any.
+    fn find_match(&self, i: uint, end: uint) -> (uint, uint) {
+        if i + MIN_MATCH > end { return (0, 0) }
+        let window = self.window.as_slice();
+        // positions are stored plus one, and positions trimmed from the window are stale.
+        let candidate = self.head.as_slice()[hash(window.slice_from(i))];
+        if candidate <= self.offset { return (0, 0) }
+        let j = candidate - 1 - self.offset;
+        if i - j > WINDOW_SIZE { return (0, 0) }
+        let max = min(end - i, MAX_MATCH);
+        let mut len = 0;
+        while len < max && window[j + len] == window[i + len] {
+            len += 1;
+        }
+        (len, i - j)
+    }
+
+    fn insert(&mut self, i: uint, end: uint) {
+        if i + MIN_MATCH > end { return }
+        let h = hash(self.window.slice_from(i));
+        self.head.as_mut_slice()[h] = self.offset + i + 1;
+    }
+
+    fn put_match(&mut self, out: &mut Vec<u8>, len: uint, dist: uint) {
+        let n = base_index(LENGTH_BASE.as_slice(), len);
+        self.put_symbol(out, 257 + n);
+        self.put_bits(out, (len - LENGTH_BASE[n] as uint) as u32, LENGTH_EXTRA[n] as uint);
+        let n = base_index(DIST_BASE.as_slice(), dist);
+        self.put_bits(out, reverse_bits(n, 5), 5);
+        self.put_bits(out, (dist - DIST_BASE[n] as uint) as u32, DIST_EXTRA[n] as uint);
+    }
+
+    // Put a literal/length symbol with the fixed Huffman code.
+    fn put_symbol(&mut self, out: &mut Vec<u8>, symbol: uint) {
+        let (code, len) = match symbol {
+            0..143 => (0x30 + symbol, 8),
+            144..255 => (0x190 + symbol - 144, 9),
+            256..279 => (symbol - 256, 7),
+            _ => (0xc0 + symbol - 280, 8),
+        };
+        self.put_bits(out, reverse_bits(code, len), len);
+    }
+
+    fn put_bits(&mut self, out: &mut Vec<u8>, value: u32, n: uint) {
+        self.bitbuf |= value << self.bitcnt;
+        self.bitcnt += n;
+        while self.bitcnt >= 8 {
+            out.push(self.bitbuf as u8);
+            self.bitbuf >>= 8;
+            self.bitcnt -= 8;
+        }
+    }
+
+    fn align(&mut self, out: &mut Vec<u8>) {
+        if self.bitcnt > 0 {
+            out.push(self.bitbuf as u8);
+        }
+        self.bitbuf = 0;
+        self.bitcnt = 0;
+    }
+}
+
+// Hash of the first 3 bytes.
+#[inline]
+fn hash(data: &[u8]) -> uint {
+    let n = data[0] as u32 << 16 | data[1] as u32 << 8 | data[2] as u32;
+    (n * 2654435761 >> (32 - HASH_BITS)) as uint
+}
+
+// Huffman codes are packed starting with the most significant bit.
+#[inline]
+fn reverse_bits(code: uint, len: uint) -> u32 {
+    let mut reversed = 0u32;
+    for i in range(0, len) {
+        reversed |= ((code >> i) & 1) as u32 << (len - 1 - i);
+    }
+    reversed
+}
+
+// Index of the largest base not greater than n.
+fn base_index(bases: &[u16], n: uint) -> uint {
+    let mut i = bases.len() - 1;
+    while bases[i] as uint > n {
+        i -= 1;
+    }
+    i
+}
+
+/// Parsed `Accept-Encoding` header, see RFC 9110 section 12.5.3.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct AcceptEncoding {
+    /// Codings and quality values in thousandths, in the order of the header. `None` is `*`.
+    pub codings: Vec<(Option<ContentCoding>, uint)>,
+}
+
+impl AcceptEncoding {
+    /// Parse `Accept-Encoding` header value.
+    ///
+    /// Elements with invalid quality values are ignored.
+    pub fn parse(value: &str) -> AcceptEncoding {
+        let mut codings = Vec::new();
+        for element in value.split(',') {
+            let mut params = element.split(';');
+            let name = params.next().unwrap_or("").trim();
+            if name.len() == 0 { continue }
+            let mut quality = Some(1000);
+            for param in params {
+                let param = param.trim();
+                if param.len() >= 2 && param.slice_to(2).eq_ignore_ascii_case("q=") {
+                    quality = parse_quality(param.slice_from(2));
+                }
+            }
+            let quality = match quality {
+                Some(quality) => quality,
+                None => continue,
+            };
+            let coding = if name == "*" { None } else { Some(ContentCoding::find(name)) };
+            codings.push((coding, quality));
+        }
+        AcceptEncoding { codings: codings }
+    }
+
+    /// Returns the quality value of the coding in thousandths.
+    ///
+    /// Codings not listed take the value of `*`. `identity` is acceptable unless excluded explicitly.
+    pub fn quality(&self, coding: ContentCoding) -> uint {
+        let mut wildcard = None;
+        for &(c, quality) in self.codings.iter() {
+            match c {
+                Some(c) if c == coding && c != CodingOther => return quality,
+                None => wildcard = Some(quality),
+                _ => (),
+            }
+        }
+        match wildcard {
+            Some(quality) => quality,
+            None if coding == CodingIdentity => 1000,
+            None => 0,
+        }
+    }
+
+    /// Choose the coding with the highest quality value among `available` and `identity`.
+    ///
+    /// Ties are broken by the order of `available`, preferring `identity` last. Returns `None` if
+    /// no coding is acceptable.
+    pub fn negotiate(&self, available: &[ContentCoding]) -> Option<ContentCoding> {
+        let mut best = None;
+        let mut best_quality = 0;
+        for &coding in available.iter().chain([CodingIdentity].iter()) {
+            let quality = self.quality(coding);
+            if quality > best_quality {
+                best = Some(coding);
+                best_quality = quality;
+            }
+        }
+        best
+    }
+}
+
+fn parse_quality(value: &str) -> Option<uint> {
+    if value.len() == 0 || value.len() > 5 { return None }
+    let mut chars = value.chars();
+    let mut quality = match chars.next() {
+        Some('0') => 0u,
+        Some('1') => 1000u,
+        _ => return None,
+    };
+    if value.len() > 1 && chars.next() != Some('.') { return None }
+    let mut scale = 100u;
+    for c in chars {
+        match c.to_digit(10) {
+            Some(digit) => quality += digit * scale,
+            None => return None,
+        }
+        scale /= 10;
+    }
+    if quality > 1000 { None } else { Some(quality) }
+}
+
+/// Incremental decoder of a content coding.
+///
+/// Encoded data is given in arbitrary pieces with `feed`, which decodes as much as possible.
+/// Only incomplete input and the last 32 KiB of output for back-references are kept between calls.
+pub struct Decoder {
+    coding: ContentCoding,
+    state: DecoderState,
+    buffer: Vec<u8>,
+    inflater: Inflater,
+    zlib: bool,
+    check: u32,
+    size: u32,
+    decoded: uint,
+    limit: uint,
+}
+
+enum DecoderState {
+    DecoderHeader,
+    DecoderData,
+    DecoderTrailer,
+    DecoderDone,
+}
+
+impl Decoder {
+    /// Create a new `Decoder` of the content coding without size limit.
+    ///
+    /// `br` and unknown codings fail with `UnsupportedCoding`.
+    pub fn new(coding: ContentCoding) -> EncodingResult<Decoder> {
+        match coding {
+            CodingIdentity | CodingGzip | CodingDeflate => (),
+            CodingBrotli | CodingOther => return Err(UnsupportedCoding),
+        }
+        Ok(Decoder {
+            coding: coding,
+            state: DecoderHeader,
+            buffer: Vec::new(),
+            inflater: Inflater::new(),
+            zlib: false,
+            check: 0,
+            size: 0,
+            decoded: 0,
+            limit: UINT_MAX,
+        })
+    }
+
+    /// Set the limit of total decoded size in bytes.
+    pub fn set_limit(&mut self, limit: uint) {
+        self.limit = limit;
+    }
+
+    #[inline]
+    /// Returns total decoded size in bytes.
+    pub fn decoded(&self) -> uint {
+        self.decoded
+    }
+
+    /// Decode a piece of encoded data, and append decoded data to `out`.
+    ///
+    /// Fails with `SizeLimitExceeded` as soon as total output exceeds the limit. The decoder must
+    /// not be used after an error.
+    pub fn feed(&mut self, data: &[u8], out: &mut Vec<u8>) -> EncodingResult<()> {
+        if self.coding == CodingIdentity {
+            if data.len() > self.limit - self.decoded { return Err(SizeLimitExceeded) }
+            self.decoded += data.len();
+            out.push_all(data);
+            return Ok(());
+        }
+        self.buffer.push_all(data);
+        let read = try!(self.decode_buffer(out));
+        if read == self.buffer.len() {
+            self.buffer.clear();
+        } else if read > 0 {
+            let rest = self.buffer.slice_from(read).to_vec();
+            self.buffer = rest;
+        }
+        Ok(())
+    }
+
+    /// Check that encoded data is complete.
+    ///
+    /// Fails with `InvalidCompressedData` if the data is truncated. Empty data is complete.
+    pub fn finish(&self) -> EncodingResult<()> {
+        match self.state {
+            _ if self.coding == CodingIdentity => Ok(()),
+            DecoderDone => Ok(()),
+            DecoderHeader if self.buffer.is_empty() => Ok(()),
+            _ => Err(InvalidCompressedData),
+        }
+    }
+
+    // Decode buffered data as far as possible, and returns consumed bytes length.
+    fn decode_buffer(&mut self, out: &mut Vec<u8>) -> EncodingResult<uint> {
+        let gzip = self.coding == CodingGzip;
+        let mut pos = 0u;
+        loop {
+            let data = self.buffer.slice_from(pos);
+            match self.state {
+                DecoderHeader => {
+                    let len = if gzip {
+                        try!(gzip_header_len(data))
+                    } else if data.len() < 2 {
+                        None
+                    } else {
+                        self.zlib = is_zlib(data);
+                        Some(if self.zlib { 2 } else { 0 })
+                    };
+                    match len {
+                        Some(len) => pos += len,
+                        None => return Ok(pos),
+                    }
+                    self.inflater = Inflater::new();
+                    self.check = if gzip { 0 } else { 1 };
+                    self.size = 0;
+                    self.state = DecoderData;
+                }
+                DecoderData => {
+                    let start = out.len();
+                    pos += try!(self.inflater.inflate(data, out, self.limit - self.decoded));
+                    let decoded = out.slice_from(start);
+                    self.decoded += decoded.len();
+                    if gzip {
+                        self.check = update_crc32(self.check, decoded);
+                        self.size += decoded.len() as u32;
+                    } else if self.zlib {
+                        self.check = update_adler32(self.check, decoded);
+                    }
+                    if !self.inflater.is_done() { return Ok(pos) }
+                    self.state = if gzip || self.zlib { DecoderTrailer } else { DecoderDone };
+                }
+                DecoderTrailer => {
+                    let len = if gzip { 8 } else { 4 };
+                    if data.len() < len { return Ok(pos) }
+                    let valid = if gzip {
+                        read_u32_le(data) == self.check && read_u32_le(data.slice_from(4)) == self.size
+                    } else {
+                        read_u32_be(data) == self.check
+                    };
+                    if !valid { return Err(ChecksumMismatch) }
+                    pos += len;
+                    self.state = DecoderDone;
+                }
+                DecoderDone => {
+                    // a gzip stream may consist of multiple members, and data after zlib stream is ignored.
+                    if !gzip { return Ok(self.buffer.len()) }
+                    if data.is_empty() { return Ok(pos) }
+                    self.state = DecoderHeader;
+                }
+            }
+        }
+    }
+}
+
+/// Message handler wrapper which decodes message body with `Content-Encoding`.
+///
+/// Body is decoded as it arrives, and decoded data is passed to the wrapped handler through `write`.
+/// `on_body` is passed with the decoded length when the body completes. Header fields are passed
+/// through as is, so the wrapped handler still sees `Content-Encoding` and the original
+/// `Content-Length`.
+///
+/// Decoded size is limited to `max_ratio` times the encoded size to prevent zip bombs. `br` and
+/// unknown codings fail with `UnsupportedCoding`. On errors the rest of the body is not passed, and
+/// `error` returns the cause.
+pub struct DecodingHandler<H> {
+    handler: H,
+    coding: ContentCoding,
+    field: Option<String>,
+    buffer: Vec<u8>,
+    decoder: Option<Decoder>,
+    encoded: uint,
+    headers_complete: bool,
+    max_ratio: uint,
+    error: Option<EncodingError>,
+}
+
+impl<H: MessageHandler> DecodingHandler<H> {
+    /// Create a new `DecodingHandler` with the default ratio limit, 100.
+    pub fn new(handler: H) -> DecodingHandler<H> {
+        DecodingHandler {
+            handler: handler,
+            coding: CodingIdentity,
+            field: None,
+            buffer: Vec::new(),
+            decoder: None,
+            encoded: 0,
+            headers_complete: false,
+            max_ratio: 100,
+            error: None,
+        }
+    }
+
+    /// Set the maximum ratio of decoded size to encoded size.
+    pub fn set_max_ratio(&mut self, ratio: uint) {
+        self.max_ratio = ratio;
+    }
+
+    #[inline]
+    /// Returns the content coding of the current message.
+    pub fn coding(&self) -> ContentCoding {
+        self.coding
+    }
+
+    #[inline]
+    /// Returns the decoding error of the last message, if any.
+    pub fn error(&self) -> Option<EncodingError> {
+        self.error
+    }
+
+    /// Gets a reference to the wrapped handler.
+    pub fn get_ref<'a>(&'a self) -> &'a H {
+        &self.handler
+    }
+
+    /// Gets a mutable reference to the wrapped handler.
+    pub fn get_mut<'a>(&'a mut self) -> &'a mut H {
+        &mut self.handler
+    }
+
+    /// Unwraps this `DecodingHandler`, returning the wrapped handler.
+    pub fn unwrap(self) -> H {
+        self.handler
+    }
+
+    fn decode(&mut self, parser: &Parser, data: &[u8]) {
+        let decoder = match self.decoder {
+            Some(ref mut decoder) => decoder,
+            None => return,
+        };
+        self.encoded += data.len();
+        decoder.set_limit(self.encoded.checked_mul(&self.max_ratio).unwrap_or(UINT_MAX));
+        let mut out = Vec::new();
+        match decoder.feed(data, &mut out) {
+            Ok(()) if out.len() > 0 => self.handler.write(parser, out.as_slice()),
+            Ok(()) => (),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    // Finish decoding, and pass `on_body` with the decoded length.
+    fn finish(&mut self, parser: &Parser) {
+        match self.decoder.take() {
+            Some(ref decoder) if self.error.is_none() => match decoder.finish() {
+                Ok(()) if decoder.decoded() > 0 => self.handler.on_body(parser, decoder.decoded()),
+                Ok(()) => (),
+                Err(e) => self.error = Some(e),
+            },
+            _ => (),
+        }
+    }
+
+    fn take_string(&mut self, length: uint) -> String {
+        let len = self.buffer.len();
+        let start = if len > length { len - length } else { 0 };
+        let s = String::from_utf8_lossy(self.buffer.slice_from(start)).into_string();
+        self.buffer.clear();
+        s
+    }
+}
+
+impl<H: MessageHandler> MessageHandler for DecodingHandler<H> {
+    fn on_message_begin(&mut self, parser: &Parser) {
+        self.coding = CodingIdentity;
+        self.field = None;
+        self.buffer.clear();
+        self.decoder = None;
+        self.encoded = 0;
+        self.headers_complete = false;
+        self.error = None;
+        self.handler.on_message_begin(parser);
+    }
+
+    fn on_method(&mut self, parser: &Parser, method: http::HttpMethod) {
+        self.handler.on_method(parser, method);
+    }
+
+    fn on_url(&mut self, parser: &Parser, length: uint) {
+        self.buffer.clear();
+        self.handler.on_url(parser, length);
+    }
+
+    fn on_version(&mut self, parser: &Parser, version: http::HttpVersion) {
+        self.handler.on_version(parser, version);
+    }
+
+    fn on_status(&mut self, parser: &Parser, status: http::StatusCode) {
+        self.handler.on_status(parser, status);
+    }
+
+    fn on_header_field(&mut self, parser: &Parser, length: uint) {
+        self.field = Some(self.take_string(length));
+        self.handler.on_header_field(parser, length);
+    }
+
+    fn on_header_value(&mut self, parser: &Parser, length: uint) {
+        let value = self.take_string(length);
+        let is_encoding = match self.field.take() {
+            Some(ref field) => field.as_slice().eq_ignore_ascii_case("Content-Encoding"),
+            None => false,
+        };
+        if is_encoding {
+            self.coding = match (self.coding, ContentCoding::from_header(value.as_slice())) {
+                (CodingIdentity, coding) | (coding, CodingIdentity) => coding,
+                _ => CodingOther, // stacked codings
+            };
+        }
+        self.handler.on_header_value(parser, length);
+    }
+
+    fn on_headers_complete(&mut self, parser: &Parser) -> bool {
+        self.headers_complete = true;
+        if self.coding != CodingIdentity {
+            match Decoder::new(self.coding) {
+                Ok(decoder) => self.decoder = Some(decoder),
+                Err(e) => self.error = Some(e),
+            }
+        }
+        self.handler.on_headers_complete(parser)
+    }
+
+    fn on_body(&mut self, parser: &Parser, length: uint) {
+        if self.coding == CodingIdentity {
+            self.handler.on_body(parser, length);
+        } else {
+            self.finish(parser);
+        }
+    }
+
+    fn on_message_complete(&mut self, parser: &Parser) {
+        // chunked or close-delimited body completes with the message.
+        self.finish(parser);
+        self.headers_complete = false;
+        self.handler.on_message_complete(parser);
+    }
+
+    fn on_protocol_method(&mut self, parser: &Parser, method: &'static str) {
+        self.handler.on_protocol_method(parser, method);
+    }
+
+    fn on_protocol_version(&mut self, parser: &Parser, major: uint, minor: uint) {
+        self.handler.on_protocol_version(parser, major, minor);
+    }
+
+    fn on_interleaved_frame(&mut self, parser: &Parser, channel: u8, length: uint) {
+        self.buffer.clear();
+        self.handler.on_interleaved_frame(parser, channel, length);
+    }
+
+    fn write(&mut self, parser: &Parser, data: &[u8]) {
+        if !self.headers_complete {
+            self.buffer.push_all(data);
+            self.handler.write(parser, data);
+        } else if self.coding == CodingIdentity {
+            self.handler.write(parser, data);
+        } else if self.error.is_none() {
+            self.decode(parser, data);
+        }
+    }
+}
+
+/// CRC-32 checksum used by gzip.
+pub fn crc32(data: &[u8]) -> u32 {
+    update_crc32(0, data)
+}
+
+/// Adler-32 checksum used by zlib.
+pub fn adler32(data: &[u8]) -> u32 {
+    update_adler32(1, data)
+}
+
+fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data.iter() {
+        crc ^= byte as u32;
+        for _ in range(0u, 8) {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn update_adler32(adler: u32, data: &[u8]) -> u32 {
+    let (mut a, mut b) = (adler & 0xffff, adler >> 16);
+    for &byte in data.iter() {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn is_zlib(data: &[u8]) -> bool {
+    data.len() >= 2 && data[0] & 0x0f == 8 && data[0] >> 4 <= 7 && data[1] & 0x20 == 0 &&
+        ((data[0] as uint) << 8 | data[1] as uint) % 31 == 0
+}
+
+// Returns gzip member header length, or `None` if the header is incomplete.
+fn gzip_header_len(data: &[u8]) -> EncodingResult<Option<uint>> {
+    if data.len() < 10 { return Ok(None) }
+    if data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 { return Err(InvalidCompressedData) }
+    let flags = data[3];
+    let mut pos = 10u;
+    if flags & FEXTRA != 0 {
+        if data.len() < pos + 2 { return Ok(None) }
+        pos += 2 + (data[pos] as uint | data[pos + 1] as uint << 8);
+    }
+    for &flag in [FNAME, FCOMMENT].iter() {
+        if flags & flag == 0 { continue }
+        if pos > data.len() { return Ok(None) }
+        match data.slice_from(pos).iter().position(|&b| b == 0) {
+            Some(n) => pos += n + 1,
+            None => return Ok(None),
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    if pos > data.len() { Ok(None) } else { Ok(Some(pos)) }
+}
+
+#[inline]
+fn read_u32_le(data: &[u8]) -> u32 {
+    data[0] as u32 | data[1] as u32 << 8 | data[2] as u32 << 16 | data[3] as u32 << 24
+}
+
+#[inline]
+fn push_u32_le(out: &mut Vec<u8>, n: u32) {
+    out.push_all([n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8]);
+}
+
+#[inline]
+fn push_u32_be(out: &mut Vec<u8>, n: u32) {
+    out.push_all([(n >> 24) as u8, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+}
+
+#[inline]
+fn read_u32_be(data: &[u8]) -> u32 {
+    data[0] as u32 << 24 | data[1] as u32 << 16 | data[2] as u32 << 8 | data[3] as u32
+}
+
+/// Resumable inflater of raw deflate data (RFC 1951).
+///
+/// Decoded data is kept in `window` for back-references, and trimmed to the last 32 KiB.
+struct Inflater {
+    bitbuf: u32,
+    bitcnt: uint,
+    block: InflateBlock,
+    last: bool,
+    window: Vec<u8>,
+}
+
+enum InflateBlock {
+    BlockHeader,
+    // remaining length of the stored block
+    BlockStored(uint),
+    BlockCodes(Huffman, Huffman),
+    BlockDone,
+}
+
+enum InflateError {
+    // more input is needed to continue.
+    InflateNeedInput,
+    InflateFailed(EncodingError),
+}
+
+type InflateResult<T> = Result<T, InflateError>;
+
+impl Inflater {
+    fn new() -> Inflater {
+        Inflater {
+            bitbuf: 0,
+            bitcnt: 0,
+            block: BlockHeader,
+            last: false,
+            window: Vec::new(),
+        }
+    }
+
+    #[inline]
+    fn is_done(&self) -> bool {
+        match self.block {
+            BlockDone => true,
+            _ => false,
+        }
+    }
+
+    /// Inflate data as far as possible into `out`, and returns consumed bytes length.
+    ///
+    /// Each step, i.e. a block header, a part of stored block or a symbol, is either completed or
+    /// rolled back to be resumed with more data. Output exceeding `room` bytes fails.
+    fn inflate(&mut self, data: &[u8], out: &mut Vec<u8>, room: uint) -> EncodingResult<uint> {
+        let mut input = Bits { data: data, pos: 0, bitbuf: self.bitbuf, bitcnt: self.bitcnt };
+        let start = self.window.len();
+        let limit = if room > UINT_MAX - start { UINT_MAX } else { start + room };
+        loop {
+            let checkpoint = (input.pos, input.bitbuf, input.bitcnt);
+            let step = match self.block {
+                BlockHeader => block_header(&mut input, &mut self.last),
+                BlockStored(rest) => stored(&mut input, rest, &mut self.window, limit),
+                BlockCodes(ref lencode, ref distcode) => {
+                    codes(&mut input, lencode, distcode, &mut self.window, limit)
+                }
+                BlockDone => break,
+            };
+            match step {
+                Ok(Some(BlockHeader)) if self.last => self.block = BlockDone,
+                Ok(Some(block)) => self.block = block,
+                Ok(None) => (),
+                Err(InflateNeedInput) => {
+                    let (pos, bitbuf, bitcnt) = checkpoint;
+                    input.pos = pos;
+                    input.bitbuf = bitbuf;
+                    input.bitcnt = bitcnt;
+                    break;
+                }
+                Err(InflateFailed(e)) => return Err(e),
+            }
+        }
+        self.bitbuf = input.bitbuf;
+        self.bitcnt = input.bitcnt;
+        out.push_all(self.window.slice_from(start));
+        if self.window.len() > 2 * WINDOW_SIZE {
+            let window = self.window.slice_from(self.window.len() - WINDOW_SIZE).to_vec();
+            self.window = window;
+        }
+        Ok(input.pos)
+    }
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: uint,
+    bitbuf: u32,
+    bitcnt: uint,
+}
+
+impl<'a> Bits<'a> {
+    fn bits(&mut self, need: uint) -> InflateResult<u32> {
+        let mut val = self.bitbuf;
+        while self.bitcnt < need {
+            if self.pos >= self.data.len() { return Err(InflateNeedInput) }
+            val |= self.data[self.pos] as u32 << self.bitcnt;
+            self.pos += 1;
+            self.bitcnt += 8;
+        }
+        self.bitbuf = val >> need;
+        self.bitcnt -= need;
+        Ok(val & ((1 << need) - 1))
+    }
+
+    fn decode(&mut self, h: &Huffman) -> InflateResult<uint> {
+        let (mut code, mut first, mut index) = (0i, 0i, 0i);
+        for len in range(1u, MAX_BITS + 1) {
+            code |= try!(self.bits(1)) as int;
+            let count = h.counts[len] as int;
+            if code - count < first {
+                return Ok(h.symbols.as_slice()[(index + code - first) as uint] as uint);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        invalid()
+    }
+}
+
+#[inline]
+fn invalid<T>() -> InflateResult<T> {
+    Err(InflateFailed(InvalidCompressedData))
+}
+
+fn block_header(input: &mut Bits, last: &mut bool) -> InflateResult<Option<InflateBlock>> {
+    let is_last = try!(input.bits(1)) == 1;
+    let block = match try!(input.bits(2)) {
+        0 => {
+            // discard remaining bits in the current byte.
+            input.bitbuf = 0;
+            input.bitcnt = 0;
+            let data = input.data.slice_from(input.pos);
+            if data.len() < 4 { return Err(InflateNeedInput) }
+            let len = data[0] as uint | data[1] as uint << 8;
+            let nlen = data[2] as uint | data[3] as uint << 8;
+            if len != !nlen & 0xffff { return invalid() }
+            input.pos += 4;
+            BlockStored(len)
+        }
+        1 => {
+            let (lencode, distcode) = fixed_codes();
+            BlockCodes(lencode, distcode)
+        }
+        2 => {
+            let (lencode, distcode) = try!(dynamic_codes(input));
+            BlockCodes(lencode, distcode)
+        }
+        _ => return invalid(),
+    };
+    *last = is_last;
+    Ok(Some(block))
+}
+
+fn stored(input: &mut Bits, rest: uint, window: &mut Vec<u8>, limit: uint) -> InflateResult<Option<InflateBlock>> {
+    if rest == 0 { return Ok(Some(BlockHeader)) }
+    let len = min(rest, input.data.len() - input.pos);
+    if len == 0 { return Err(InflateNeedInput) }
+    if window.len() + len > limit { return Err(InflateFailed(SizeLimitExceeded)) }
+    window.push_all(input.data.slice(input.pos, input.pos + len));
+    input.pos += len;
+    Ok(Some(BlockStored(rest - len)))
+}
+
+// Decode a literal or a back-reference, or the end of block.
+fn codes(input: &mut Bits, lencode: &Huffman, distcode: &Huffman, window: &mut Vec<u8>,
+         limit: uint) -> InflateResult<Option<InflateBlock>> {
+    let symbol = try!(input.decode(lencode));
+    if symbol < 256 {
+        if window.len() >= limit { return Err(InflateFailed(SizeLimitExceeded)) }
+        window.push(symbol as u8);
+    } else if symbol == 256 {
+        return Ok(Some(BlockHeader));
+    } else {
+        let symbol = symbol - 257;
+        if symbol >= 29 { return invalid() }
+        let len = LENGTH_BASE[symbol] as uint + try!(input.bits(LENGTH_EXTRA[symbol] as uint)) as uint;
+        let symbol = try!(input.decode(distcode));
+        if symbol >= 30 { return invalid() }
+        let dist = DIST_BASE[symbol] as uint + try!(input.bits(DIST_EXTRA[symbol] as uint)) as uint;
+        if dist > window.len() { return invalid() }
+        if window.len() + len > limit { return Err(InflateFailed(SizeLimitExceeded)) }
+        for _ in range(0, len) {
+            let byte = window.as_slice()[window.len() - dist];
+            window.push(byte);
+        }
+    }
+    Ok(None)
+}
+
+fn dynamic_codes(input: &mut Bits) -> InflateResult<(Huffman, Huffman)> {
+    let nlen = try!(input.bits(5)) as uint + 257;
+    let ndist = try!(input.bits(5)) as uint + 1;
+    let ncode = try!(input.bits(4)) as uint + 4;
+    if nlen > 286 || ndist > 30 { return invalid() }
+
+    let mut lengths = Vec::from_elem(19, 0u8);
+    for i in range(0, ncode) {
+        lengths.as_mut_slice()[CODE_LENGTH_ORDER[i]] = try!(input.bits(3)) as u8;
+    }
+    let lencode = match Huffman::new(lengths.as_slice()) {
+        Some(h) => h,
+        None => return invalid(),
+    };
+
+    let mut lengths = Vec::with_capacity(nlen + ndist);
+    while lengths.len() < nlen + ndist {
+        let symbol = try!(input.decode(&lencode));
+        let (len, repeat) = match symbol {
+            0..15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&prev) => (prev, 3 + try!(input.bits(2)) as uint),
+                None => return invalid(),
+            },
+            17 => (0, 3 + try!(input.bits(3)) as uint),
+            _ => (0, 11 + try!(input.bits(7)) as uint),
+        };
+        if lengths.len() + repeat > nlen + ndist { return invalid() }
+        lengths.grow(repeat, &len);
+    }
+    // end of block code is required.
+    if lengths.as_slice()[256] == 0 { return invalid() }
+    match (Huffman::new(lengths.slice_to(nlen)), Huffman::new(lengths.slice_from(nlen))) {
+        (Some(lencode), Some(distcode)) => Ok((lencode, distcode)),
+        _ => invalid(),
+    }
+}
+
+/// Canonical Huffman code, decoded bit by bit.
+struct Huffman {
+    counts: [u16, ..16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Option<Huffman> {
+        let mut counts = [0u16, ..16];
+        for &len in lengths.iter() {
+            counts[len as uint] += 1;
+        }
+        counts[0] = 0;
+        // over-subscribed code is invalid, incomplete code is allowed.
+        let mut left = 1i;
+        for len in range(1u, MAX_BITS + 1) {
+            left <<= 1;
+            left -= counts[len] as int;
+            if left < 0 { return None }
+        }
+        let mut offsets = [0u16, ..16];
+        for len in range(1u, MAX_BITS) {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = Vec::from_elem(lengths.len(), 0u16);
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols.as_mut_slice()[offsets[len as uint] as uint] = symbol as u16;
+                offsets[len as uint] += 1;
+            }
+        }
+        Some(Huffman { counts: counts, symbols: symbols })
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = Vec::with_capacity(288);
+    lengths.grow(144, &8u8);
+    lengths.grow(112, &9u8);
+    lengths.grow(24, &7u8);
+    lengths.grow(8, &8u8);
+    let lencode = Huffman::new(lengths.as_slice()).unwrap();
+    let distcode = Huffman::new(Vec::from_elem(30, 5u8).as_slice()).unwrap();
+    (lencode, distcode)
+}
+
+static FHCRC: u8    = 0x02;
+static FEXTRA: u8   = 0x04;
+static FNAME: u8    = 0x08;
+static FCOMMENT: u8 = 0x10;
+
+static MAX_BITS: uint = 15;
+static WINDOW_SIZE: uint = 32768;
+static END_OF_BLOCK: uint = 256;
+static MIN_MATCH: uint = 3;
+static MAX_MATCH: uint = 258;
+static HASH_BITS: uint = 12;
+static HASH_SIZE: uint = 1 << HASH_BITS;
+
+static CODE_LENGTH_ORDER: [uint, ..19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+static LENGTH_BASE: [u16, ..29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+                                   35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+static LENGTH_EXTRA: [u8, ..29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+                                   3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+static DIST_BASE: [u16, ..30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+                                 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+static DIST_EXTRA: [u8, ..30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+                                 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
@@ -0,0 +1,188 @@
+//! Streaming parser for `text/event-stream`, i.e. Server-Sent Events.
+//!
+//! Feed response body bytes received by `MessageHandler::write` to `EventStreamParser::feed`.
+//! Fields are interpreted as the HTML standard's event stream interpretation.
+
+#![experimental]
+
+use std::mem;
+
+/// A dispatched event.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct Event {
+    /// Event type, `message` unless `event` field is given.
+    pub event: String,
+    /// `data` fields joined with LF.
+    pub data: String,
+    /// Last event ID, which is kept across events until another `id` field.
+    pub id: String,
+    /// Reconnection time in milliseconds given in the event.
+    pub retry: Option<u64>,
+}
+
+/// A list specifying categories of event stream errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum EventStreamError {
+    /// A line or data of an event exceeds the limit.
+    EventTooLarge,
+}
+
+/// Result of event stream parsing.
+pub type EventStreamResult<T> = Result<T, EventStreamError>;
+
+/// Streaming event stream parser.
+///
+/// Data can be fed in arbitrary pieces, and lines split across pieces, including CRLF, are joined.
+/// Invalid UTF-8 sequences are replaced with U+FFFD. An incomplete event at the end of stream is
+/// discarded.
+pub struct EventStreamParser {
+    max_event_size: uint,
+    line: Vec<u8>,
+    last_cr: bool,
+    started: bool,
+    event: String,
+    data: String,
+    retry: Option<u64>,
+    id: String,
+    last_event_id: String,
+    reconnection_time: Option<u64>,
+    error: Option<EventStreamError>,
+}
+
+impl EventStreamParser {
+    /// Create a new `EventStreamParser`.
+    pub fn new() -> EventStreamParser {
+        EventStreamParser {
+            max_event_size: ::std::uint::MAX,
+            line: Vec::new(),
+            last_cr: false,
+            started: false,
+            event: String::new(),
+            data: String::new(),
+            retry: None,
+            id: String::new(),
+            last_event_id: String::new(),
+            reconnection_time: None,
+            error: None,
+        }
+    }
+
+    /// Set the maximum size of buffered line and data of an event. Exceeding it results in
+    /// `EventTooLarge`.
+    pub fn set_max_event_size(&mut self, size: uint) {
+        self.max_event_size = size;
+    }
+
+    /// Parse a piece of event stream, and returns events dispatched in it.
+    pub fn feed(&mut self, data: &[u8]) -> EventStreamResult<Vec<Event>> {
+        match self.error {
+            Some(e) => return Err(e),
+            None => (),
+        }
+        let mut events = Vec::new();
+        for &byte in data.iter() {
+            match byte {
+                CR => {
+                    self.process_line(&mut events);
+                    self.last_cr = true;
+                }
+                LF => {
+                    if !self.last_cr {
+                        self.process_line(&mut events);
+                    }
+                    self.last_cr = false;
+                }
+                _ => {
+                    self.line.push(byte);
+                    self.last_cr = false;
+                    if self.line.len() + self.data.len() > self.max_event_size {
+                        self.error = Some(EventTooLarge);
+                        return Err(EventTooLarge);
+                    }
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    #[inline]
+    /// Returns the last event ID to send as `Last-Event-ID` header on reconnection.
+    ///
+    /// An `id` field takes effect when its event is dispatched, not while the event is incomplete.
+    pub fn last_event_id<'a>(&'a self) -> &'a str {
+        self.last_event_id.as_slice()
+    }
+
+    #[inline]
+    /// Returns the reconnection time in milliseconds given by the stream.
+    pub fn reconnection_time(&self) -> Option<u64> {
+        self.reconnection_time
+    }
+
+    fn process_line(&mut self, events: &mut Vec<Event>) {
+        let mut bytes = mem::replace(&mut self.line, Vec::new());
+        if !self.started {
+            self.started = true;
+            if bytes.as_slice().starts_with(BOM) {
+                bytes = bytes.slice_from(BOM.len()).to_vec();
+            }
+        }
+        if bytes.is_empty() {
+            self.dispatch(events);
+            return;
+        }
+        let line = String::from_utf8_lossy(bytes.as_slice()).into_string();
+        let line = line.as_slice();
+        if line.starts_with(":") { return } // comment
+        let (field, value) = match line.find(':') {
+            Some(pos) => {
+                let value = line.slice_from(pos + 1);
+                (line.slice_to(pos), if value.starts_with(" ") { value.slice_from(1) } else { value })
+            }
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push_char('\n');
+            }
+            "id" => {
+                if !value.contains_char('\0') {
+                    self.id = value.to_string();
+                }
+            }
+            "retry" => {
+                if value.len() > 0 && value.chars().all(|c| c >= '0' && c <= '9') {
+                    match from_str::<u64>(value) {
+                        Some(retry) => {
+                            self.retry = Some(retry);
+                            self.reconnection_time = Some(retry);
+                        }
+                        None => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn dispatch(&mut self, events: &mut Vec<Event>) {
+        let event = mem::replace(&mut self.event, String::new());
+        let mut data = mem::replace(&mut self.data, String::new());
+        let retry = self.retry.take();
+        self.last_event_id = self.id.clone();
+        if data.is_empty() { return }
+        data.pop_char(); // trailing LF
+        events.push(Event {
+            event: if event.is_empty() { "message".to_string() } else { event },
+            data: data,
+            id: self.last_event_id.clone(),
+            retry: retry,
+        });
+    }
+}
+
+static LF: u8 = 0x0a;
+static CR: u8 = 0x0d;
+static BOM: &'static [u8] = &[0xef, 0xbb, 0xbf];
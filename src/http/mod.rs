@@ -6,8 +6,11 @@ pub use self::parser::Parser;
 pub use self::parser::ParseType;
 pub use self::parser::ParseError;
 pub use self::parser::MessageHandler;
+pub use self::status::StatusCode;
 
+use std::ascii::StrAsciiExt;
 use std::fmt::{Formatter, FormatError, Show};
+use std::from_str::FromStr;
 
 /// A list of supported HTTP versions.
 #[allow(non_camel_case_types)]
@@ -122,6 +125,81 @@ impl HttpMethod {
     pub fn hit(&self, pos: uint, c: char) -> bool {
         self.name().char_at(pos) == c
     }
+
+    /// Safe methods are read-only, see RFC 7231 section 4.2.1.
+    pub fn is_safe(&self) -> bool {
+        match *self {
+            HttpGet | HttpHead | HttpOptions | HttpPropFind | HttpReport | HttpSearch | HttpTrace => true,
+            _ => false,
+        }
+    }
+
+    /// Idempotent methods can be retried automatically, see RFC 7231 section 4.2.2.
+    pub fn is_idempotent(&self) -> bool {
+        if self.is_safe() { return true }
+        match *self {
+            HttpCheckout | HttpCopy | HttpDelete | HttpLink | HttpMerge | HttpMkActivity
+                | HttpMkCalendar | HttpMkCol | HttpMove | HttpPropPatch | HttpPut
+                | HttpUnlink | HttpUnlock => true,
+            _ => false,
+        }
+    }
+
+    /// Responses to cacheable methods are allowed to be stored, see RFC 7231 section 4.2.3.
+    ///
+    /// Note that POST responses are cacheable only with explicit freshness information.
+    pub fn is_cacheable(&self) -> bool {
+        match *self {
+            HttpGet | HttpHead | HttpPost => true,
+            _ => false,
+        }
+    }
+
+    /// Returns false if a request body has no defined semantics for the method.
+    pub fn allows_request_body(&self) -> bool {
+        match *self {
+            HttpConnect | HttpCopy | HttpDelete | HttpGet | HttpHead | HttpMove | HttpMsearch
+                | HttpPurge | HttpSubscribe | HttpTrace | HttpUnlock | HttpUnsubscribe => false,
+            _ => true,
+        }
+    }
+}
+
+impl FromStr for HttpMethod {
+    fn from_str(s: &str) -> Option<HttpMethod> {
+        Some(match s {
+            "CHECKOUT"    => HttpCheckout,
+            "CONNECT"     => HttpConnect,
+            "COPY"        => HttpCopy,
+            "DELETE"      => HttpDelete,
+            "GET"         => HttpGet,
+            "HEAD"        => HttpHead,
+            "LINK"        => HttpLink,
+            "LOCK"        => HttpLock,
+            "MERGE"       => HttpMerge,
+            "MKACTIVITY"  => HttpMkActivity,
+            "MKCALENDAR"  => HttpMkCalendar,
+            "MKCOL"       => HttpMkCol,
+            "MOVE"        => HttpMove,
+            "M-SEARCH"    => HttpMsearch,
+            "NOTIFY"      => HttpNotify,
+            "OPTIONS"     => HttpOptions,
+            "PATCH"       => HttpPatch,
+            "POST"        => HttpPost,
+            "PROPFIND"    => HttpPropFind,
+            "PROPPATCH"   => HttpPropPatch,
+            "PUT"         => HttpPut,
+            "PURGE"       => HttpPurge,
+            "REPORT"      => HttpReport,
+            "SEARCH"      => HttpSearch,
+            "SUBSCRIBE"   => HttpSubscribe,
+            "TRACE"       => HttpTrace,
+            "UNLINK"      => HttpUnlink,
+            "UNLOCK"      => HttpUnlock,
+            "UNSUBSCRIBE" => HttpUnsubscribe,
+            _ => return None,
+        })
+    }
 }
 
 impl Show for HttpMethod {
@@ -130,5 +208,84 @@ impl Show for HttpMethod {
     }
 }
 
+/// Split an absolute `http` URL into host, port and request target.
+///
+/// Brackets of an IPv6 address are removed, e.g. `::1` for `http://[::1]:8080/`.
+pub fn split_url(url: &str) -> Option<(String, u16, String)> {
+    if url.len() < 7 || !url.slice_to(7).eq_ignore_ascii_case("http://") { return None }
+    let rest = url.slice_from(7);
+    let (authority, target) = match rest.find(|c: char| c == '/' || c == '?' || c == '#') {
+        Some(pos) => (rest.slice_to(pos), rest.slice_from(pos)),
+        None => (rest, ""),
+    };
+    let target = match target.find('#') {
+        Some(pos) => target.slice_to(pos),
+        None => target,
+    };
+    let target = if target.starts_with("/") {
+        target.to_string()
+    } else {
+        format!("/{}", target)
+    };
+    let authority = match authority.rfind('@') {
+        Some(pos) => authority.slice_from(pos + 1),
+        None => authority,
+    };
+    let (host, port) = match authority.rfind(':') {
+        Some(pos) if !authority.ends_with("]") => match from_str::<u16>(authority.slice_from(pos + 1)) {
+            Some(port) => (authority.slice_to(pos), port),
+            None => return None,
+        },
+        _ => (authority, 80),
+    };
+    let host = if host.starts_with("[") || host.ends_with("]") {
+        if host.len() < 2 || !host.starts_with("[") || !host.ends_with("]") { return None }
+        host.slice(1, host.len() - 1)
+    } else {
+        host
+    };
+    if host.len() == 0 { return None }
+    Some((host.to_string(), port, target))
+}
+
+/// Format host and port as an authority, e.g. for `Host` header. The default port 80 is omitted,
+/// and an IPv6 address is enclosed in brackets.
+pub fn authority(host: &str, port: u16) -> String {
+    let host = if host.contains_char(':') { format!("[{}]", host) } else { host.to_string() };
+    if port == 80 { host } else { format!("{}:{}", host, port) }
+}
+
+#[inline]
+fn is_field_name_char(byte: u8) -> bool {
+    byte >= 0x21 && byte <= 0x7e && byte != 0x3a // visible characters except ':'
+}
+
+#[inline]
+fn is_field_value_char(byte: u8) -> bool {
+    byte != 0x0a && byte != 0x0d // CR and LF terminate header value
+}
+
+#[inline]
+fn is_token(s: &str) -> bool {
+    s.len() > 0 && s.as_bytes().iter().all(|&b| {
+        is_field_name_char(b) && !"\"(),/;<=>?@[\\]{}".as_bytes().contains(&b)
+    })
+}
+
+pub mod cache;
+pub mod chunked;
+pub mod cookie;
+pub mod date;
+pub mod encoding;
+pub mod eventsource;
+pub mod multipart;
 pub mod parser;
+pub mod proxy;
+pub mod proxy_protocol;
+pub mod range;
+pub mod reader;
+pub mod rewriter;
+pub mod serializer;
+pub mod status;
+pub mod urlencoded;
 #[cfg(test)] pub mod tests;
@@ -0,0 +1,307 @@
+//! Streaming `multipart/form-data` parser.
+//!
+//! Feed message body bytes received by `MessageHandler::write` to `MultipartParser::parse`.
+//! Part data is passed to the handler as it arrives, so whole files are never buffered.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+
+/// Multipart parser event handler.
+pub trait MultipartHandler {
+    #[allow(unused_variable)]
+    /// Called when start to parsing of a part.
+    fn on_part_begin(&mut self, parser: &MultipartParser) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when part header field's name parsed.
+    fn on_header_field(&mut self, parser: &MultipartParser, length: uint) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when part header field's value parsed.
+    fn on_header_value(&mut self, parser: &MultipartParser, length: uint) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when completed to parsing of part headers.
+    fn on_headers_complete(&mut self, parser: &MultipartParser) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when a piece of part data parsed.
+    fn on_part_data(&mut self, parser: &MultipartParser, data: &[u8]) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when completed to parsing of a part.
+    fn on_part_end(&mut self, parser: &MultipartParser) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when found the close delimiter.
+    fn on_body_end(&mut self, parser: &MultipartParser) {
+    }
+
+    /// Write partial data to buffer, i.e. part header field and value.
+    fn write(&mut self, &MultipartParser, &[u8]);
+}
+
+/// A list specifying categories of multipart parse errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum MultipartError {
+    /// Any parse error not part of this list.
+    OtherMultipartError,
+    /// Invalid characters after boundary delimiter.
+    InvalidBoundary,
+    /// Invalid part header field.
+    InvalidPartHeader,
+}
+
+/// Result of multipart parsing.
+pub type MultipartResult = Result<uint, MultipartError>;
+
+/// Streaming `multipart/form-data` parser.
+pub struct MultipartParser {
+    state: MultipartState,
+    delimiter: Vec<u8>,
+    index: uint,
+}
+
+impl MultipartParser {
+    /// Create a new `MultipartParser` with the boundary, e.g. extracted by `boundary`.
+    pub fn new(boundary: &str) -> MultipartParser {
+        let mut delimiter = Vec::with_capacity(boundary.len() + 4);
+        delimiter.push_all([CR, LF, HYPHEN, HYPHEN]);
+        delimiter.push_all(boundary.as_bytes());
+        MultipartParser {
+            state: MpPreamble,
+            delimiter: delimiter,
+            // first boundary may be placed at the head of body without CRLF.
+            index: 2,
+        }
+    }
+
+    /// Parse a piece of multipart body, and returns parsed bytes length.
+    ///
+    /// Preamble and epilogue are ignored.
+    pub fn parse<C: MultipartHandler>(&mut self, data: &[u8], handler: &mut C) -> MultipartResult {
+        if self.state == MpCrashed { return Err(OtherMultipartError) }
+
+        let mut mark = match self.state {
+            MpHeaderField | MpHeaderValue => Some(0u),
+            _ => None,
+        };
+
+        for (i, &byte) in data.iter().enumerate() {
+            match self.state {
+                MpPreamble | MpPartData => {
+                    if byte == self.delimiter.as_slice()[self.index] {
+                        if self.index == 0 {
+                            match mark.take() {
+                                Some(m) => self.emit_data(data.slice(m, i), handler),
+                                None => (),
+                            }
+                        }
+                        self.index += 1;
+                        if self.index == self.delimiter.len() {
+                            if self.state == MpPartData {
+                                handler.on_part_end(self);
+                            }
+                            self.state = MpBoundaryEnd;
+                            self.index = 0;
+                        }
+                    } else {
+                        if self.index > 0 {
+                            // bytes held as a part of delimiter are data actually.
+                            self.emit_data(self.delimiter.slice_to(self.index), handler);
+                            self.index = 0;
+                            if byte == CR {
+                                self.index = 1;
+                                continue;
+                            }
+                        }
+                        if mark.is_none() { mark = Some(i) }
+                    }
+                }
+                MpBoundaryEnd => {
+                    match byte {
+                        HYPHEN => self.state = MpCloseHyphen,
+                        CR => self.state = MpBoundaryAlmostDone,
+                        LF => self.begin_part(handler),
+                        SPACE | TAB => (), // transport padding
+                        _ => { self.state = MpCrashed; return Err(InvalidBoundary) },
+                    }
+                }
+                MpBoundaryAlmostDone => {
+                    if byte != LF { self.state = MpCrashed; return Err(InvalidBoundary) }
+                    self.begin_part(handler);
+                }
+                MpCloseHyphen => {
+                    if byte != HYPHEN { self.state = MpCrashed; return Err(InvalidBoundary) }
+                    handler.on_body_end(self);
+                    self.state = MpEpilogue;
+                }
+                MpHeaderFieldStart => {
+                    match byte {
+                        CR => self.state = MpHeadersAlmostDone,
+                        LF => self.headers_complete(handler),
+                        0x21..0x7e if byte != COLON => {
+                            self.state = MpHeaderField;
+                            self.index = 1;
+                            mark = Some(i);
+                        }
+                        _ => { self.state = MpCrashed; return Err(InvalidPartHeader) },
+                    }
+                }
+                MpHeaderField => {
+                    match byte {
+                        COLON => {
+                            match mark.take() {
+                                Some(m) => handler.write(self, data.slice(m, i)),
+                                None => (),
+                            }
+                            handler.on_header_field(self, self.index);
+                            self.state = MpHeaderValueDiscardWS;
+                            self.index = 0;
+                        }
+                        0x21..0x7e => self.index += 1,
+                        _ => { self.state = MpCrashed; return Err(InvalidPartHeader) },
+                    }
+                }
+                MpHeaderValueDiscardWS => {
+                    match byte {
+                        SPACE | TAB => (), // skip
+                        CR => {
+                            handler.on_header_value(self, 0);
+                            self.state = MpHeaderValueAlmostDone;
+                        }
+                        LF => {
+                            handler.on_header_value(self, 0);
+                            self.state = MpHeaderFieldStart;
+                        }
+                        _ => {
+                            self.state = MpHeaderValue;
+                            self.index = 1;
+                            mark = Some(i);
+                        }
+                    }
+                }
+                MpHeaderValue => {
+                    match byte {
+                        CR | LF => {
+                            match mark.take() {
+                                Some(m) => handler.write(self, data.slice(m, i)),
+                                None => (),
+                            }
+                            handler.on_header_value(self, self.index);
+                            self.index = 0;
+                            self.state = if byte == CR {
+                                MpHeaderValueAlmostDone
+                            } else {
+                                MpHeaderFieldStart
+                            };
+                        }
+                        _ => self.index += 1,
+                    }
+                }
+                MpHeaderValueAlmostDone => {
+                    if byte != LF { self.state = MpCrashed; return Err(InvalidPartHeader) }
+                    self.state = MpHeaderFieldStart;
+                }
+                MpHeadersAlmostDone => {
+                    if byte != LF { self.state = MpCrashed; return Err(InvalidPartHeader) }
+                    self.headers_complete(handler);
+                }
+                MpEpilogue => break,
+                MpCrashed => unreachable!(),
+            }
+        }
+
+        match (self.state, mark) {
+            (MpPartData, Some(m)) => handler.on_part_data(self, data.slice_from(m)),
+            (MpHeaderField, Some(m)) | (MpHeaderValue, Some(m)) => handler.write(self, data.slice_from(m)),
+            _ => (),
+        }
+
+        Ok(data.len())
+    }
+
+    #[inline]
+    /// Returns true if found the close delimiter.
+    pub fn is_finished(&self) -> bool {
+        self.state == MpEpilogue
+    }
+
+    #[inline]
+    fn begin_part<C: MultipartHandler>(&mut self, handler: &mut C) {
+        handler.on_part_begin(self);
+        self.state = MpHeaderFieldStart;
+        self.index = 0;
+    }
+
+    #[inline]
+    fn headers_complete<C: MultipartHandler>(&mut self, handler: &mut C) {
+        handler.on_headers_complete(self);
+        self.state = MpPartData;
+        self.index = 0;
+    }
+
+    #[inline]
+    fn emit_data<C: MultipartHandler>(&self, data: &[u8], handler: &mut C) {
+        if self.state == MpPartData && data.len() > 0 {
+            handler.on_part_data(self, data);
+        }
+    }
+}
+
+/// Extract boundary parameter from `Content-Type` header value of multipart message.
+pub fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';');
+    match params.next() {
+        Some(media) if media.trim().to_ascii_lower().as_slice().starts_with("multipart/") => (),
+        _ => return None,
+    }
+    for param in params {
+        let param = param.trim();
+        match param.find('=') {
+            Some(pos) if param.slice_to(pos).trim().eq_ignore_ascii_case("boundary") => {
+                let value = param.slice_from(pos + 1).trim();
+                let value = if value.len() >= 2 && value.starts_with("\"") && value.ends_with("\"") {
+                    value.slice(1, value.len() - 1)
+                } else {
+                    value
+                };
+                if value.len() == 0 || value.len() > 70 { return None }
+                return Some(value.to_string());
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+static TAB: u8    = 0x09;
+static LF: u8     = 0x0a;
+static CR: u8     = 0x0d;
+static SPACE: u8  = 0x20;
+static HYPHEN: u8 = 0x2d;
+static COLON: u8  = 0x3a;
+
+#[deriving(PartialEq, Eq, Clone, Show)]
+enum MultipartState {
+    MpPreamble,
+    MpBoundaryEnd,
+    MpBoundaryAlmostDone,
+    MpCloseHyphen,
+    MpHeaderFieldStart,
+    MpHeaderField,
+    MpHeaderValueDiscardWS,
+    MpHeaderValue,
+    MpHeaderValueAlmostDone,
+    MpHeadersAlmostDone,
+    MpPartData,
+    MpEpilogue,
+    MpCrashed,
+}
@@ -5,6 +5,7 @@
 use UINT_MAX = std::uint::MAX;
 
 use http;
+use http::status;
 
 #[deriving(PartialEq, Eq, Clone, Show)]
 /// A parser types.
@@ -40,8 +41,8 @@ pub trait MessageHandler {
     }
 
     #[allow(unused_variable)]
-    /// Called when request method parsed.
-    fn on_status(&mut self, parser: &Parser, status: uint) {
+    /// Called when status code parsed.
+    fn on_status(&mut self, parser: &Parser, status: http::StatusCode) {
     }
 
     #[allow(unused_variable)]
@@ -73,6 +74,22 @@ pub trait MessageHandler {
     fn on_message_complete(&mut self, parser: &Parser) {
     }
 
+    #[allow(unused_variable)]
+    /// Called when request method of other protocol parsed, see `Parser::with_protocol`.
+    fn on_protocol_method(&mut self, parser: &Parser, method: &'static str) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when version of other protocol parsed.
+    fn on_protocol_version(&mut self, parser: &Parser, major: uint, minor: uint) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when interleaved binary frame, i.e. `$`, channel and length, parsed. Frame data is
+    /// written before.
+    fn on_interleaved_frame(&mut self, parser: &Parser, channel: u8, length: uint) {
+    }
+
     /// Write partial data to buffer, e.g. URL, header field, message body.
     fn write(&mut self, &Parser, &[u8]);
 }
@@ -100,19 +117,83 @@ pub enum ParseError {
     InvalidHeaders,
     /// Invalid chunk data.
     InvalidChunk,
+    /// Input ended in the middle of a message.
+    UnexpectedEof,
+    /// Start line and header section exceed the limit.
+    HeaderTooLarge,
+    /// Message body exceeds the limit.
+    BodyTooLarge,
 }
 
 pub type ParseResult = Result<uint, ParseError>;
 
+/// A list of ways a request starts HTTP/2, see RFC 7540 section 3.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum Http2Start {
+    /// HTTP/2 connection preface, i.e. prior knowledge. HTTP/2 frames follow the preface.
+    Http2Preface,
+    /// `Upgrade: h2c` request with `HTTP2-Settings` header.
+    Http2Upgrade,
+}
+
+/// A protocol which shares the message syntax of HTTP/1, e.g. RTSP, ICAP and SIP.
+///
+/// Define a static `Protocol` to parse other protocols with `Parser::with_protocol`.
+pub struct Protocol {
+    /// Protocol name in start line, e.g. `RTSP` of `RTSP/1.0`.
+    pub name: &'static str,
+    /// Supported versions as pairs of major and minor version.
+    pub versions: &'static [(uint, uint)],
+    /// Request methods. Methods are case-sensitive.
+    pub methods: &'static [&'static str],
+    /// Whether `$` interleaved binary frames may appear between messages, like RTSP.
+    pub interleaved: bool,
+}
+
+/// RTSP/1.0, see RFC 2326.
+pub static RTSP: Protocol = Protocol {
+    name: "RTSP",
+    versions: &[(1, 0)],
+    methods: &["ANNOUNCE", "DESCRIBE", "GET_PARAMETER", "OPTIONS", "PAUSE", "PLAY", "RECORD",
+               "REDIRECT", "SETUP", "SET_PARAMETER", "TEARDOWN"],
+    interleaved: true,
+};
+
+/// ICAP/1.0, see RFC 3507.
+pub static ICAP: Protocol = Protocol {
+    name: "ICAP",
+    versions: &[(1, 0)],
+    methods: &["OPTIONS", "REQMOD", "RESPMOD"],
+    interleaved: false,
+};
+
+/// SIP/2.0, see RFC 3261.
+pub static SIP: Protocol = Protocol {
+    name: "SIP",
+    versions: &[(2, 0)],
+    methods: &["ACK", "BYE", "CANCEL", "INFO", "INVITE", "MESSAGE", "NOTIFY", "OPTIONS", "PRACK",
+               "PUBLISH", "REFER", "REGISTER", "SUBSCRIBE", "UPDATE"],
+    interleaved: false,
+};
+
 /// HTTP request/response parser.
 pub struct Parser {
     // parser internal state
     parser_type: ParseType,
+    protocol: Option<&'static Protocol>,
     state: ParserState,
     hstate: HeaderState,
     cstate: ChunkState,
     index: uint,
+    token_index: uint,
     skip_body: bool,
+    completed: bool,
+
+    // limits
+    max_header_size: uint,
+    max_body_size: uint,
+    header_size: uint,
+    body_size: uint,
 
     // http version
     http_version: Option<http::HttpVersion>,
@@ -127,9 +208,17 @@ pub struct Parser {
 
     // request
     method: Option<http::HttpMethod>,
+    h2c: bool,
+    http2_settings: bool,
+    http2: Option<Http2Start>,
 
     // response
-    status_code: uint,
+    status_code: http::StatusCode,
+
+    // other protocols
+    protocol_method: Option<uint>,
+    channel: u8,
+    frame_size: uint,
 }
 
 impl Parser {
@@ -137,6 +226,7 @@ impl Parser {
     pub fn new(t: ParseType) -> Parser {
         Parser {
             parser_type: t,
+            protocol: None,
             http_version: None,
             state: match t {
                 ParseRequest  => StartReq,
@@ -145,10 +235,22 @@ impl Parser {
             hstate: HeaderGeneral,
             cstate: ChunkSize,
             method: None,
-            status_code: 0,
+            h2c: false,
+            http2_settings: false,
+            http2: None,
+            status_code: http::StatusCode(0),
+            protocol_method: None,
+            channel: 0,
+            frame_size: 0,
             message_body_rest: UINT_MAX,
             skip_body: false,
+            completed: false,
+            max_header_size: UINT_MAX,
+            max_body_size: UINT_MAX,
+            header_size: 0,
+            body_size: 0,
             index: 0,
+            token_index: 0,
             major: 0,
             minor: 0,
             keep_alive: false,
@@ -157,14 +259,30 @@ impl Parser {
         }
     }
 
+    /// Create a new `Parser` of other protocol, e.g. `RTSP`.
+    ///
+    /// Request methods and versions are reported by `on_protocol_method` and `on_protocol_version`
+    /// instead of `on_method` and `on_version`. Connections are persistent by default, and a
+    /// message without `Content-Length` or `Transfer-Encoding` has no body.
+    pub fn with_protocol(t: ParseType, protocol: &'static Protocol) -> Parser {
+        let mut parser = Parser::new(t);
+        parser.protocol = Some(protocol);
+        parser
+    }
+
     #[allow(unused_must_use)]
     #[unstable]
     /// Parse HTTP message, and returns parsed bytes length.
     ///
     /// - If find `Connection: upgrade`, skip parsing message body.
     /// - If find `Transfer-Encoding: chunked`, decode message body.
+    /// - If find HTTP/2 connection preface, complete it as a message, and never parse following
+    ///   bytes. See `http2_start`.
+    /// - If the protocol has interleaved frames, report `$` frames between messages.
     pub fn parse<C: MessageHandler>(&mut self, data: &[u8], handler: &mut C) -> ParseResult {
         if self.state == Crashed { return Err(OtherParseError) }
+        if self.state == Http2 { return Ok(0) }
+        self.completed = false;
         if data.len() == 0 { return Ok(0) }
 
         let mut read = 0u;
@@ -172,38 +290,74 @@ impl Parser {
         if !self.state.is_body() {
             for &byte in data.iter() {
                 read += 1;
+                self.header_size += 1;
+                if self.header_size > self.max_header_size {
+                    self.state = Crashed;
+                    return Err(HeaderTooLarge);
+                }
                 match self.state {
                     StartReq => {
-                        self.method = Some(match byte {
-                            UPPER_C => http::HttpConnect,     // or CHECKOUT, COPY
-                            UPPER_D => http::HttpDelete,
-                            UPPER_G => http::HttpGet,
-                            UPPER_H => http::HttpHead,
-                            UPPER_L => http::HttpLink,        // or LOCK
-                            UPPER_M => http::HttpMkCol,       // or M-SEARCH, MERGE, MKACTIVITY, MKCALENDER
-                            UPPER_N => http::HttpNotify,
-                            UPPER_O => http::HttpOptions,
-                            UPPER_P => http::HttpPut,         // or PATCH, POST, PROPPATCH, PROPFIND
-                            UPPER_R => http::HttpReport,
-                            UPPER_S => http::HttpSearch,      // or SUBSCRIBE
-                            UPPER_T => http::HttpTrace,
-                            UPPER_U => http::HttpUnlink,      // or UNLOCK, UNSUBSCRIBE
-                            CR | LF => break,
-                            _   => { self.state = Crashed; return Err(InvalidMethod) },
-                        });
+                        match self.protocol {
+                            Some(protocol) => {
+                                match byte {
+                                    DOLLAR if protocol.interleaved => {
+                                        self.state = InterleavedChannel;
+                                        continue;
+                                    }
+                                    CR | LF => break,
+                                    _ => (),
+                                }
+                                self.protocol_method = match find_method(protocol.methods, 0, 0, byte) {
+                                    None => { self.state = Crashed; return Err(InvalidMethod) },
+                                    m => m,
+                                };
+                                self.method = None;
+                                self.state = ReqProtocolMethod;
+                            }
+                            None => {
+                                self.method = Some(match byte {
+                                    UPPER_C => http::HttpConnect,     // or CHECKOUT, COPY
+                                    UPPER_D => http::HttpDelete,
+                                    UPPER_G => http::HttpGet,
+                                    UPPER_H => http::HttpHead,
+                                    UPPER_L => http::HttpLink,        // or LOCK
+                                    UPPER_M => http::HttpMkCol,       // or M-SEARCH, MERGE, MKACTIVITY, MKCALENDER
+                                    UPPER_N => http::HttpNotify,
+                                    UPPER_O => http::HttpOptions,
+                                    UPPER_P => http::HttpPut,         // or PATCH, POST, PROPPATCH, PROPFIND
+                                    UPPER_R => http::HttpReport,
+                                    UPPER_S => http::HttpSearch,      // or SUBSCRIBE
+                                    UPPER_T => http::HttpTrace,
+                                    UPPER_U => http::HttpUnlink,      // or UNLOCK, UNSUBSCRIBE
+                                    CR | LF => break,
+                                    _   => { self.state = Crashed; return Err(InvalidMethod) },
+                                });
+                                self.state = ReqMethod;
+                            }
+                        }
+                        self.chunked = false;
+                        self.upgrade = false;
+                        self.h2c = false;
+                        self.http2_settings = false;
+                        self.http2 = None;
                         handler.on_message_begin(self);
-                        self.state = ReqMethod;
                         self.index = 1;
                     }
                     StartRes => {
                         match byte {
-                            UPPER_H => {
+                            DOLLAR if self.protocol.map_or(false, |p| p.interleaved) => {
+                                self.state = InterleavedChannel;
+                                continue;
+                            }
+                            _ if byte == self.protocol_name().as_bytes()[0] => {
                                 self.state = HttpStart;
                                 self.index = 1;
                             },
                             CR | LF => break,
                             _ => { self.state = Crashed; return Err(InvalidMethod) },
                         }
+                        self.chunked = false;
+                        self.upgrade = false;
                         handler.on_message_begin(self);
                     }
                     ReqMethod => {
@@ -212,6 +366,11 @@ impl Parser {
                             handler.on_method(self, method);
                             self.state = ReqUrl;
                             self.index = 0;
+                        } else if method == http::HttpPropPatch && self.index == 2 && byte == UPPER_I {
+                            // "PRI" starts HTTP/2 connection preface.
+                            self.method = None;
+                            self.state = ReqHttp2Preface;
+                            self.index = 3;
                         } else {
                             if !method.hit(self.index, byte as char) {
                                 self.method = Some(match (method, self.index, byte) {
@@ -236,6 +395,55 @@ impl Parser {
                             self.index += 1;
                         }
                     }
+                    ReqProtocolMethod => {
+                        let methods = self.protocol.unwrap().methods;
+                        let current = self.protocol_method.unwrap();
+                        if byte == SPACE {
+                            // the current method may be longer, e.g. FOOBAR listed before FOO.
+                            let method = match find_exact_method(methods, current, self.index) {
+                                Some(m) => methods[m],
+                                None => { self.state = Crashed; return Err(InvalidMethod) },
+                            };
+                            handler.on_protocol_method(self, method);
+                            self.state = ReqUrl;
+                            self.index = 0;
+                        } else {
+                            self.protocol_method = match find_method(methods, current, self.index, byte) {
+                                None => { self.state = Crashed; return Err(InvalidMethod) },
+                                m => m,
+                            };
+                            self.index += 1;
+                        }
+                    }
+                    InterleavedChannel => {
+                        self.channel = byte;
+                        self.frame_size = 0;
+                        self.state = InterleavedLength;
+                        self.index = 0;
+                    }
+                    InterleavedLength => {
+                        self.frame_size = self.frame_size << 8 | byte as uint;
+                        self.index += 1;
+                        if self.index == 2 {
+                            if self.frame_size > self.max_body_size { self.state = Crashed; return Err(BodyTooLarge) }
+                            self.message_body_rest = self.frame_size;
+                            self.state = InterleavedData;
+                            break;
+                        }
+                    }
+                    ReqHttp2Preface => {
+                        if byte != HTTP2_PREFACE.as_bytes()[self.index] {
+                            self.state = Crashed;
+                            return Err(InvalidRequestLine);
+                        }
+                        self.index += 1;
+                        if self.index == HTTP2_PREFACE.len() {
+                            self.http2 = Some(Http2Preface);
+                            self.complete(handler);
+                            self.state = Http2;
+                            break;
+                        }
+                    }
                     ReqUrl => {
                         match byte {
                             SPACE => {
@@ -249,13 +457,14 @@ impl Parser {
                             }
                             CR | LF => {
                                 if self.index == 0 { self.state = Crashed; return Err(InvalidUrl) }
+                                // HTTP/0.9 request line has no version.
+                                if self.protocol.is_some() { self.state = Crashed; return Err(InvalidRequestLine) }
                                 self.http_version = Some(http::HTTP_0_9);
                                 let start = if read > self.index + 1 { read - self.index - 1 } else { 0 };
                                 let end = read - 1;
                                 handler.write(self, data.slice(start, end));
                                 handler.on_url(self, self.index);
-                                handler.on_message_complete(self);
-                                self.reset();
+                                self.complete(handler);
                                 break;
                             }
                             _ => {
@@ -264,12 +473,11 @@ impl Parser {
                         }
                     }
                     HttpStart => {
+                        let name = self.protocol_name().as_bytes();
                         match (byte, self.index) {
-                            (UPPER_H, 0)    => self.index += 1,
-                            (UPPER_T, 1..2) => self.index += 1,
-                            (UPPER_P, 3)    => self.index += 1,
-                            (SLASH, 4)      => self.index += 1,
-                            (ZERO..NINE, 5) => {
+                            (_, i) if i < name.len() && byte == name[i] => self.index += 1,
+                            (SLASH, i) if i == name.len() => self.index += 1,
+                            (ZERO..NINE, i) if i == name.len() + 1 => {
                                 self.state = HttpMajor;
                                 self.major = (byte - ZERO) as uint;
                                 self.index = 1;
@@ -302,21 +510,31 @@ impl Parser {
                                 self.minor += (byte - ZERO) as uint;
                             }
                             (CR, 1..2, ParseRequest) | (LF, 1..2, ParseRequest) | (SPACE, 1..2, ParseResponse) => {
-                                match http::HttpVersion::find(self.major, self.minor) {
-                                    None => { self.state = Crashed; return Err(InvalidVersion) }
-                                    v => {
-                                        handler.on_version(self, v.unwrap());
-                                        self.http_version = v;
-                                        self.keep_alive = v == Some(http::HTTP_1_1);
-                                        self.state = match (byte, self.parser_type) {
-                                            (CR, ParseRequest) => ReqLineAlmostDone,
-                                            (LF, ParseRequest) => HeaderFieldStart,
-                                            (SPACE, ParseResponse) => ResStatusCode,
-                                            _ => { self.state = Crashed; return Err(InvalidVersion) }
-                                        };
-                                        self.index = 0;
+                                match self.protocol {
+                                    Some(protocol) => {
+                                        if !protocol.versions.contains(&(self.major, self.minor)) {
+                                            self.state = Crashed;
+                                            return Err(InvalidVersion);
+                                        }
+                                        handler.on_protocol_version(self, self.major, self.minor);
+                                        self.keep_alive = true;
                                     }
+                                    None => match http::HttpVersion::find(self.major, self.minor) {
+                                        None => { self.state = Crashed; return Err(InvalidVersion) }
+                                        v => {
+                                            handler.on_version(self, v.unwrap());
+                                            self.http_version = v;
+                                            self.keep_alive = v == Some(http::HTTP_1_1);
+                                        }
+                                    },
                                 }
+                                self.state = match (byte, self.parser_type) {
+                                    (CR, ParseRequest) => ReqLineAlmostDone,
+                                    (LF, ParseRequest) => HeaderFieldStart,
+                                    (SPACE, ParseResponse) => ResStatusCode,
+                                    _ => { self.state = Crashed; return Err(InvalidVersion) }
+                                };
+                                self.index = 0;
                             }
                             _ => { self.state = Crashed; return Err(InvalidVersion) },
                         }
@@ -327,8 +545,8 @@ impl Parser {
                     }
                     ResStatusCode => {
                         if byte >= ZERO && byte <= NINE && self.index < 3 {
-                            self.status_code *= 10;
-                            self.status_code += (byte - ZERO) as uint;
+                            let code = self.status_code.code();
+                            self.status_code = http::StatusCode(code * 10 + (byte - ZERO) as uint);
                             self.index += 1;
                         } else {
                             handler.on_status(self, self.status_code);
@@ -359,30 +577,14 @@ impl Parser {
                         match byte {
                             CR => self.state = HeadersAlmostDone,
                             LF => {
-                                if handler.on_headers_complete(self) || self.skip_body {
-                                    handler.on_message_complete(self);
-                                    self.reset();
-                                } else {
-                                    match self.message_body_rest {
-                                        0u => {
-                                            handler.on_message_complete(self);
-                                            self.reset();
-                                        }
-                                        UINT_MAX => if self.parser_type == ParseRequest || !self.needs_eof() {
-                                            handler.on_message_complete(self);
-                                            self.reset();
-                                        } else {
-                                            self.state = BodyIdentityEOF;
-                                        },
-                                        _ => self.state = BodyIdentity,
-                                    }
-                                };
+                                try!(self.headers_complete(handler));
                                 break
                             }
-                            0x21..0x7e => {
+                            _ if http::is_field_name_char(byte) => {
                                 self.state = HeaderField;
                                 self.hstate = match byte {
                                     UPPER_C | LOWER_C => HeaderConnection,
+                                    UPPER_H | LOWER_H => HeaderHttp2Settings,
                                     UPPER_T | LOWER_T => HeaderTransferEncoding,
                                     UPPER_U | LOWER_U => HeaderUpgrade,
                                     _                 => HeaderGeneral,
@@ -395,6 +597,12 @@ impl Parser {
                     HeaderField => {
                         match byte {
                             COLON => {
+                                match (self.hstate, self.index) {
+                                    (HeaderUpgrade, 7) => (),
+                                    (HeaderHttp2Settings, 14) => self.http2_settings = true,
+                                    (HeaderUpgrade, _) | (HeaderHttp2Settings, _) => self.hstate = HeaderGeneral,
+                                    _ => (),
+                                }
                                 let start = if read > self.index + 1 { read - self.index - 1} else { 0 };
                                 let end = read - 1;
                                 handler.write(self, data.slice(start, end));
@@ -410,7 +618,7 @@ impl Parser {
                                 self.state = HeaderFieldStart;
                                 self.index = 0;
                             }
-                            0x21..0x7e => {
+                            _ if http::is_field_name_char(byte) => {
                                 if self.hstate != HeaderGeneral {
                                     self.hstate = match self.hstate {
                                         HeaderConnection => match (byte, self.index) {
@@ -457,6 +665,10 @@ impl Parser {
                                                 | (UPPER_G, 16) | (LOWER_G, 16) => HeaderTransferEncoding,
                                             _ => HeaderGeneral,
                                         },
+                                        HeaderUpgrade if token_char(byte, "upgrade", self.index) => HeaderUpgrade,
+                                        HeaderHttp2Settings if token_char(byte, "http2-settings", self.index) => {
+                                            HeaderHttp2Settings
+                                        }
                                         _ => HeaderGeneral,
                                     };
                                 }
@@ -472,12 +684,10 @@ impl Parser {
                             LF => self.state = HeaderValueDiscardLWS,
                             _ => {
                                 self.hstate = match (self.hstate, byte) {
-                                    (HeaderConnection, UPPER_C)
-                                        | (HeaderConnection, LOWER_C) => HeaderMatchingClose,
-                                    (HeaderConnection, UPPER_K)
-                                        | (HeaderConnection, LOWER_K) => HeaderMatchingKeepAlive,
-                                    (HeaderConnection, UPPER_U)
-                                        | (HeaderConnection, LOWER_U) => HeaderMatchingUpgrade,
+                                    (HeaderConnection, _) | (HeaderUpgrade, _) => {
+                                        self.token_index = 0;
+                                        self.match_token(byte)
+                                    }
                                     (HeaderTransferEncoding, UPPER_C)
                                         | (HeaderTransferEncoding, LOWER_C) => HeaderMatchingChunked,
                                     (HeaderContentLength, _) => {
@@ -505,31 +715,10 @@ impl Parser {
                             match byte {
                                 CR => self.state = HeadersAlmostDone,
                                 LF => {
-                                    if handler.on_headers_complete(self) || self.upgrade || self.skip_body {
-                                        handler.on_message_complete(self);
-                                        self.reset();
-                                    } else if self.chunked {
-                                        self.state = BodyChunk;
-                                        self.cstate = ChunkSize;
-                                        self.message_body_rest = 0;
-                                    } else {
-                                        match self.message_body_rest {
-                                            0u => {
-                                                handler.on_message_complete(self);
-                                                self.reset();
-                                            }
-                                            UINT_MAX => if self.parser_type == ParseRequest || !self.needs_eof() {
-                                                handler.on_message_complete(self);
-                                                self.reset();
-                                            } else {
-                                                self.state = BodyIdentityEOF;
-                                            },
-                                            _ => self.state = BodyIdentity,
-                                        }
-                                    };
+                                    try!(self.headers_complete(handler));
                                     break
                                 }
-                                0x21..0x7e => {
+                                _ if http::is_field_name_char(byte) => {
                                     self.state = HeaderFieldStart;
                                     self.index = 1;
                                 }
@@ -546,11 +735,8 @@ impl Parser {
                                     HeaderFieldStart
                                 };
                                 match (self.hstate, self.index) {
-                                    (HeaderMatchingChunked, 7)    => self.chunked = true,
-                                    (HeaderMatchingClose, 5)      => self.keep_alive = false,
-                                    (HeaderMatchingKeepAlive, 10) => self.keep_alive = true,
-                                    (HeaderMatchingUpgrade, 6)    => self.upgrade = true,
-                                    _ => (),
+                                    (HeaderMatchingChunked, 7) => self.chunked = true,
+                                    _ => self.token_done(),
                                 }
                                 let start = if read > self.index + 1 { read - self.index - 1 } else { 0 };
                                 let end = read - 1;
@@ -559,27 +745,10 @@ impl Parser {
                                 self.index = 0;
                             }
                             _ => {
-                                if self.hstate != HeaderGeneral {
+                                if self.hstate.is_token_list() {
+                                    self.hstate = self.match_token(byte);
+                                } else if self.hstate != HeaderGeneral {
                                     self.hstate = match (self.hstate, byte) {
-                                        (HeaderMatchingKeepAlive, _) => match (byte, self.index) {
-                                            (UPPER_E, 1) | (LOWER_E, 1)
-                                                | (UPPER_E, 2) | (LOWER_E, 2)
-                                                | (UPPER_P, 3) | (LOWER_P, 3)
-                                                | (HYPHEN, 4)
-                                                | (UPPER_A, 5) | (LOWER_A, 5)
-                                                | (UPPER_L, 6) | (LOWER_L, 6)
-                                                | (UPPER_I, 7) | (LOWER_I, 7)
-                                                | (UPPER_V, 8) | (LOWER_V, 8)
-                                                | (UPPER_E, 9) | (LOWER_E, 9) => HeaderMatchingKeepAlive,
-                                            _ => HeaderGeneral,
-                                        },
-                                        (HeaderMatchingClose, _) => match (byte, self.index) {
-                                            (UPPER_L, 1) | (LOWER_L, 1)
-                                                | (UPPER_O, 2) | (LOWER_O, 2)
-                                                | (UPPER_S, 3) | (LOWER_S, 3)
-                                                | (UPPER_E, 4) | (LOWER_E, 4) => HeaderMatchingClose,
-                                            _ => HeaderGeneral,
-                                        },
                                         (HeaderMatchingChunked, _) => match (byte, self.index) {
                                             (UPPER_H, 1) | (LOWER_H, 1)
                                                 | (UPPER_U, 2) | (LOWER_U, 2)
@@ -589,15 +758,6 @@ impl Parser {
                                                 | (UPPER_D, 6) | (LOWER_D, 6) => HeaderMatchingChunked,
                                             _ => HeaderGeneral,
                                         },
-                                        (HeaderMatchingUpgrade, _) => match (byte, self.index) {
-                                            (UPPER_P, 1) | (LOWER_P, 1)
-                                                | (UPPER_G, 2) | (LOWER_G, 2)
-                                                | (UPPER_R, 3) | (LOWER_R, 3)
-                                                | (UPPER_A, 4) | (LOWER_A, 4)
-                                                | (UPPER_D, 5) | (LOWER_D, 5)
-                                                | (UPPER_E, 6) | (LOWER_E, 6) => HeaderMatchingUpgrade,
-                                            _ => HeaderGeneral,
-                                        },
                                         (HeaderContentLength, ZERO..NINE) => {
                                             self.message_body_rest *= 10;
                                             self.message_body_rest += (byte - ZERO) as uint;
@@ -620,31 +780,10 @@ impl Parser {
                     }
                     HeadersAlmostDone => {
                         if byte != LF { self.state = Crashed; return Err(InvalidHeaders) }
-                        if handler.on_headers_complete(self) || self.upgrade || self.skip_body {
-                            handler.on_message_complete(self);
-                            self.reset();
-                        } else if self.chunked {
-                            self.state = BodyChunk;
-                            self.cstate = ChunkSize;
-                            self.message_body_rest = 0;
-                        } else {
-                            match self.message_body_rest {
-                                0u => {
-                                    handler.on_message_complete(self);
-                                    self.reset();
-                                }
-                                UINT_MAX => if self.parser_type == ParseRequest || !self.needs_eof() {
-                                    handler.on_message_complete(self);
-                                    self.reset();
-                                } else {
-                                    self.state = BodyIdentityEOF;
-                                },
-                                _ => self.state = BodyIdentity,
-                            }
-                        }
+                        try!(self.headers_complete(handler));
                         break
                     }
-                    BodyIdentity | BodyIdentityEOF | BodyChunk | Crashed => unreachable!(),
+                    BodyIdentity | BodyIdentityEOF | BodyChunk | InterleavedData | Http2 | Crashed => unreachable!(),
                 }
             }
         }
@@ -655,9 +794,8 @@ impl Parser {
                 if rest >= self.message_body_rest {
                     handler.write(self, data.slice(read, read + self.message_body_rest));
                     handler.on_body(self, self.message_body_rest);
-                    handler.on_message_complete(self);
                     read += self.message_body_rest;
-                    self.reset();
+                    self.complete(handler);
                 } else {
                     handler.write(self, data.slice_from(read));
                     read += rest;
@@ -665,26 +803,20 @@ impl Parser {
                 }
             }
             BodyIdentityEOF if data.len() != read => {
+                self.body_size += data.len() - read;
+                if self.body_size > self.max_body_size { self.state = Crashed; return Err(BodyTooLarge) }
                 handler.write(self, data.slice_from(read));
+                read = data.len();
             }
             BodyChunk => {
                 'chunk: loop {
                     if self.cstate == ChunkData {
                         let rest = data.len() - read;
-                        if self.message_body_rest == 0 && rest > 2 {
-                            if data[read] != CR || data[read+1] != LF {
-                                self.state = Crashed;
-                                return Err(InvalidChunk);
-                            }
-                            read += 2;
-                            self.cstate = ChunkSize;
-                        } else if rest >= self.message_body_rest {
+                        if rest >= self.message_body_rest {
                             handler.write(self, data.slice(read, read + self.message_body_rest));
                             read += self.message_body_rest;
                             self.message_body_rest = 0;
-                            if data.len() - read < 2 { break 'chunk }
-                            read += 2;
-                            self.cstate = ChunkSize;
+                            self.cstate = ChunkDataAlmostDone;
                         } else {
                             handler.write(self, data.slice_from(read));
                             read += rest;
@@ -692,7 +824,7 @@ impl Parser {
                             break 'chunk;
                         }
                     } else {
-                        'chunksize: for &byte in data.slice_from(read).iter() {
+                        for &byte in data.slice_from(read).iter() {
                             read += 1;
                             match (self.cstate, byte) {
                                 (ChunkExtension, CR) => {
@@ -707,26 +839,74 @@ impl Parser {
                                 }
                                 (ChunkSize, _) => {
                                     let val = unhex(byte);
-                                    if val > 15 { self.state = Crashed; return Err(InvalidChunk) }
+                                    if val > 15 || self.message_body_rest > UINT_MAX >> 4 {
+                                        self.state = Crashed;
+                                        return Err(InvalidChunk);
+                                    }
                                     self.message_body_rest *= 16;
                                     self.message_body_rest += val;
                                 }
                                 (ChunkSizeAlmostDone, _) => {
                                     if byte != LF { self.state = Crashed; return Err(InvalidChunk) }
+                                    self.body_size += self.message_body_rest;
+                                    if self.body_size > self.max_body_size {
+                                        self.state = Crashed;
+                                        return Err(BodyTooLarge);
+                                    }
                                     if self.message_body_rest == 0 {
-                                        handler.on_message_complete(self);
-                                        break 'chunk;
+                                        self.cstate = ChunkTrailerStart;
                                     } else {
                                         self.cstate = ChunkData;
-                                        break 'chunksize;
+                                        continue 'chunk;
                                     }
                                 }
-                                _ => unreachable!()
+                                (ChunkDataAlmostDone, CR) => {
+                                    self.cstate = ChunkDataDone;
+                                }
+                                (ChunkDataDone, LF) => {
+                                    self.cstate = ChunkSize;
+                                }
+                                (ChunkDataAlmostDone, _) | (ChunkDataDone, _) => {
+                                    self.state = Crashed;
+                                    return Err(InvalidChunk);
+                                }
+                                (ChunkTrailerStart, CR) => {
+                                    self.cstate = ChunkTrailersAlmostDone;
+                                }
+                                (ChunkTrailerStart, LF) | (ChunkTrailersAlmostDone, LF) => {
+                                    self.complete(handler);
+                                    break 'chunk;
+                                }
+                                (ChunkTrailersAlmostDone, _) => {
+                                    self.state = Crashed;
+                                    return Err(InvalidChunk);
+                                }
+                                (ChunkTrailerStart, _) => {
+                                    self.cstate = ChunkTrailer; // ignore trailer fields
+                                }
+                                (ChunkTrailer, LF) => {
+                                    self.cstate = ChunkTrailerStart;
+                                }
+                                (ChunkTrailer, _) => { /* ignore */ }
+                                (ChunkData, _) => unreachable!()
                             }
                         }
+                        break 'chunk;
                     }
                 }
             }
+            InterleavedData => {
+                let rest = data.len() - read;
+                let n = if rest < self.message_body_rest { rest } else { self.message_body_rest };
+                handler.write(self, data.slice(read, read + n));
+                read += n;
+                self.message_body_rest -= n;
+                if self.message_body_rest == 0 {
+                    let (channel, size) = (self.channel, self.frame_size);
+                    self.reset();
+                    handler.on_interleaved_frame(self, channel, size);
+                }
+            }
             ReqUrl | HeaderField | HeaderValue => {
                 let start = if read > self.index { read - self.index } else { 0 };
                 handler.write(self, data.slice(start, read));
@@ -737,6 +917,55 @@ impl Parser {
         return Ok(read);
     }
 
+    /// Set the maximum size of start line and header section in bytes.
+    ///
+    /// Exceeding the limit results in `HeaderTooLarge`. Unlimited by default.
+    pub fn set_max_header_size(&mut self, size: uint) {
+        self.max_header_size = size;
+    }
+
+    /// Set the maximum size of message body in bytes.
+    ///
+    /// Exceeding the limit results in `BodyTooLarge`. Unlimited by default.
+    pub fn set_max_body_size(&mut self, size: uint) {
+        self.max_body_size = size;
+    }
+
+    /// Parse HTTP message until the end of a message.
+    ///
+    /// Returns the offset where the next message starts if a message is completed, or `None` if
+    /// whole data is consumed in the middle of a message. Feed the rest of data to parse following
+    /// pipelined messages.
+    pub fn next_message<C: MessageHandler>(&mut self, data: &[u8], handler: &mut C) -> Result<Option<uint>, ParseError> {
+        let mut read = 0u;
+        while read < data.len() {
+            let n = try!(self.parse(data.slice_from(read), handler));
+            read += n;
+            if self.completed { return Ok(Some(read)) }
+            if n == 0 { break }
+        }
+        Ok(None)
+    }
+
+    /// Notify the end of input, e.g. the connection is closed.
+    ///
+    /// Completes a response body delimited by closing the connection. Returns `UnexpectedEof` if
+    /// input ended in the middle of any other message.
+    pub fn finish<C: MessageHandler>(&mut self, handler: &mut C) -> ParseResult {
+        match self.state {
+            StartReq | StartRes | Http2 => Ok(0),
+            BodyIdentityEOF => {
+                self.complete(handler);
+                Ok(0)
+            }
+            Crashed => Err(OtherParseError),
+            _ => {
+                self.state = Crashed;
+                Err(UnexpectedEof)
+            }
+        }
+    }
+
     #[inline]
     /// Connection: keep-alive or Connection: close
     pub fn should_keep_alive(&self) -> bool {
@@ -755,6 +984,109 @@ impl Parser {
         self.chunked
     }
 
+    #[inline]
+    /// Returns the protocol given by `with_protocol`, or `None` for HTTP.
+    pub fn protocol(&self) -> Option<&'static Protocol> {
+        self.protocol
+    }
+
+    #[inline]
+    /// Returns how the last request starts HTTP/2, if it does.
+    ///
+    /// After `Http2Preface`, the connection should be handed to an HTTP/2 implementation with
+    /// unparsed bytes. `Http2Upgrade` is reported from `on_headers_complete`, and the server may
+    /// switch with `101 Switching Protocols` or ignore the upgrade.
+    pub fn http2_start(&self) -> Option<Http2Start> {
+        self.http2
+    }
+
+    #[inline]
+    fn protocol_name(&self) -> &'static str {
+        match self.protocol {
+            Some(protocol) => protocol.name,
+            None => "HTTP",
+        }
+    }
+
+    #[inline]
+    fn headers_complete<C: MessageHandler>(&mut self, handler: &mut C) -> Result<(), ParseError> {
+        if self.parser_type == ParseRequest && self.upgrade && self.h2c && self.http2_settings {
+            self.http2 = Some(Http2Upgrade);
+        }
+        if !self.chunked && self.message_body_rest != UINT_MAX && self.message_body_rest > self.max_body_size {
+            self.state = Crashed;
+            return Err(BodyTooLarge);
+        }
+        if handler.on_headers_complete(self) || self.upgrade || self.skip_body {
+            self.complete(handler);
+        } else if self.chunked {
+            self.state = BodyChunk;
+            self.cstate = ChunkSize;
+            self.message_body_rest = 0;
+        } else {
+            match self.message_body_rest {
+                0u => self.complete(handler),
+                UINT_MAX => if self.parser_type == ParseRequest || !self.needs_eof() {
+                    self.complete(handler);
+                } else {
+                    self.state = BodyIdentityEOF;
+                },
+                _ => self.state = BodyIdentity,
+            }
+        }
+        Ok(())
+    }
+
+    /// Match comma-separated tokens of `Connection` and `Upgrade` header values.
+    #[inline]
+    fn match_token(&mut self, byte: u8) -> HeaderState {
+        let (start, skip) = match self.hstate {
+            HeaderUpgrade | HeaderUpgradeToken | HeaderMatchingH2c => (HeaderUpgrade, HeaderUpgradeToken),
+            _ => (HeaderConnection, HeaderConnectionToken),
+        };
+        if byte == COMMA {
+            self.token_done();
+            self.token_index = 0;
+            return start;
+        }
+        if byte == SPACE || byte == TAB {
+            if self.hstate == start { return start }
+            self.token_done();
+            return skip;
+        }
+        let hstate = match self.hstate {
+            HeaderConnection if token_char(byte, "close", 0)      => HeaderMatchingClose,
+            HeaderConnection if token_char(byte, "keep-alive", 0) => HeaderMatchingKeepAlive,
+            HeaderConnection if token_char(byte, "upgrade", 0)    => HeaderMatchingUpgrade,
+            HeaderUpgrade    if token_char(byte, "h2c", 0)        => HeaderMatchingH2c,
+            HeaderMatchingClose     if token_char(byte, "close", self.token_index)      => HeaderMatchingClose,
+            HeaderMatchingKeepAlive if token_char(byte, "keep-alive", self.token_index) => HeaderMatchingKeepAlive,
+            HeaderMatchingUpgrade   if token_char(byte, "upgrade", self.token_index)    => HeaderMatchingUpgrade,
+            HeaderMatchingH2c       if token_char(byte, "h2c", self.token_index)        => HeaderMatchingH2c,
+            _ => skip,
+        };
+        self.token_index += 1;
+        hstate
+    }
+
+    #[inline]
+    fn token_done(&mut self) {
+        match (self.hstate, self.token_index) {
+            (HeaderMatchingClose, 5)      => self.keep_alive = false,
+            (HeaderMatchingKeepAlive, 10) => self.keep_alive = true,
+            (HeaderMatchingUpgrade, 7)    => self.upgrade = true,
+            (HeaderMatchingH2c, 3)        => self.h2c = true,
+            _ => (),
+        }
+    }
+
+    #[inline]
+    fn complete<C: MessageHandler>(&mut self, handler: &mut C) {
+        handler.on_message_complete(self);
+        self.reset();
+        self.completed = true;
+    }
+
     #[inline]
     fn reset(&mut self) {
         self.state = match self.parser_type {
@@ -765,18 +1097,20 @@ impl Parser {
         self.major = 0;
         self.minor = 0;
         self.message_body_rest = UINT_MAX;
+        self.header_size = 0;
+        self.body_size = 0;
         self.skip_body = false;
-        self.status_code = 0;
+        self.status_code = http::StatusCode(0);
     }
 
     #[inline]
     fn needs_eof(&mut self) -> bool {
-        if self.parser_type == ParseRequest {
+        if self.parser_type == ParseRequest || self.protocol.is_some() {
             return false;
         }
-        if self.status_code / 100 == 1 ||     // 1xx e.g. Continue
-            self.status_code == 204 ||        // No Content
-            self.status_code == 304 ||        // Not Modified
+        if self.status_code.is_informational() ||
+            self.status_code == status::NO_CONTENT ||
+            self.status_code == status::NOT_MODIFIED ||
             self.skip_body {
             return false;
         }
@@ -789,6 +1123,8 @@ static TAB: u8       = 0x09;
 static LF: u8        = 0x0a;
 static CR: u8        = 0x0d;
 static SPACE: u8     = 0x20;
+static DOLLAR: u8    = 0x24;
+static COMMA: u8     = 0x2c;
 static HYPHEN: u8    = 0x2d;
 static DOT: u8       = 0x2e;
 static SLASH: u8     = 0x2f;
@@ -815,6 +1151,7 @@ static UPPER_S: u8   = 0x53;
 static UPPER_T: u8   = 0x54;
 static UPPER_U: u8   = 0x55;
 static UPPER_V: u8   = 0x56;
+static UPPER_Z: u8   = 0x5a;
 static LOWER_A: u8   = 0x61;
 static LOWER_C: u8   = 0x63;
 static LOWER_D: u8   = 0x64;
@@ -834,6 +1171,26 @@ static LOWER_T: u8   = 0x74;
 static LOWER_U: u8   = 0x75;
 static LOWER_V: u8   = 0x76;
 
+static HTTP2_PREFACE: &'static str = "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+#[inline]
+fn token_char(byte: u8, token: &str, index: uint) -> bool {
+    let lower = if byte >= UPPER_A && byte <= UPPER_Z { byte + 0x20 } else { byte };
+    index < token.len() && token.as_bytes()[index] == lower
+}
+
+#[inline]
+fn find_method(methods: &[&'static str], current: uint, index: uint, byte: u8) -> Option<uint> {
+    let prefix = if index == 0 { "" } else { methods[current].slice_to(index) };
+    methods.iter().position(|m| m.len() > index && m.as_bytes()[index] == byte && m.starts_with(prefix))
+}
+
+#[inline]
+fn find_exact_method(methods: &[&'static str], current: uint, index: uint) -> Option<uint> {
+    let prefix = methods[current].slice_to(index);
+    methods.iter().position(|m| *m == prefix)
+}
+
 #[inline]
 fn unhex(b: u8) -> uint {
     match b {
@@ -868,6 +1225,12 @@ enum ParserState {
     BodyIdentity,
     BodyIdentityEOF,
     BodyChunk,
+    ReqProtocolMethod,
+    ReqHttp2Preface,
+    Http2,
+    InterleavedChannel,
+    InterleavedLength,
+    InterleavedData,
     Crashed,
 }
 
@@ -875,7 +1238,7 @@ impl ParserState {
     #[inline]
     fn is_body(&self) -> bool {
         match *self {
-            BodyIdentity | BodyIdentityEOF | BodyChunk => true,
+            BodyIdentity | BodyIdentityEOF | BodyChunk | InterleavedData => true,
             _ => false,
         }
     }
@@ -888,10 +1251,25 @@ enum HeaderState {
     HeaderContentLength,
     HeaderTransferEncoding,
     HeaderUpgrade,
+    HeaderHttp2Settings,
+    HeaderConnectionToken,
+    HeaderUpgradeToken,
     HeaderMatchingChunked,
     HeaderMatchingClose,
     HeaderMatchingKeepAlive,
     HeaderMatchingUpgrade,
+    HeaderMatchingH2c,
+}
+
+impl HeaderState {
+    #[inline]
+    fn is_token_list(&self) -> bool {
+        match *self {
+            HeaderConnection | HeaderConnectionToken | HeaderMatchingClose | HeaderMatchingKeepAlive
+                | HeaderMatchingUpgrade | HeaderUpgrade | HeaderUpgradeToken | HeaderMatchingH2c => true,
+            _ => false,
+        }
+    }
 }
 
 #[deriving(PartialEq, Eq, Clone, Show)]
@@ -900,4 +1278,9 @@ enum ChunkState {
     ChunkSizeAlmostDone,
     ChunkExtension,
     ChunkData,
+    ChunkDataAlmostDone,
+    ChunkDataDone,
+    ChunkTrailerStart,
+    ChunkTrailer,
+    ChunkTrailersAlmostDone,
 }
@@ -0,0 +1,167 @@
+//! Helpers for HTTP forward proxies.
+//!
+//! A forward proxy receives requests in absolute-form, e.g. `GET http://example.com/ HTTP/1.1`,
+//! and forwards them to the origin server in origin-form. Hop-by-hop headers are removed in both
+//! directions, and bodies are streamed through as is.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::io::{IoResult, EndOfFile};
+use std::io::net::ip::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use http;
+use http::split_url;
+use http::reader::Message;
+use http::serializer::MessageWriter;
+
+/// Header names which are meaningful only for a single connection, see RFC 7230 section 6.1.
+pub static HOP_BY_HOP_HEADERS: &'static [&'static str] = &[
+    "Connection", "Keep-Alive", "Proxy-Authenticate", "Proxy-Authorization", "Proxy-Connection",
+    "TE", "Trailer", "Transfer-Encoding", "Upgrade",
+];
+
+/// Request head rewritten for the origin server.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct ForwardRequest {
+    /// Host of the origin server.
+    pub host: String,
+    /// Port of the origin server.
+    pub port: u16,
+    /// Request method.
+    pub method: http::HttpMethod,
+    /// Request target in origin-form.
+    pub target: String,
+    /// End-to-end header fields, with `Host`, `Via` and `Forwarded`.
+    pub headers: Vec<(String, String)>,
+    /// Whether the request has a body, i.e. `Content-Length` or `Transfer-Encoding` is given.
+    pub has_body: bool,
+}
+
+/// Returns true if the header is hop-by-hop in the message, i.e. one of `HOP_BY_HOP_HEADERS` or
+/// listed in `Connection` header.
+pub fn is_hop_by_hop(message: &Message, name: &str) -> bool {
+    HOP_BY_HOP_HEADERS.iter().any(|n| n.eq_ignore_ascii_case(name)) ||
+        message.headers_named("Connection").iter()
+            .any(|value| value.split(',').any(|token| token.trim().eq_ignore_ascii_case(name)))
+}
+
+/// Returns header fields of the message without hop-by-hop headers.
+pub fn end_to_end_headers(message: &Message) -> Vec<(String, String)> {
+    message.headers.iter()
+        .filter(|&&(ref name, _)| !is_hop_by_hop(message, name.as_slice()))
+        .map(|&(ref name, ref value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Rewrite a request in absolute-form for the origin server.
+///
+/// `Host` is replaced with the authority of the URL. `Via` with the pseudonym of the proxy, and
+/// `Forwarded` with the client address are appended. Returns `None` if the request target is not
+/// an absolute `http` URL.
+pub fn forward_request(request: &Message, pseudonym: &str, client: Option<IpAddr>) -> Option<ForwardRequest> {
+    let method = match request.method {
+        Some(method) if method != http::HttpConnect => method,
+        _ => return None,
+    };
+    let (host, port, target) = match request.url {
+        Some(ref url) => match split_url(url.as_slice()) {
+            Some(parts) => parts,
+            None => return None,
+        },
+        None => return None,
+    };
+    let authority = http::authority(host.as_slice(), port);
+
+    let mut headers: Vec<(String, String)> = end_to_end_headers(request).move_iter()
+        .filter(|&(ref name, _)| !name.as_slice().eq_ignore_ascii_case("Host"))
+        .collect();
+    headers.insert(0, ("Host".to_string(), authority.clone()));
+    headers.push(("Via".to_string(), via(request, pseudonym)));
+    let node = match client {
+        Some(ip @ Ipv4Addr(..)) => format!("{}", ip),
+        Some(ip @ Ipv6Addr(..)) => format!("\"[{}]\"", ip),
+        None => "unknown".to_string(),
+    };
+    headers.push(("Forwarded".to_string(), format!("for={};host={};proto=http", node, quote(authority.as_slice()))));
+
+    Some(ForwardRequest {
+        host: host,
+        port: port,
+        method: method,
+        target: target,
+        headers: headers,
+        has_body: request.chunked || request.header("Content-Length").is_some(),
+    })
+}
+
+/// Returns header fields of a response to relay to the client, with `Via` appended.
+pub fn forward_response_headers(response: &Message, pseudonym: &str) -> Vec<(String, String)> {
+    let mut headers = end_to_end_headers(response);
+    headers.push(("Via".to_string(), via(response, pseudonym)));
+    headers
+}
+
+/// Write the request head to the origin server, and stream the body from the reader.
+///
+/// A chunked body is forwarded with chunked encoding, and other bodies as is.
+pub fn write_request<W: Writer, R: Reader>(writer: W, request: &ForwardRequest, body: &mut R) -> IoResult<W> {
+    let mut w = MessageWriter::new(writer);
+    try!(w.request_line(request.method, request.target.as_slice(), http::HTTP_1_1));
+    for &(ref name, ref value) in request.headers.iter() {
+        try!(w.header(name.as_slice(), value.as_slice()));
+    }
+    if request.has_body {
+        try!(copy_body(body, &mut w));
+    }
+    w.finish()
+}
+
+/// Write the response of the origin server to the client, and stream the body from the reader.
+///
+/// The response is written in the version of the request. A body delimited by closing the
+/// connection is forwarded with chunked encoding to HTTP/1.1 clients.
+pub fn write_response<W: Writer, R: Reader>(writer: W, request: &Message, response: &Message, pseudonym: &str,
+                                             body: &mut R) -> IoResult<W> {
+    let mut w = MessageWriter::new(writer);
+    let status = response.status.unwrap_or(http::status::BAD_GATEWAY);
+    try!(w.status_line(request.version.unwrap_or(http::HTTP_1_0), status));
+    for &(ref name, ref value) in forward_response_headers(response, pseudonym).iter() {
+        try!(w.header(name.as_slice(), value.as_slice()));
+    }
+    if request.method == Some(http::HttpHead) {
+        w.no_body();
+    } else {
+        try!(copy_body(body, &mut w));
+    }
+    w.finish()
+}
+
+fn copy_body<R: Reader, W: Writer>(body: &mut R, w: &mut MessageWriter<W>) -> IoResult<()> {
+    try!(w.start_body());
+    let mut buf = Vec::from_elem(4096, 0u8);
+    loop {
+        match body.read(buf.as_mut_slice()) {
+            Ok(n) => try!(w.write(buf.slice_to(n))),
+            Err(ref e) if e.kind == EndOfFile => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn via(message: &Message, pseudonym: &str) -> String {
+    let version = match message.version {
+        Some(http::HTTP_1_1) => "1.1",
+        Some(http::HTTP_1_0) => "1.0",
+        _ => "0.9",
+    };
+    format!("{} {}", version, pseudonym)
+}
+
+fn quote(value: &str) -> String {
+    if http::is_token(value) {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace("\\", "\\\\").replace("\"", "\\\""))
+    }
+}
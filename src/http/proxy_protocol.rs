@@ -0,0 +1,273 @@
+//! PROXY protocol header, which load balancers prepend to a connection before HTTP messages.
+//!
+//! Both the human-readable version 1 and the binary version 2 are parsed. Parse the header with
+//! `parse_proxy_header` or `read_proxy_header`, and feed the rest of the stream to `Parser`.
+
+#![experimental]
+
+use std::io::{IoError, IoResult, InvalidInput};
+use std::io::net::ip::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str;
+
+/// Type of TLV: Application-Layer Protocol Negotiation.
+pub static PP2_TYPE_ALPN: u8 = 0x01;
+/// Type of TLV: host name given by the client, e.g. TLS SNI.
+pub static PP2_TYPE_AUTHORITY: u8 = 0x02;
+/// Type of TLV: CRC32c checksum of the header.
+pub static PP2_TYPE_CRC32C: u8 = 0x03;
+/// Type of TLV: padding.
+pub static PP2_TYPE_NOOP: u8 = 0x04;
+/// Type of TLV: opaque connection ID.
+pub static PP2_TYPE_UNIQUE_ID: u8 = 0x05;
+/// Type of TLV: TLS information.
+pub static PP2_TYPE_SSL: u8 = 0x20;
+/// Type of TLV: network namespace.
+pub static PP2_TYPE_NETNS: u8 = 0x30;
+
+/// A list of PROXY protocol commands.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum ProxyCommand {
+    /// The connection is made by the proxy itself, e.g. health checks. Use the real peer address.
+    CommandLocal,
+    /// The connection is relayed on behalf of the client.
+    CommandProxy,
+}
+
+/// An address carried by PROXY protocol header.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum ProxyAddress {
+    /// TCP or UDP over IPv4 or IPv6.
+    InetAddress(SocketAddr),
+    /// Path of a UNIX domain socket, trailing NUL bytes are removed.
+    UnixAddress(Vec<u8>),
+}
+
+/// Parsed PROXY protocol header.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct ProxyHeader {
+    /// Protocol version, 1 or 2.
+    pub version: uint,
+    /// Command. Version 1 `UNKNOWN` is reported as `CommandLocal`.
+    pub command: ProxyCommand,
+    /// Source address, i.e. the client. `None` for unknown or unspecified address family.
+    pub source: Option<ProxyAddress>,
+    /// Destination address, i.e. the proxy.
+    pub destination: Option<ProxyAddress>,
+    /// Type-length-value vectors of version 2 in received order.
+    pub tlvs: Vec<(u8, Vec<u8>)>,
+}
+
+impl ProxyHeader {
+    /// Find the first TLV value with the type.
+    pub fn tlv<'a>(&'a self, t: u8) -> Option<&'a [u8]> {
+        self.tlvs.iter().find(|&&(ty, _)| ty == t).map(|&(_, ref v)| v.as_slice())
+    }
+}
+
+/// A list specifying categories of PROXY protocol errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum ProxyError {
+    /// Data does not start with PROXY protocol signature. A connection without the header can be
+    /// handled as is, if the header is optional.
+    NotProxyHeader,
+    /// Invalid version 1 line or version 2 header.
+    InvalidProxyHeader,
+    /// Version 2 signature with unknown version or command.
+    UnsupportedProxyVersion,
+}
+
+/// Result of PROXY protocol header parsing.
+pub type ProxyResult<T> = Result<T, ProxyError>;
+
+/// Parse PROXY protocol header at the start of data.
+///
+/// Returns the header and its length, or `None` if data ends in the middle of the header. Bytes
+/// following the length are the first HTTP message.
+pub fn parse_proxy_header(data: &[u8]) -> ProxyResult<Option<(ProxyHeader, uint)>> {
+    if starts_with_prefix(data, V2_SIGNATURE) {
+        if data.len() < V2_HEADER_SIZE { return Ok(None) }
+        parse_v2(data)
+    } else if starts_with_prefix(data, V1_SIGNATURE.as_bytes()) {
+        if data.len() < V1_SIGNATURE.len() { return Ok(None) }
+        parse_v1(data)
+    } else {
+        Err(NotProxyHeader)
+    }
+}
+
+/// Read PROXY protocol header from a stream.
+///
+/// Bytes following the header are never consumed, so the stream can be passed to `MessageReader`
+/// as is. If the stream does not start with PROXY protocol signature, returns `Err` with the bytes
+/// read so far, which are the start of the first HTTP message, see `MessageReader::with_buffer`.
+/// Parse errors are returned as `InvalidInput`.
+pub fn read_proxy_header<R: Reader>(reader: &mut R) -> IoResult<Result<ProxyHeader, Vec<u8>>> {
+    let mut buf = Vec::new();
+    loop {
+        let parsed = parse_proxy_header(buf.as_slice());
+        match parsed {
+            Ok(Some((header, _))) => return Ok(Ok(header)),
+            Ok(None) => (),
+            Err(NotProxyHeader) => return Ok(Err(buf)),
+            Err(e) => return Err(IoError {
+                kind: InvalidInput,
+                desc: "invalid PROXY protocol header",
+                detail: Some(format!("{}", e)),
+            }),
+        }
+        let wanted = if buf.len() >= V2_HEADER_SIZE && buf.as_slice().starts_with(V2_SIGNATURE) {
+            V2_HEADER_SIZE + read_u16(buf.slice_from(14)) as uint - buf.len()
+        } else {
+            // one byte at a time, not to block on a short request without the header, nor to read
+            // past the end of version 1 line.
+            1
+        };
+        buf.push_all(try!(reader.read_exact(wanted)).as_slice());
+    }
+}
+
+fn parse_v1(data: &[u8]) -> ProxyResult<Option<(ProxyHeader, uint)>> {
+    let end = match data.iter().take(V1_MAX_SIZE).position(|&b| b == LF) {
+        Some(pos) => pos,
+        None if data.len() >= V1_MAX_SIZE => return Err(InvalidProxyHeader),
+        None => return Ok(None),
+    };
+    if end == 0 || data[end - 1] != CR { return Err(InvalidProxyHeader) }
+    let line = match str::from_utf8(data.slice(0, end - 1)) {
+        Some(line) => line,
+        None => return Err(InvalidProxyHeader),
+    };
+    let fields: Vec<&str> = line.split(' ').collect();
+    let mut header = ProxyHeader {
+        version: 1,
+        command: CommandProxy,
+        source: None,
+        destination: None,
+        tlvs: Vec::new(),
+    };
+    match fields.as_slice()[1] {
+        "UNKNOWN" => header.command = CommandLocal, // the rest of line is ignored.
+        family @ "TCP4" | family @ "TCP6" if fields.len() == 6 => {
+            let v4 = family == "TCP4";
+            let f = fields.as_slice();
+            header.source = Some(InetAddress(try!(parse_v1_address(f[2], f[4], v4))));
+            header.destination = Some(InetAddress(try!(parse_v1_address(f[3], f[5], v4))));
+        }
+        _ => return Err(InvalidProxyHeader),
+    }
+    Ok(Some((header, end + 1)))
+}
+
+fn parse_v1_address(ip: &str, port: &str, v4: bool) -> ProxyResult<SocketAddr> {
+    let ip = match from_str::<IpAddr>(ip) {
+        Some(ip @ Ipv4Addr(..)) if v4 => ip,
+        Some(ip @ Ipv6Addr(..)) if !v4 => ip,
+        _ => return Err(InvalidProxyHeader),
+    };
+    // leading zeros are not allowed.
+    if port.len() == 0 || port.len() > 1 && port.starts_with("0") ||
+        !port.chars().all(|c| c >= '0' && c <= '9') {
+        return Err(InvalidProxyHeader);
+    }
+    match from_str::<u16>(port) {
+        Some(port) => Ok(SocketAddr { ip: ip, port: port }),
+        None => Err(InvalidProxyHeader),
+    }
+}
+
+fn parse_v2(data: &[u8]) -> ProxyResult<Option<(ProxyHeader, uint)>> {
+    let ver_cmd = data[12];
+    let family = data[13];
+    let length = V2_HEADER_SIZE + read_u16(data.slice_from(14)) as uint;
+    if ver_cmd >> 4 != 2 { return Err(UnsupportedProxyVersion) }
+    let command = match ver_cmd & 0x0f {
+        0 => CommandLocal,
+        1 => CommandProxy,
+        _ => return Err(UnsupportedProxyVersion),
+    };
+    if data.len() < length { return Ok(None) }
+
+    let body = data.slice(V2_HEADER_SIZE, length);
+    let (addresses, size) = match family >> 4 {
+        AF_INET => {
+            if body.len() < 12 { return Err(InvalidProxyHeader) }
+            let ip = |b: &[u8]| Ipv4Addr(b[0], b[1], b[2], b[3]);
+            (Some((InetAddress(SocketAddr { ip: ip(body.slice(0, 4)), port: read_u16(body.slice_from(8)) }),
+                   InetAddress(SocketAddr { ip: ip(body.slice(4, 8)), port: read_u16(body.slice_from(10)) }))),
+             12)
+        }
+        AF_INET6 => {
+            if body.len() < 36 { return Err(InvalidProxyHeader) }
+            let ip = |b: &[u8]| Ipv6Addr(read_u16(b), read_u16(b.slice_from(2)), read_u16(b.slice_from(4)),
+                                         read_u16(b.slice_from(6)), read_u16(b.slice_from(8)),
+                                         read_u16(b.slice_from(10)), read_u16(b.slice_from(12)),
+                                         read_u16(b.slice_from(14)));
+            (Some((InetAddress(SocketAddr { ip: ip(body.slice(0, 16)), port: read_u16(body.slice_from(32)) }),
+                   InetAddress(SocketAddr { ip: ip(body.slice(16, 32)), port: read_u16(body.slice_from(34)) }))),
+             36)
+        }
+        AF_UNIX => {
+            if body.len() < 216 { return Err(InvalidProxyHeader) }
+            let path = |b: &[u8]| {
+                let end = b.iter().position(|&c| c == 0).unwrap_or(b.len());
+                UnixAddress(b.slice_to(end).to_vec())
+            };
+            (Some((path(body.slice(0, 108)), path(body.slice(108, 216)))), 216)
+        }
+        AF_UNSPEC => (None, 0),
+        _ => return Err(InvalidProxyHeader),
+    };
+
+    let mut tlvs = Vec::new();
+    let mut rest = body.slice_from(size);
+    while rest.len() > 0 {
+        if rest.len() < 3 { return Err(InvalidProxyHeader) }
+        let len = read_u16(rest.slice_from(1)) as uint;
+        if rest.len() < 3 + len { return Err(InvalidProxyHeader) }
+        tlvs.push((rest[0], rest.slice(3, 3 + len).to_vec()));
+        rest = rest.slice_from(3 + len);
+    }
+
+    let mut header = ProxyHeader {
+        version: 2,
+        command: command,
+        source: None,
+        destination: None,
+        tlvs: tlvs,
+    };
+    // addresses of LOCAL command are meaningless.
+    if command == CommandProxy {
+        match addresses {
+            Some((src, dst)) => {
+                header.source = Some(src);
+                header.destination = Some(dst);
+            }
+            None => (),
+        }
+    }
+    Ok(Some((header, length)))
+}
+
+#[inline]
+fn starts_with_prefix(data: &[u8], signature: &[u8]) -> bool {
+    let len = if data.len() < signature.len() { data.len() } else { signature.len() };
+    data.slice_to(len) == signature.slice_to(len)
+}
+
+#[inline]
+fn read_u16(b: &[u8]) -> u16 {
+    (b[0] as u16 << 8) | b[1] as u16
+}
+
+static V1_SIGNATURE: &'static str = "PROXY ";
+static V1_MAX_SIZE: uint = 107;
+static V2_SIGNATURE: &'static [u8] = &[0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a];
+static V2_HEADER_SIZE: uint = 16;
+
+static AF_UNSPEC: u8 = 0x0;
+static AF_INET: u8 = 0x1;
+static AF_INET6: u8 = 0x2;
+static AF_UNIX: u8 = 0x3;
+
+static LF: u8 = 0x0a;
+static CR: u8 = 0x0d;
@@ -0,0 +1,359 @@
+//! Range requests, i.e. `Range`, `If-Range` and `Content-Range` headers, see RFC 7233.
+//!
+//! A server parses `Range` with `parse_range`, resolves it against the representation length with
+//! `satisfiable_ranges`, and writes `206 Partial Content` with `write_partial_content`. A client
+//! parses a single part response with `ContentRange::parse`, and `multipart/byteranges` body with
+//! `parse_byteranges`.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::cmp::{max, min};
+use std::fmt::{Formatter, FormatError, Show};
+use std::io::{IoError, IoResult, InvalidInput, Seek, SeekSet};
+use std::str;
+use time::Timespec;
+
+use http::date::parse_date;
+use http::multipart::{MultipartHandler, MultipartParser, boundary};
+use http::serializer::MessageWriter;
+
+/// Maximum number of ranges sent in a `206 Partial Content` response.
+pub static MAX_RANGES: uint = 16;
+
+/// A byte range given by `Range` header.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum ByteRange {
+    /// First and last byte positions, inclusive, e.g. `0-499`.
+    ByteRangeFromTo(uint, uint),
+    /// First byte position to the end, e.g. `9500-`.
+    ByteRangeFrom(uint),
+    /// Length of the last bytes, e.g. `-500`.
+    ByteRangeSuffix(uint),
+}
+
+impl ByteRange {
+    /// Resolve the range against the representation length.
+    ///
+    /// Returns first and last byte positions, inclusive, or `None` if the range is unsatisfiable.
+    pub fn resolve(&self, length: uint) -> Option<(uint, uint)> {
+        match *self {
+            ByteRangeFromTo(first, last) if first < length => Some((first, min(last, length - 1))),
+            ByteRangeFrom(first) if first < length => Some((first, length - 1)),
+            ByteRangeSuffix(n) if n > 0 && length > 0 => Some((length - min(n, length), length - 1)),
+            _ => None,
+        }
+    }
+}
+
+/// `Content-Range` header value.
+///
+/// `Show` formats the header value, e.g. `bytes 0-499/1234` or `bytes */1234`.
+#[deriving(PartialEq, Eq, Clone)]
+pub struct ContentRange {
+    /// First and last byte positions, inclusive. `None` for unsatisfied range.
+    pub range: Option<(uint, uint)>,
+    /// Complete representation length. `None` if unknown.
+    pub length: Option<uint>,
+}
+
+impl ContentRange {
+    /// Parse `Content-Range` header value. Only `bytes` unit is supported.
+    pub fn parse(value: &str) -> Option<ContentRange> {
+        let value = value.trim();
+        let (unit, rest) = match value.find(' ') {
+            Some(pos) => (value.slice_to(pos), value.slice_from(pos + 1).trim_left()),
+            None => return None,
+        };
+        if !unit.eq_ignore_ascii_case("bytes") { return None }
+        let (range, length) = match rest.find('/') {
+            Some(pos) => (rest.slice_to(pos), rest.slice_from(pos + 1)),
+            None => return None,
+        };
+        let length = match length {
+            "*" => None,
+            n => match number(n) {
+                Some(n) => Some(n),
+                None => return None,
+            },
+        };
+        let range = match range {
+            "*" if length.is_some() => None,
+            r => match (split_range(r), length) {
+                (Some((_, last)), Some(n)) if last >= n => return None,
+                (Some(range), _) => Some(range),
+                (None, _) => return None,
+            },
+        };
+        Some(ContentRange { range: range, length: length })
+    }
+}
+
+impl Show for ContentRange {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match self.range {
+            Some((first, last)) => try!(write!(f, "bytes {}-{}/", first, last)),
+            None => try!(write!(f, "bytes */")),
+        }
+        match self.length {
+            Some(length) => write!(f, "{}", length),
+            None => write!(f, "*"),
+        }
+    }
+}
+
+/// A part of `multipart/byteranges` body.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct BytePart {
+    /// `Content-Range` of the part.
+    pub range: ContentRange,
+    /// `Content-Type` of the part, if given.
+    pub content_type: Option<String>,
+    /// Part data.
+    pub data: Vec<u8>,
+}
+
+/// Parse `Range` header value into byte ranges in received order, e.g. `bytes=0-499, -500`.
+///
+/// Returns `None` if the value is invalid or the unit is not `bytes`, then the header should be
+/// ignored.
+pub fn parse_range(value: &str) -> Option<Vec<ByteRange>> {
+    let value = value.trim();
+    let pos = match value.find('=') {
+        Some(pos) => pos,
+        None => return None,
+    };
+    if !value.slice_to(pos).trim().eq_ignore_ascii_case("bytes") { return None }
+    let mut ranges = Vec::new();
+    // empty list elements are allowed.
+    for spec in value.slice_from(pos + 1).split(',').map(|s| s.trim()).filter(|s| s.len() > 0) {
+        let range = if spec.starts_with("-") {
+            number(spec.slice_from(1)).map(|n| ByteRangeSuffix(n))
+        } else if spec.ends_with("-") {
+            number(spec.slice_to(spec.len() - 1)).map(|n| ByteRangeFrom(n))
+        } else {
+            split_range(spec).map(|(first, last)| ByteRangeFromTo(first, last))
+        };
+        match range {
+            Some(range) => ranges.push(range),
+            None => return None,
+        }
+    }
+    if ranges.is_empty() { None } else { Some(ranges) }
+}
+
+/// Resolve byte ranges against the representation length, and returns satisfiable ones as first
+/// and last byte positions, inclusive.
+///
+/// Ranges are sorted, and overlapping or adjacent ones are coalesced, so no byte is sent twice. If
+/// more than `MAX_RANGES` ranges remain, they are coalesced into a single range from the first to
+/// the last byte. Empty result means `416 Range Not Satisfiable`.
+pub fn satisfiable_ranges(ranges: &[ByteRange], length: uint) -> Vec<(uint, uint)> {
+    let mut resolved: Vec<(uint, uint)> = ranges.iter().filter_map(|range| range.resolve(length)).collect();
+    resolved.sort();
+    let mut merged: Vec<(uint, uint)> = Vec::new();
+    for &(first, last) in resolved.iter() {
+        let previous = merged.last().map(|&range| range);
+        match previous {
+            // last byte position is less than the length, so `end + 1` never overflows.
+            Some((start, end)) if first <= end + 1 => {
+                merged.pop();
+                merged.push((start, max(end, last)));
+            }
+            _ => merged.push((first, last)),
+        }
+    }
+    if merged.len() > MAX_RANGES {
+        let (first, last) = (merged[0].val0(), merged[merged.len() - 1].val1());
+        return vec![(first, last)];
+    }
+    merged
+}
+
+/// Returns true if `If-Range` header value matches the current representation, i.e. `Range` header
+/// should be applied. Otherwise the whole representation should be sent.
+///
+/// Entity tags are compared with the strong comparison, and dates must equal `Last-Modified`.
+pub fn if_range_matches(value: &str, etag: Option<&str>, last_modified: Option<Timespec>) -> bool {
+    let value = value.trim();
+    if value.starts_with("\"") || value.starts_with("W/") {
+        match etag {
+            Some(etag) => !value.starts_with("W/") && !etag.starts_with("W/") && value == etag.trim(),
+            None => false,
+        }
+    } else {
+        match (parse_date(value), last_modified) {
+            (Some(date), Some(modified)) => date == modified,
+            _ => false,
+        }
+    }
+}
+
+/// Write `Content-Range`, `Content-Type` and `Content-Length` headers and the body of
+/// `206 Partial Content` response, and finish the message.
+///
+/// The status line and other headers must be written already. Ranges must be satisfiable, sorted
+/// without overlaps, and at most `MAX_RANGES`, e.g. given by `satisfiable_ranges`. Otherwise
+/// returns an `InvalidInput` error before writing anything. A single range is sent as is, and
+/// multiple ranges as `multipart/byteranges` body delimited by the boundary, which must not appear
+/// in the data.
+pub fn write_partial_content<W: Writer, R: Reader + Seek>(mut w: MessageWriter<W>, ranges: &[(uint, uint)],
+                                                          length: uint, content_type: &str, boundary: &str,
+                                                          body: &mut R) -> IoResult<W> {
+    if ranges.len() == 0 || ranges.len() > MAX_RANGES {
+        return Err(invalid_ranges("no ranges or too many ranges"));
+    }
+    let mut next = 0u;
+    for &(first, last) in ranges.iter() {
+        if first < next || first > last || last >= length {
+            return Err(invalid_ranges("ranges overlap or are not satisfiable"));
+        }
+        next = last + 1;
+    }
+    if ranges.len() == 1 {
+        let (first, last) = ranges[0];
+        let content_range = ContentRange { range: Some((first, last)), length: Some(length) };
+        try!(w.header("Content-Range", format!("{}", content_range).as_slice()));
+        try!(w.header("Content-Type", content_type));
+        try!(w.header("Content-Length", (last - first + 1).to_string().as_slice()));
+        try!(w.start_body());
+        try!(copy_range(body, first, last, &mut w));
+        return w.finish();
+    }
+
+    let heads: Vec<String> = ranges.iter().enumerate().map(|(i, &(first, last))| {
+        let content_range = ContentRange { range: Some((first, last)), length: Some(length) };
+        // the first delimiter needs no preceding CRLF.
+        format!("{}--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+                if i == 0 { "" } else { "\r\n" }, boundary, content_type, content_range)
+    }).collect();
+    let close = format!("\r\n--{}--\r\n", boundary);
+    let total = ranges.iter().zip(heads.iter()).fold(close.len(), |n, (&(first, last), head)| {
+        n + head.len() + last - first + 1
+    });
+    try!(w.header("Content-Type", format!("multipart/byteranges; boundary={}", boundary).as_slice()));
+    try!(w.header("Content-Length", total.to_string().as_slice()));
+    try!(w.start_body());
+    for (&(first, last), head) in ranges.iter().zip(heads.iter()) {
+        try!(w.write(head.as_bytes()));
+        try!(copy_range(body, first, last, &mut w));
+    }
+    try!(w.write(close.as_bytes()));
+    w.finish()
+}
+
+/// Parse `multipart/byteranges` body of `206 Partial Content` response with the `Content-Type`.
+///
+/// Returns `None` if the body is not a complete `multipart/byteranges`, or a part has no valid
+/// `Content-Range`.
+pub fn parse_byteranges(content_type: &str, body: &[u8]) -> Option<Vec<BytePart>> {
+    let media = content_type.split(';').next().unwrap_or("").trim();
+    if !media.eq_ignore_ascii_case("multipart/byteranges") { return None }
+    let mut parser = match boundary(content_type) {
+        Some(boundary) => MultipartParser::new(boundary.as_slice()),
+        None => return None,
+    };
+    let mut handler = ByteRangesHandler {
+        parts: Vec::new(),
+        buffer: Vec::new(),
+        range: None,
+        content_type: None,
+        data: Vec::new(),
+        invalid: false,
+    };
+    if parser.parse(body, &mut handler).is_err() || !parser.is_finished() || handler.invalid {
+        return None;
+    }
+    Some(handler.parts)
+}
+
+struct ByteRangesHandler {
+    parts: Vec<BytePart>,
+    buffer: Vec<u8>,
+    range: Option<ContentRange>,
+    content_type: Option<String>,
+    data: Vec<u8>,
+    invalid: bool,
+}
+
+impl MultipartHandler for ByteRangesHandler {
+    fn on_part_begin(&mut self, _: &MultipartParser) {
+        self.range = None;
+        self.content_type = None;
+        self.data.clear();
+    }
+
+    fn on_header_value(&mut self, _: &MultipartParser, length: uint) {
+        let len = self.buffer.len();
+        let name = str::from_utf8(self.buffer.slice_to(len - length));
+        let value = str::from_utf8(self.buffer.slice_from(len - length));
+        match (name, value) {
+            (Some(name), Some(value)) => {
+                if name.eq_ignore_ascii_case("Content-Range") {
+                    self.range = ContentRange::parse(value);
+                } else if name.eq_ignore_ascii_case("Content-Type") {
+                    self.content_type = Some(value.trim().to_string());
+                }
+            }
+            _ => (),
+        }
+        self.buffer.clear();
+    }
+
+    fn on_part_data(&mut self, _: &MultipartParser, data: &[u8]) {
+        self.data.push_all(data);
+    }
+
+    fn on_part_end(&mut self, _: &MultipartParser) {
+        match self.range.take() {
+            Some(range) if range.range.is_some() => self.parts.push(BytePart {
+                range: range,
+                content_type: self.content_type.take(),
+                data: self.data.clone(),
+            }),
+            _ => self.invalid = true,
+        }
+    }
+
+    fn write(&mut self, _: &MultipartParser, data: &[u8]) {
+        self.buffer.push_all(data);
+    }
+}
+
+fn copy_range<R: Reader + Seek, W: Writer>(body: &mut R, first: uint, last: uint, w: &mut W) -> IoResult<()> {
+    try!(body.seek(first as i64, SeekSet));
+    let mut buf = Vec::from_elem(4096, 0u8);
+    let mut rest = last - first + 1;
+    while rest > 0 {
+        let len = min(rest, buf.len());
+        let n = try!(body.read(buf.as_mut_slice().mut_slice_to(len)));
+        try!(w.write(buf.slice_to(n)));
+        rest -= n;
+    }
+    Ok(())
+}
+
+fn invalid_ranges(desc: &'static str) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: None,
+    }
+}
+
+// `first-last` with digits only, where first <= last.
+fn split_range(s: &str) -> Option<(uint, uint)> {
+    let pos = match s.find('-') {
+        Some(pos) => pos,
+        None => return None,
+    };
+    match (number(s.slice_to(pos)), number(s.slice_from(pos + 1))) {
+        (Some(first), Some(last)) if first <= last => Some((first, last)),
+        _ => None,
+    }
+}
+
+fn number(s: &str) -> Option<uint> {
+    if s.len() == 0 || !s.chars().all(|c| c >= '0' && c <= '9') { return None }
+    from_str::<uint>(s)
+}
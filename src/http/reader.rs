@@ -0,0 +1,445 @@
+//! Blocking reader which yields parsed HTTP messages from a stream.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::cmp::min;
+use std::io::{IoError, IoResult, EndOfFile, InvalidInput, OtherIoError};
+use std::io;
+use std::mem;
+use std::slice::bytes::copy_memory;
+
+use http;
+use http::cookie::{SetCookie, parse_cookie};
+use http::parser::{Parser, ParseType, ParseError, MessageHandler, Http2Start};
+
+static READ_SIZE: uint = 4096;
+
+/// HTTP message head, i.e. start line and header fields.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct Message {
+    /// Request method.
+    pub method: Option<http::HttpMethod>,
+    /// Request URL.
+    pub url: Option<String>,
+    /// Response status code.
+    pub status: Option<http::StatusCode>,
+    /// HTTP version. `None` means HTTP/0.9 request, or a message of other protocol.
+    pub version: Option<http::HttpVersion>,
+    /// Request method of other protocol, see `Parser::with_protocol`.
+    pub protocol_method: Option<&'static str>,
+    /// Version of other protocol as a pair of major and minor version.
+    pub protocol_version: Option<(uint, uint)>,
+    /// Header fields in received order.
+    pub headers: Vec<(String, String)>,
+    /// Connection: keep-alive or Connection: close
+    pub keep_alive: bool,
+    /// Connection: upgrade
+    pub upgrade: bool,
+    /// Transfer-Encoding: chunked
+    pub chunked: bool,
+    /// HTTP/2 connection preface, or `Upgrade: h2c` request.
+    pub http2: Option<Http2Start>,
+}
+
+impl Message {
+    /// Create an empty `Message`.
+    pub fn new() -> Message {
+        Message {
+            method: None,
+            url: None,
+            status: None,
+            version: None,
+            protocol_method: None,
+            protocol_version: None,
+            headers: Vec::new(),
+            keep_alive: false,
+            upgrade: false,
+            chunked: false,
+            http2: None,
+        }
+    }
+
+    /// Find the first header value with the name. Header names are case-insensitive.
+    pub fn header<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.headers.iter()
+            .find(|&&(ref n, _)| n.as_slice().eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_slice())
+    }
+
+    /// Find all header values with the name.
+    pub fn headers_named<'a>(&'a self, name: &str) -> Vec<&'a str> {
+        self.headers.iter()
+            .filter(|&&(ref n, _)| n.as_slice().eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_slice())
+            .collect()
+    }
+
+    /// Parse `Cookie` headers into name-value pairs.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        let mut cookies = Vec::new();
+        for value in self.headers_named("Cookie").iter() {
+            cookies.push_all_move(parse_cookie(*value));
+        }
+        cookies
+    }
+
+    /// Find the first cookie value with the name in `Cookie` headers. Cookie names are case-sensitive.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().move_iter().find(|&(ref n, _)| n.as_slice() == name).map(|(_, v)| v)
+    }
+
+    /// Parse `Set-Cookie` headers. Invalid ones are ignored.
+    pub fn set_cookies(&self) -> Vec<SetCookie> {
+        self.headers_named("Set-Cookie").iter().filter_map(|value| SetCookie::parse(*value)).collect()
+    }
+}
+
+/// A list specifying categories of read errors.
+#[deriving(PartialEq, Clone, Show)]
+pub enum ReadError {
+    /// I/O error of the underlying stream. `EndOfFile` before a message means the stream closed.
+    ReadIoError(IoError),
+    /// Invalid message.
+    ReadParseError(ParseError),
+}
+
+/// Result of reading.
+pub type ReadResult<T> = Result<T, ReadError>;
+
+/// Blocking reader which drives `Parser` over a stream.
+///
+/// Bytes following a message are kept for the next message, so pipelined messages are read in order.
+/// Interleaved binary frames between messages of other protocols, e.g. `RTSP`, are skipped.
+pub struct MessageReader<R> {
+    reader: R,
+    parser: Parser,
+    collector: Collector,
+    buffer: Vec<u8>,
+    pos: uint,
+    closed: bool,
+    error: Option<ParseError>,
+}
+
+impl<R: Reader> MessageReader<R> {
+    /// Create a new `MessageReader`.
+    pub fn new(reader: R, t: ParseType) -> MessageReader<R> {
+        MessageReader::with_parser(reader, Parser::new(t))
+    }
+
+    /// Create a new `MessageReader` with a configured `Parser`, e.g. with size limits.
+    pub fn with_parser(reader: R, parser: Parser) -> MessageReader<R> {
+        MessageReader::with_buffer(reader, parser, Vec::with_capacity(READ_SIZE))
+    }
+
+    /// Create a new `MessageReader` with bytes already read from the stream, which are parsed first.
+    ///
+    /// Useful to pass bytes returned by `read_proxy_header` or `unwrap`.
+    pub fn with_buffer(reader: R, parser: Parser, buffer: Vec<u8>) -> MessageReader<R> {
+        MessageReader {
+            reader: reader,
+            parser: parser,
+            collector: Collector::new(),
+            buffer: buffer,
+            pos: 0,
+            closed: false,
+            error: None,
+        }
+    }
+
+    /// Read the next message head.
+    ///
+    /// Unread body of the previous message is skipped.
+    pub fn read_message(&mut self) -> ReadResult<Message> {
+        if self.collector.headers_complete && !self.collector.message_complete {
+            let mut buf = Vec::from_elem(READ_SIZE, 0u8);
+            loop {
+                match self.read_body(buf.as_mut_slice()) {
+                    Ok(_) => (),
+                    Err(ref e) if e.kind == EndOfFile => break,
+                    Err(e) => return Err(ReadIoError(e)),
+                }
+            }
+        }
+        self.collector.reset();
+        loop {
+            if self.pos < self.buffer.len() {
+                if try!(self.parse_buffered()) > 0 {
+                    if self.collector.headers_complete || self.collector.message_complete {
+                        return Ok(mem::replace(&mut self.collector.message, Message::new()));
+                    }
+                    continue;
+                }
+            }
+            match self.fill() {
+                Ok(_) => (),
+                Err(e) => {
+                    if e.kind == EndOfFile {
+                        // stream closed in the middle of message head.
+                        match self.parser.finish(&mut self.collector) {
+                            Ok(_) => (),
+                            Err(e) => {
+                                self.error = Some(e);
+                                return Err(ReadParseError(e));
+                            }
+                        }
+                    }
+                    return Err(ReadIoError(e));
+                }
+            }
+        }
+    }
+
+    /// Returns a reader of the current message body.
+    ///
+    /// Identity, chunked and connection-close delimited bodies are decoded on demand, and the reader
+    /// returns `EndOfFile` exactly at the end of the message.
+    pub fn body<'a>(&'a mut self) -> BodyReader<'a, R> {
+        BodyReader { reader: self }
+    }
+
+    /// Skip body of following messages, e.g. responses to HEAD requests.
+    pub fn set_skip_body(&mut self, skip: bool) {
+        self.collector.skip_body = skip;
+    }
+
+    /// Returns the parse error occurred while reading, if any.
+    ///
+    /// Useful to find the cause of an `InvalidInput` error returned by `BodyReader`.
+    pub fn error(&self) -> Option<ParseError> {
+        self.error
+    }
+
+    /// Returns true if the last message is read completely without error, and no extra bytes are
+    /// buffered, i.e. the stream can be reused for the next message.
+    ///
+    /// Returns false once the stream reached EOF, e.g. after a body delimited by closing the
+    /// connection, even if the message was persistent by its version.
+    pub fn is_reusable(&self) -> bool {
+        self.error.is_none() && !self.closed && self.collector.message_complete && self.pos == self.buffer.len()
+    }
+
+    /// Gets a reference to the underlying reader.
+    pub fn get_ref<'a>(&'a self) -> &'a R {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying reader.
+    pub fn get_mut<'a>(&'a mut self) -> &'a mut R {
+        &mut self.reader
+    }
+
+    /// Unwraps this `MessageReader`, returning the underlying reader and unparsed bytes.
+    ///
+    /// Useful to take over the connection after `Connection: upgrade` or HTTP/2 connection preface.
+    pub fn unwrap(self) -> (R, Vec<u8>) {
+        let rest = self.buffer.slice_from(self.pos).to_vec();
+        (self.reader, rest)
+    }
+
+    fn read_body(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        loop {
+            let available = self.collector.body.len() - self.collector.body_pos;
+            if available > 0 {
+                let n = min(buf.len(), available);
+                let start = self.collector.body_pos;
+                copy_memory(buf, self.collector.body.slice(start, start + n));
+                self.collector.body_pos += n;
+                if self.collector.body_pos == self.collector.body.len() {
+                    self.collector.body.clear();
+                    self.collector.body_pos = 0;
+                }
+                return Ok(n);
+            }
+            if !self.collector.headers_complete || self.collector.message_complete {
+                return Err(io::standard_error(EndOfFile));
+            }
+            if self.pos < self.buffer.len() {
+                match self.parse_buffered() {
+                    Ok(n) if n > 0 => continue,
+                    Ok(_) => (),
+                    Err(ReadParseError(e)) => return Err(IoError {
+                        kind: InvalidInput,
+                        desc: "invalid message body",
+                        detail: Some(format!("{}", e)),
+                    }),
+                    Err(ReadIoError(e)) => return Err(e),
+                }
+            }
+            match self.fill() {
+                Err(ref e) if e.kind == EndOfFile => {
+                    match self.parser.finish(&mut self.collector) {
+                        Ok(_) => continue,
+                        Err(e) => {
+                            self.error = Some(e);
+                            return Err(IoError {
+                                kind: OtherIoError,
+                                desc: "connection closed before message completed",
+                                detail: None,
+                            });
+                        }
+                    }
+                }
+                Err(e) => return Err(e),
+                Ok(_) => (),
+            }
+        }
+    }
+
+    fn parse_buffered(&mut self) -> ReadResult<uint> {
+        match self.parser.parse(self.buffer.slice_from(self.pos), &mut self.collector) {
+            Ok(n) => {
+                self.pos += n;
+                Ok(n)
+            }
+            Err(e) => {
+                self.error = Some(e);
+                Err(ReadParseError(e))
+            }
+        }
+    }
+
+    fn fill(&mut self) -> IoResult<uint> {
+        if self.pos > 0 {
+            let rest = self.buffer.len() - self.pos;
+            {
+                let buf = self.buffer.as_mut_slice();
+                for i in range(0, rest) {
+                    buf[i] = buf[self.pos + i];
+                }
+            }
+            self.buffer.truncate(rest);
+            self.pos = 0;
+        }
+        let len = self.buffer.len();
+        self.buffer.grow(READ_SIZE, &0u8);
+        match self.reader.read(self.buffer.as_mut_slice().mut_slice_from(len)) {
+            Ok(n) => {
+                self.buffer.truncate(len + n);
+                Ok(n)
+            }
+            Err(e) => {
+                if e.kind == EndOfFile { self.closed = true }
+                self.buffer.truncate(len);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Reader of a message body, returns `EndOfFile` at the end of the message.
+///
+/// Bytes of the following message are never consumed, so the next `read_message` starts at the
+/// message boundary.
+pub struct BodyReader<'a, R> {
+    reader: &'a mut MessageReader<R>,
+}
+
+impl<'a, R: Reader> Reader for BodyReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+        self.reader.read_body(buf)
+    }
+}
+
+struct Collector {
+    message: Message,
+    buffer: Vec<u8>,
+    body: Vec<u8>,
+    body_pos: uint,
+    skip_body: bool,
+    headers_complete: bool,
+    message_complete: bool,
+}
+
+impl Collector {
+    fn new() -> Collector {
+        Collector {
+            message: Message::new(),
+            buffer: Vec::new(),
+            body: Vec::new(),
+            body_pos: 0,
+            skip_body: false,
+            headers_complete: false,
+            message_complete: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.body.clear();
+        self.body_pos = 0;
+        self.headers_complete = false;
+        self.message_complete = false;
+    }
+
+    fn take_string(&mut self, length: uint) -> String {
+        let len = self.buffer.len();
+        let s = String::from_utf8_lossy(self.buffer.slice_from(len - length)).into_string();
+        self.buffer.truncate(len - length);
+        s
+    }
+}
+
+impl MessageHandler for Collector {
+    fn on_message_begin(&mut self, _: &Parser) {
+        self.message = Message::new();
+    }
+
+    fn on_method(&mut self, _: &Parser, method: http::HttpMethod) {
+        self.message.method = Some(method);
+    }
+
+    fn on_url(&mut self, _: &Parser, length: uint) {
+        self.message.url = Some(self.take_string(length));
+        self.buffer.clear();
+    }
+
+    fn on_version(&mut self, _: &Parser, version: http::HttpVersion) {
+        self.message.version = Some(version);
+    }
+
+    fn on_protocol_method(&mut self, _: &Parser, method: &'static str) {
+        self.message.protocol_method = Some(method);
+    }
+
+    fn on_protocol_version(&mut self, _: &Parser, major: uint, minor: uint) {
+        self.message.protocol_version = Some((major, minor));
+    }
+
+    fn on_status(&mut self, _: &Parser, status: http::StatusCode) {
+        self.message.status = Some(status);
+    }
+
+    fn on_header_value(&mut self, _: &Parser, length: uint) {
+        let value = self.take_string(length);
+        let name = String::from_utf8_lossy(self.buffer.as_slice()).into_string();
+        self.message.headers.push((name, value));
+        self.buffer.clear();
+    }
+
+    fn on_headers_complete(&mut self, parser: &Parser) -> bool {
+        self.message.keep_alive = parser.should_keep_alive();
+        self.message.upgrade = parser.should_upgrade();
+        self.message.chunked = parser.chunked();
+        self.message.http2 = parser.http2_start();
+        self.headers_complete = true;
+        self.skip_body
+    }
+
+    fn on_message_complete(&mut self, parser: &Parser) {
+        self.message.http2 = parser.http2_start();
+        self.message_complete = true;
+    }
+
+    fn on_interleaved_frame(&mut self, _: &Parser, _: u8, _: uint) {
+        self.buffer.clear();
+    }
+
+    fn write(&mut self, _: &Parser, data: &[u8]) {
+        if self.headers_complete {
+            self.body.push_all(data);
+        } else {
+            self.buffer.push_all(data);
+        }
+    }
+}
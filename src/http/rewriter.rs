@@ -0,0 +1,341 @@
+//! Message rewriter which re-serializes parsed messages while they are parsed.
+//!
+//! `Rewriter` is a `MessageHandler` which writes each message to a writer. Start line and header
+//! fields are buffered until the header section is complete, and altered by a `RewriteFilter`.
+//! Message body is written as it arrives.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::io::{IoError, InvalidInput};
+use std::mem;
+
+use http;
+use http::parser::{Parser, MessageHandler};
+use http::serializer::MessageWriter;
+
+/// Hooks to alter messages passing through `Rewriter`.
+pub trait RewriteFilter {
+    #[allow(unused_variable)]
+    /// Called with the request line before it is written.
+    fn request_line(&mut self, method: &mut http::HttpMethod, url: &mut String, version: &mut http::HttpVersion) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called with the status line before it is written.
+    fn status_line(&mut self, version: &mut http::HttpVersion, status: &mut http::StatusCode) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called with the request line of other protocol before it is written, see
+    /// `Parser::with_protocol`. Version is a pair of major and minor version.
+    fn protocol_request_line(&mut self, method: &mut &'static str, url: &mut String, version: &mut (uint, uint)) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called with the status line of other protocol before it is written.
+    fn protocol_status_line(&mut self, version: &mut (uint, uint), status: &mut http::StatusCode) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called with header fields in received order before they are written.
+    ///
+    /// Framing headers, i.e. `Content-Length` and `Transfer-Encoding`, are kept by default, so the
+    /// body is written in the same framing. If both are removed, the body is written with chunked
+    /// encoding for HTTP/1.1.
+    fn headers(&mut self, headers: &mut Vec<(String, String)>) {
+    }
+}
+
+/// A list of header rules applied in order, which adds, removes and renames header fields.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct HeaderRules {
+    rules: Vec<HeaderRule>,
+}
+
+impl HeaderRules {
+    /// Create an empty `HeaderRules`.
+    pub fn new() -> HeaderRules {
+        HeaderRules {
+            rules: Vec::new(),
+        }
+    }
+
+    /// Append a header field.
+    pub fn add(&mut self, name: &str, value: &str) {
+        self.rules.push(AddHeader(name.to_string(), value.to_string()));
+    }
+
+    /// Remove all header fields with the name. Header names are case-insensitive.
+    pub fn remove(&mut self, name: &str) {
+        self.rules.push(RemoveHeader(name.to_string()));
+    }
+
+    /// Rename all header fields with the name, keeping the values.
+    pub fn rename(&mut self, from: &str, to: &str) {
+        self.rules.push(RenameHeader(from.to_string(), to.to_string()));
+    }
+}
+
+impl RewriteFilter for HeaderRules {
+    fn headers(&mut self, headers: &mut Vec<(String, String)>) {
+        for rule in self.rules.iter() {
+            match *rule {
+                AddHeader(ref name, ref value) => headers.push((name.clone(), value.clone())),
+                RemoveHeader(ref name) => {
+                    headers.retain(|&(ref n, _)| !n.as_slice().eq_ignore_ascii_case(name.as_slice()));
+                }
+                RenameHeader(ref from, ref to) => {
+                    let renamed = headers.iter().map(|&(ref n, ref v)| {
+                        let name = if n.as_slice().eq_ignore_ascii_case(from.as_slice()) { to } else { n };
+                        (name.clone(), v.clone())
+                    }).collect();
+                    *headers = renamed;
+                }
+            }
+        }
+    }
+}
+
+/// `MessageHandler` which writes parsed messages to a writer with alterations.
+///
+/// Pipelined messages are written in order. Chunked body is decoded by `Parser`, and encoded
+/// again, so chunk sizes may differ and chunk extensions and trailer fields are dropped. Write
+/// errors are kept, and following messages are not written, see `error`.
+///
+/// Messages of other protocols, e.g. `RTSP`, are written with their start lines, and interleaved
+/// binary frames between messages are written as is.
+pub struct Rewriter<W, F> {
+    writer: Option<W>,
+    message: Option<MessageWriter<W>>,
+    filter: F,
+    buffer: Vec<u8>,
+    method: Option<http::HttpMethod>,
+    url: String,
+    version: Option<http::HttpVersion>,
+    protocol_method: Option<&'static str>,
+    protocol_version: Option<(&'static str, uint, uint)>,
+    status: Option<http::StatusCode>,
+    headers: Vec<(String, String)>,
+    skip_body: bool,
+    error: Option<IoError>,
+}
+
+impl<W: Writer, F: RewriteFilter> Rewriter<W, F> {
+    /// Create a new `Rewriter`.
+    pub fn new(writer: W, filter: F) -> Rewriter<W, F> {
+        Rewriter {
+            writer: Some(writer),
+            message: None,
+            filter: filter,
+            buffer: Vec::new(),
+            method: None,
+            url: String::new(),
+            version: None,
+            protocol_method: None,
+            protocol_version: None,
+            status: None,
+            headers: Vec::new(),
+            skip_body: false,
+            error: None,
+        }
+    }
+
+    /// Skip body of following messages, e.g. responses to HEAD requests.
+    pub fn set_skip_body(&mut self, skip: bool) {
+        self.skip_body = skip;
+    }
+
+    /// Gets a mutable reference to the filter.
+    pub fn filter_mut<'a>(&'a mut self) -> &'a mut F {
+        &mut self.filter
+    }
+
+    /// Returns the write error occurred while rewriting, if any.
+    pub fn error(&self) -> Option<IoError> {
+        self.error.clone()
+    }
+
+    /// Unwraps this `Rewriter`, returning the underlying writer.
+    ///
+    /// Returns `None` in the middle of a message, or after a write error.
+    pub fn unwrap(self) -> Option<W> {
+        self.writer
+    }
+
+    fn take_string(&mut self, length: uint) -> String {
+        let len = self.buffer.len();
+        let s = String::from_utf8_lossy(self.buffer.slice_from(len - length)).into_string();
+        self.buffer.truncate(len - length);
+        s
+    }
+
+    fn write_head(&mut self) -> Result<(), IoError> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let mut w = MessageWriter::new(writer);
+        let mut version = self.version.unwrap_or(http::HTTP_0_9);
+        match (self.protocol_version, self.protocol_method, self.method, self.status) {
+            (Some((name, major, minor)), Some(mut method), _, _) => {
+                let mut url = mem::replace(&mut self.url, String::new());
+                let mut version = (major, minor);
+                self.filter.protocol_request_line(&mut method, &mut url, &mut version);
+                let (major, minor) = version;
+                try!(w.protocol_request_line(method, url.as_slice(), name, major, minor));
+            }
+            (Some((name, major, minor)), None, _, Some(mut status)) => {
+                let mut version = (major, minor);
+                self.filter.protocol_status_line(&mut version, &mut status);
+                let (major, minor) = version;
+                try!(w.protocol_status_line(name, major, minor, status));
+            }
+            (None, _, Some(mut method), _) => {
+                let mut url = mem::replace(&mut self.url, String::new());
+                self.filter.request_line(&mut method, &mut url, &mut version);
+                try!(w.request_line(method, url.as_slice(), version));
+            }
+            (None, _, None, Some(mut status)) => {
+                self.filter.status_line(&mut version, &mut status);
+                try!(w.status_line(version, status));
+            }
+            _ => return Err(IoError {
+                kind: InvalidInput,
+                desc: "message has no start line",
+                detail: None,
+            }),
+        }
+        let mut headers = mem::replace(&mut self.headers, Vec::new());
+        self.filter.headers(&mut headers);
+        for &(ref name, ref value) in headers.iter() {
+            try!(w.header(name.as_slice(), value.as_slice()));
+        }
+        if self.skip_body { w.no_body() }
+        self.message = Some(w);
+        Ok(())
+    }
+
+    fn fail(&mut self, e: IoError) {
+        if self.error.is_none() { self.error = Some(e) }
+        self.writer = None;
+        self.message = None;
+    }
+}
+
+impl<W: Writer, F: RewriteFilter> MessageHandler for Rewriter<W, F> {
+    fn on_message_begin(&mut self, _: &Parser) {
+        self.buffer.clear();
+        self.method = None;
+        self.url = String::new();
+        self.version = None;
+        self.protocol_method = None;
+        self.protocol_version = None;
+        self.status = None;
+        self.headers.clear();
+    }
+
+    fn on_method(&mut self, _: &Parser, method: http::HttpMethod) {
+        self.method = Some(method);
+    }
+
+    fn on_url(&mut self, _: &Parser, length: uint) {
+        self.url = self.take_string(length);
+        self.buffer.clear();
+    }
+
+    fn on_version(&mut self, _: &Parser, version: http::HttpVersion) {
+        self.version = Some(version);
+    }
+
+    fn on_protocol_method(&mut self, _: &Parser, method: &'static str) {
+        self.protocol_method = Some(method);
+    }
+
+    fn on_protocol_version(&mut self, parser: &Parser, major: uint, minor: uint) {
+        match parser.protocol() {
+            Some(protocol) => self.protocol_version = Some((protocol.name, major, minor)),
+            None => (),
+        }
+    }
+
+    fn on_status(&mut self, _: &Parser, status: http::StatusCode) {
+        self.status = Some(status);
+    }
+
+    fn on_header_value(&mut self, _: &Parser, length: uint) {
+        let value = self.take_string(length);
+        let name = String::from_utf8_lossy(self.buffer.as_slice()).into_string();
+        self.headers.push((name, value));
+        self.buffer.clear();
+    }
+
+    fn on_headers_complete(&mut self, _: &Parser) -> bool {
+        match self.write_head() {
+            Ok(()) => (),
+            Err(e) => self.fail(e),
+        }
+        self.skip_body
+    }
+
+    fn on_message_complete(&mut self, _: &Parser) {
+        // HTTP/0.9 request and HTTP/2 connection preface have no header section.
+        if self.message.is_none() && self.writer.is_some() {
+            match self.write_head() {
+                Ok(()) => (),
+                Err(e) => return self.fail(e),
+            }
+        }
+        match self.message.take() {
+            Some(w) => match w.finish() {
+                Ok(writer) => self.writer = Some(writer),
+                Err(e) => self.fail(e),
+            },
+            None => (),
+        }
+    }
+
+    fn on_interleaved_frame(&mut self, _: &Parser, channel: u8, length: uint) {
+        if self.error.is_some() { return }
+        let start = self.buffer.len() - length;
+        let result = match self.writer {
+            Some(ref mut w) => {
+                let header = [DOLLAR, channel, (length >> 8) as u8, length as u8];
+                match w.write(header) {
+                    Ok(()) => w.write(self.buffer.slice_from(start)),
+                    Err(e) => Err(e),
+                }
+            }
+            None => Ok(()),
+        };
+        self.buffer.clear();
+        match result {
+            Ok(()) => (),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn write(&mut self, _: &Parser, data: &[u8]) {
+        if self.error.is_some() { return }
+        let result = match self.message {
+            Some(ref mut w) => w.write(data),
+            None => {
+                self.buffer.push_all(data);
+                return;
+            }
+        };
+        match result {
+            Ok(()) => (),
+            Err(e) => self.fail(e),
+        }
+    }
+}
+
+static DOLLAR: u8 = 0x24;
+
+#[deriving(PartialEq, Eq, Clone, Show)]
+enum HeaderRule {
+    AddHeader(String, String),
+    RemoveHeader(String),
+    RenameHeader(String, String),
+}
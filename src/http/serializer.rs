@@ -0,0 +1,397 @@
+//! HTTP/1.x message serializer.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::io::{IoError, IoResult, InvalidInput, OtherIoError};
+
+use http;
+use http::chunked;
+use http::encoding::{ContentCoding, CodingGzip, CodingDeflate, Encoder, encode};
+
+/// HTTP request/response writer.
+///
+/// Header names and values are validated with the same rules as `Parser`.
+/// Unless `Content-Length` or `Transfer-Encoding: chunked` is written explicitly, message body is
+/// framed automatically:
+///
+/// - `body` writes `Content-Length`.
+/// - Streaming via `Writer` uses chunked encoding for HTTP/1.1, or is delimited by closing the
+///   connection for HTTP/1.0 responses.
+///
+/// With `content_coding`, body is compressed and framed the same way with the compressed length.
+pub struct MessageWriter<W> {
+    writer: W,
+    state: WriterState,
+    version: http::HttpVersion,
+    protocol: bool,
+    response: bool,
+    has_body: bool,
+    content_length: Option<uint>,
+    chunked: bool,
+    framing: BodyFraming,
+    rest: uint,
+    coding: Option<ContentCoding>,
+    encoder: Option<Encoder>,
+}
+
+impl<W: Writer> MessageWriter<W> {
+    /// Create a new `MessageWriter`.
+    pub fn new(writer: W) -> MessageWriter<W> {
+        MessageWriter {
+            writer: writer,
+            state: WriteStartLine,
+            version: http::HTTP_1_1,
+            protocol: false,
+            response: false,
+            has_body: true,
+            content_length: None,
+            chunked: false,
+            framing: FrameNone,
+            rest: 0,
+            coding: None,
+            encoder: None,
+        }
+    }
+
+    /// Write request line.
+    pub fn request_line(&mut self, method: http::HttpMethod, url: &str, version: http::HttpVersion) -> IoResult<()> {
+        try!(self.check_state(WriteStartLine));
+        if version == http::HTTP_0_9 { return Err(invalid_input("HTTP/0.9 is not supported", None)) }
+        if url.len() == 0 || url.as_bytes().iter().any(|&b| b == SPACE || b == CR || b == LF) {
+            return Err(invalid_input("invalid request URL", Some(url)));
+        }
+        try!(write!(self.writer, "{} {} {}\r\n", method, url, version));
+        self.version = version;
+        self.response = false;
+        self.state = WriteHeaders;
+        Ok(())
+    }
+
+    /// Write status line.
+    ///
+    /// Responses with 1xx, 204 or 304 status have no body.
+    pub fn status_line(&mut self, version: http::HttpVersion, status: http::StatusCode) -> IoResult<()> {
+        try!(self.check_state(WriteStartLine));
+        if version == http::HTTP_0_9 { return Err(invalid_input("HTTP/0.9 is not supported", None)) }
+        if status.code() < 100 || status.code() > 999 {
+            return Err(invalid_input("invalid status code", None));
+        }
+        try!(write!(self.writer, "{} {} {}\r\n",
+                    version, status.code(), status.canonical_reason().unwrap_or("")));
+        self.version = version;
+        self.response = true;
+        self.has_body = !(status.is_informational() ||
+                          status == http::status::NO_CONTENT ||
+                          status == http::status::NOT_MODIFIED);
+        self.state = WriteHeaders;
+        Ok(())
+    }
+
+    /// Write request line of other protocol, e.g. `RTSP`, see `Parser::with_protocol`.
+    ///
+    /// Streamed body is not framed automatically, so `Content-Length` must be written before.
+    pub fn protocol_request_line(&mut self, method: &str, url: &str,
+                                 protocol: &str, major: uint, minor: uint) -> IoResult<()> {
+        try!(self.check_state(WriteStartLine));
+        if method.len() == 0 || !method.as_bytes().iter().all(|&b| http::is_field_name_char(b)) {
+            return Err(invalid_input("invalid request method", Some(method)));
+        }
+        if url.len() == 0 || url.as_bytes().iter().any(|&b| b == SPACE || b == CR || b == LF) {
+            return Err(invalid_input("invalid request URL", Some(url)));
+        }
+        try!(check_protocol(protocol));
+        try!(write!(self.writer, "{} {} {}/{}.{}\r\n", method, url, protocol, major, minor));
+        self.protocol = true;
+        self.response = false;
+        self.state = WriteHeaders;
+        Ok(())
+    }
+
+    /// Write status line of other protocol, e.g. `RTSP`.
+    ///
+    /// Responses with 1xx, 204 or 304 status have no body, the same as HTTP.
+    pub fn protocol_status_line(&mut self, protocol: &str, major: uint, minor: uint,
+                                status: http::StatusCode) -> IoResult<()> {
+        try!(self.check_state(WriteStartLine));
+        try!(check_protocol(protocol));
+        if status.code() < 100 || status.code() > 999 {
+            return Err(invalid_input("invalid status code", None));
+        }
+        try!(write!(self.writer, "{}/{}.{} {} {}\r\n",
+                    protocol, major, minor, status.code(), status.canonical_reason().unwrap_or("")));
+        self.protocol = true;
+        self.response = true;
+        self.has_body = !(status.is_informational() ||
+                          status == http::status::NO_CONTENT ||
+                          status == http::status::NOT_MODIFIED);
+        self.state = WriteHeaders;
+        Ok(())
+    }
+
+    /// Write a header field.
+    pub fn header(&mut self, name: &str, value: &str) -> IoResult<()> {
+        try!(self.check_state(WriteHeaders));
+        if name.len() == 0 || !name.as_bytes().iter().all(|&b| http::is_field_name_char(b)) {
+            return Err(invalid_input("invalid header name", Some(name)));
+        }
+        if !value.as_bytes().iter().all(|&b| http::is_field_value_char(b)) {
+            return Err(invalid_input("invalid header value", Some(value)));
+        }
+        if name.eq_ignore_ascii_case("Content-Length") {
+            let length = match from_str::<uint>(value.trim()) {
+                Some(length) => length,
+                None => return Err(invalid_input("invalid Content-Length", Some(value))),
+            };
+            if self.coding.is_some() {
+                return Err(invalid_input("Content-Length is given for compressed body", Some(value)));
+            }
+            match self.content_length {
+                Some(n) if n != length => return Err(invalid_input("conflicting Content-Length", Some(value))),
+                _ => (),
+            }
+            self.content_length = Some(length);
+        } else if name.eq_ignore_ascii_case("Transfer-Encoding") {
+            self.chunked = match value.split(',').last() {
+                Some(coding) => coding.trim().eq_ignore_ascii_case("chunked"),
+                None => false,
+            };
+        }
+        if self.chunked && self.content_length.is_some() {
+            return Err(invalid_input("both Content-Length and Transfer-Encoding are given", None));
+        }
+        write!(self.writer, "{}: {}\r\n", name, value)
+    }
+
+    /// Compress the body with the content coding, and write `Content-Encoding` and `Vary` headers.
+    ///
+    /// Only `gzip` and `deflate` are supported, and `Content-Length` must not be written. Streamed
+    /// body is compressed as it is written, and `flush` sends all compressed data written so far.
+    pub fn content_coding(&mut self, coding: ContentCoding) -> IoResult<()> {
+        try!(self.check_state(WriteHeaders));
+        match coding {
+            CodingGzip | CodingDeflate => (),
+            _ => return Err(invalid_input("unsupported content coding", Some(coding.name()))),
+        }
+        if self.coding.is_some() { return Err(invalid_input("content coding is already set", None)) }
+        if self.content_length.is_some() {
+            return Err(invalid_input("Content-Length is given for compressed body", None));
+        }
+        try!(self.header("Content-Encoding", coding.name()));
+        try!(self.header("Vary", "Accept-Encoding"));
+        self.encoder = Encoder::new(coding).ok();
+        self.coding = Some(coding);
+        Ok(())
+    }
+
+    /// Declare the message has no body, e.g. a response to HEAD request.
+    ///
+    /// Headers are written as is, and writing body is not allowed.
+    pub fn no_body(&mut self) {
+        self.has_body = false;
+    }
+
+    /// Finish header section, and start streaming body.
+    pub fn start_body(&mut self) -> IoResult<()> {
+        try!(self.check_state(WriteHeaders));
+        self.end_headers(None)
+    }
+
+    /// Write whole body, and finish the message.
+    pub fn body(mut self, data: &[u8]) -> IoResult<W> {
+        if self.state == WriteHeaders {
+            if self.compresses() {
+                let encoded = try!(self.encode(data));
+                self.encoder = None;
+                try!(self.end_headers(Some(encoded.len())));
+                try!(self.write_body(encoded.as_slice()));
+                return self.finish();
+            }
+            try!(self.end_headers(Some(data.len())));
+        }
+        try!(self.write(data));
+        self.finish()
+    }
+
+    /// Finish the message, and returns the underlying writer.
+    pub fn finish(self) -> IoResult<W> {
+        self.finish_with_trailers([])
+    }
+
+    /// Finish the message with trailer fields, and returns the underlying writer.
+    ///
+    /// Trailer fields are written only if the body is chunked.
+    pub fn finish_with_trailers(mut self, trailers: &[(&str, &str)]) -> IoResult<W> {
+        match self.state {
+            WriteStartLine | WriteDone => return Err(other_error("message is not started")),
+            WriteHeaders => {
+                // compressed length of empty body is unknown until encoded.
+                let length = if self.compresses() { None } else { Some(0) };
+                try!(self.end_headers(length));
+            }
+            WriteBody => (),
+        }
+        if self.compresses() {
+            let mut encoded = Vec::new();
+            self.encoder.take().unwrap().finish(&mut encoded);
+            try!(self.write_body(encoded.as_slice()));
+        }
+        match self.framing {
+            FrameChunked => try!(chunked::write_last_chunk(&mut self.writer, trailers)),
+            FrameLength if self.rest > 0 => return Err(other_error("body is shorter than Content-Length")),
+            _ => (),
+        }
+        self.state = WriteDone;
+        try!(self.writer.flush());
+        Ok(self.writer)
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref<'a>(&'a self) -> &'a W {
+        &self.writer
+    }
+
+    fn end_headers(&mut self, length: Option<uint>) -> IoResult<()> {
+        self.framing = if !self.has_body {
+            FrameNone
+        } else if self.chunked {
+            FrameChunked
+        } else {
+            match (self.content_length, length) {
+                (Some(n), _) => {
+                    self.rest = n;
+                    FrameLength
+                }
+                (None, Some(n)) => {
+                    if n > 0 || self.response {
+                        try!(write!(self.writer, "Content-Length: {}\r\n", n));
+                    }
+                    self.rest = n;
+                    FrameLength
+                }
+                (None, None) if self.protocol => {
+                    return Err(invalid_input("streamed body of other protocol requires Content-Length", None))
+                }
+                (None, None) if self.version == http::HTTP_1_1 => {
+                    try!(self.writer.write("Transfer-Encoding: chunked\r\n".as_bytes()));
+                    FrameChunked
+                }
+                (None, None) if self.response => FrameEof,
+                (None, None) => return Err(invalid_input("HTTP/1.0 request body requires Content-Length", None)),
+            }
+        };
+        try!(self.writer.write("\r\n".as_bytes()));
+        self.state = WriteBody;
+        Ok(())
+    }
+
+    fn compresses(&self) -> bool {
+        self.encoder.is_some() && self.has_body
+    }
+
+    fn encode(&self, data: &[u8]) -> IoResult<Vec<u8>> {
+        match self.coding {
+            Some(coding) => encode(coding, data).map_err(|_| other_error("failed to compress body")),
+            None => Ok(data.to_vec()),
+        }
+    }
+
+    fn write_body(&mut self, buf: &[u8]) -> IoResult<()> {
+        if buf.len() == 0 { return Ok(()) }
+        match self.framing {
+            FrameNone => Err(other_error("message has no body")),
+            FrameLength => {
+                if buf.len() > self.rest { return Err(other_error("body exceeds Content-Length")) }
+                self.rest -= buf.len();
+                self.writer.write(buf)
+            }
+            FrameChunked => chunked::write_chunk(&mut self.writer, buf, []),
+            FrameEof => self.writer.write(buf),
+        }
+    }
+
+    fn check_state(&self, state: WriterState) -> IoResult<()> {
+        if self.state == state { return Ok(()) }
+        Err(other_error(match self.state {
+            WriteStartLine => "start line is not written",
+            WriteHeaders => "start line is already written",
+            WriteBody => "headers are already written",
+            WriteDone => "message is already finished",
+        }))
+    }
+}
+
+impl<W: Writer> Writer for MessageWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        match self.state {
+            WriteHeaders => try!(self.end_headers(None)),
+            WriteBody => (),
+            _ => try!(self.check_state(WriteBody)),
+        }
+        if self.compresses() {
+            let mut encoded = Vec::new();
+            match self.encoder {
+                Some(ref mut encoder) => encoder.feed(buf, &mut encoded),
+                None => (),
+            }
+            return self.write_body(encoded.as_slice());
+        }
+        self.write_body(buf)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        if self.state == WriteBody && self.compresses() {
+            let mut encoded = Vec::new();
+            match self.encoder {
+                Some(ref mut encoder) => encoder.flush(&mut encoded),
+                None => (),
+            }
+            try!(self.write_body(encoded.as_slice()));
+        }
+        self.writer.flush()
+    }
+}
+
+static LF: u8    = 0x0a;
+static CR: u8    = 0x0d;
+static SPACE: u8 = 0x20;
+static SLASH: u8 = 0x2f;
+
+fn check_protocol(protocol: &str) -> IoResult<()> {
+    if protocol.len() == 0 || !protocol.as_bytes().iter().all(|&b| http::is_field_name_char(b) && b != SLASH) {
+        return Err(invalid_input("invalid protocol name", Some(protocol)));
+    }
+    Ok(())
+}
+
+fn invalid_input(desc: &'static str, detail: Option<&str>) -> IoError {
+    IoError {
+        kind: InvalidInput,
+        desc: desc,
+        detail: detail.map(|s| s.to_string()),
+    }
+}
+
+fn other_error(desc: &'static str) -> IoError {
+    IoError {
+        kind: OtherIoError,
+        desc: desc,
+        detail: None,
+    }
+}
+
+#[deriving(PartialEq, Eq, Clone, Show)]
+enum WriterState {
+    WriteStartLine,
+    WriteHeaders,
+    WriteBody,
+    WriteDone,
+}
+
+#[deriving(PartialEq, Eq, Clone, Show)]
+enum BodyFraming {
+    FrameNone,
+    FrameLength,
+    FrameChunked,
+    FrameEof,
+}
@@ -0,0 +1,188 @@
+//! HTTP status codes.
+
+#![experimental]
+#![allow(missing_doc)]
+
+use std::fmt::{Formatter, FormatError, Show};
+
+/// HTTP status code.
+#[deriving(PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+pub struct StatusCode(pub uint);
+
+impl StatusCode {
+    #[inline]
+    /// Returns the status code as a number.
+    pub fn code(&self) -> uint {
+        let StatusCode(code) = *self;
+        code
+    }
+
+    /// Returns the reason phrase registered with IANA, if any.
+    pub fn canonical_reason(&self) -> Option<&'static str> {
+        Some(match self.code() {
+            100 => "Continue",
+            101 => "Switching Protocols",
+            102 => "Processing",
+            103 => "Early Hints",
+            200 => "OK",
+            201 => "Created",
+            202 => "Accepted",
+            203 => "Non-Authoritative Information",
+            204 => "No Content",
+            205 => "Reset Content",
+            206 => "Partial Content",
+            207 => "Multi-Status",
+            208 => "Already Reported",
+            226 => "IM Used",
+            300 => "Multiple Choices",
+            301 => "Moved Permanently",
+            302 => "Found",
+            303 => "See Other",
+            304 => "Not Modified",
+            305 => "Use Proxy",
+            307 => "Temporary Redirect",
+            308 => "Permanent Redirect",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            402 => "Payment Required",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            406 => "Not Acceptable",
+            407 => "Proxy Authentication Required",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            410 => "Gone",
+            411 => "Length Required",
+            412 => "Precondition Failed",
+            413 => "Content Too Large",
+            414 => "URI Too Long",
+            415 => "Unsupported Media Type",
+            416 => "Range Not Satisfiable",
+            417 => "Expectation Failed",
+            421 => "Misdirected Request",
+            422 => "Unprocessable Content",
+            423 => "Locked",
+            424 => "Failed Dependency",
+            425 => "Too Early",
+            426 => "Upgrade Required",
+            428 => "Precondition Required",
+            429 => "Too Many Requests",
+            431 => "Request Header Fields Too Large",
+            451 => "Unavailable For Legal Reasons",
+            500 => "Internal Server Error",
+            501 => "Not Implemented",
+            502 => "Bad Gateway",
+            503 => "Service Unavailable",
+            504 => "Gateway Timeout",
+            505 => "HTTP Version Not Supported",
+            506 => "Variant Also Negotiates",
+            507 => "Insufficient Storage",
+            508 => "Loop Detected",
+            510 => "Not Extended",
+            511 => "Network Authentication Required",
+            _ => return None,
+        })
+    }
+
+    #[inline]
+    /// 1xx
+    pub fn is_informational(&self) -> bool {
+        self.code() / 100 == 1
+    }
+
+    #[inline]
+    /// 2xx
+    pub fn is_success(&self) -> bool {
+        self.code() / 100 == 2
+    }
+
+    #[inline]
+    /// 3xx
+    pub fn is_redirection(&self) -> bool {
+        self.code() / 100 == 3
+    }
+
+    #[inline]
+    /// 4xx
+    pub fn is_client_error(&self) -> bool {
+        self.code() / 100 == 4
+    }
+
+    #[inline]
+    /// 5xx
+    pub fn is_server_error(&self) -> bool {
+        self.code() / 100 == 5
+    }
+}
+
+impl Show for StatusCode {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), FormatError> {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.code(), reason),
+            None => write!(f, "{}", self.code()),
+        }
+    }
+}
+
+pub static CONTINUE: StatusCode                        = StatusCode(100);
+pub static SWITCHING_PROTOCOLS: StatusCode             = StatusCode(101);
+pub static PROCESSING: StatusCode                      = StatusCode(102);
+pub static EARLY_HINTS: StatusCode                     = StatusCode(103);
+pub static OK: StatusCode                              = StatusCode(200);
+pub static CREATED: StatusCode                         = StatusCode(201);
+pub static ACCEPTED: StatusCode                        = StatusCode(202);
+pub static NON_AUTHORITATIVE_INFORMATION: StatusCode   = StatusCode(203);
+pub static NO_CONTENT: StatusCode                      = StatusCode(204);
+pub static RESET_CONTENT: StatusCode                   = StatusCode(205);
+pub static PARTIAL_CONTENT: StatusCode                 = StatusCode(206);
+pub static MULTI_STATUS: StatusCode                    = StatusCode(207);
+pub static ALREADY_REPORTED: StatusCode                = StatusCode(208);
+pub static IM_USED: StatusCode                         = StatusCode(226);
+pub static MULTIPLE_CHOICES: StatusCode                = StatusCode(300);
+pub static MOVED_PERMANENTLY: StatusCode               = StatusCode(301);
+pub static FOUND: StatusCode                           = StatusCode(302);
+pub static SEE_OTHER: StatusCode                       = StatusCode(303);
+pub static NOT_MODIFIED: StatusCode                    = StatusCode(304);
+pub static USE_PROXY: StatusCode                       = StatusCode(305);
+pub static TEMPORARY_REDIRECT: StatusCode              = StatusCode(307);
+pub static PERMANENT_REDIRECT: StatusCode              = StatusCode(308);
+pub static BAD_REQUEST: StatusCode                     = StatusCode(400);
+pub static UNAUTHORIZED: StatusCode                    = StatusCode(401);
+pub static PAYMENT_REQUIRED: StatusCode                = StatusCode(402);
+pub static FORBIDDEN: StatusCode                       = StatusCode(403);
+pub static NOT_FOUND: StatusCode                       = StatusCode(404);
+pub static METHOD_NOT_ALLOWED: StatusCode              = StatusCode(405);
+pub static NOT_ACCEPTABLE: StatusCode                  = StatusCode(406);
+pub static PROXY_AUTHENTICATION_REQUIRED: StatusCode   = StatusCode(407);
+pub static REQUEST_TIMEOUT: StatusCode                 = StatusCode(408);
+pub static CONFLICT: StatusCode                        = StatusCode(409);
+pub static GONE: StatusCode                            = StatusCode(410);
+pub static LENGTH_REQUIRED: StatusCode                 = StatusCode(411);
+pub static PRECONDITION_FAILED: StatusCode             = StatusCode(412);
+pub static CONTENT_TOO_LARGE: StatusCode               = StatusCode(413);
+pub static URI_TOO_LONG: StatusCode                    = StatusCode(414);
+pub static UNSUPPORTED_MEDIA_TYPE: StatusCode          = StatusCode(415);
+pub static RANGE_NOT_SATISFIABLE: StatusCode           = StatusCode(416);
+pub static EXPECTATION_FAILED: StatusCode              = StatusCode(417);
+pub static MISDIRECTED_REQUEST: StatusCode             = StatusCode(421);
+pub static UNPROCESSABLE_CONTENT: StatusCode           = StatusCode(422);
+pub static LOCKED: StatusCode                          = StatusCode(423);
+pub static FAILED_DEPENDENCY: StatusCode               = StatusCode(424);
+pub static TOO_EARLY: StatusCode                       = StatusCode(425);
+pub static UPGRADE_REQUIRED: StatusCode                = StatusCode(426);
+pub static PRECONDITION_REQUIRED: StatusCode           = StatusCode(428);
+pub static TOO_MANY_REQUESTS: StatusCode               = StatusCode(429);
+pub static REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode = StatusCode(431);
+pub static UNAVAILABLE_FOR_LEGAL_REASONS: StatusCode   = StatusCode(451);
+pub static INTERNAL_SERVER_ERROR: StatusCode           = StatusCode(500);
+pub static NOT_IMPLEMENTED: StatusCode                 = StatusCode(501);
+pub static BAD_GATEWAY: StatusCode                     = StatusCode(502);
+pub static SERVICE_UNAVAILABLE: StatusCode             = StatusCode(503);
+pub static GATEWAY_TIMEOUT: StatusCode                 = StatusCode(504);
+pub static HTTP_VERSION_NOT_SUPPORTED: StatusCode      = StatusCode(505);
+pub static VARIANT_ALSO_NEGOTIATES: StatusCode         = StatusCode(506);
+pub static INSUFFICIENT_STORAGE: StatusCode            = StatusCode(507);
+pub static LOOP_DETECTED: StatusCode                   = StatusCode(508);
+pub static NOT_EXTENDED: StatusCode                    = StatusCode(510);
+pub static NETWORK_AUTHENTICATION_REQUIRED: StatusCode = StatusCode(511);
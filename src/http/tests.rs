@@ -19,6 +19,381 @@ fn bench_no_message(b: &mut Bencher) {
     b.iter(|| Parser::new(ParseRequest).parse([], &mut BenchHandler) );
 }
 
+mod encoding {
+    use flate;
+    use http::encoding::*;
+    use http::parser::*;
+    use http::rewriter::{Rewriter, HeaderRules};
+    use std::io::MemWriter;
+    use super::TestHandler;
+
+    static GZIP_HELLO: [u8, ..38] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7,
+        0x51, 0xf0, 0x08, 0x09, 0x09, 0x50, 0x28, 0xcf, 0x2f, 0xca, 0x49, 0x51, 0x04, 0x00, 0x31, 0x37,
+        0x8f, 0x96, 0x12, 0x00, 0x00, 0x00];
+
+    static ZLIB_HELLO: [u8, ..26] = [
+        0x78, 0x9c, 0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0xd7, 0x51, 0xf0, 0x08, 0x09, 0x09, 0x50, 0x28, 0xcf,
+        0x2f, 0xca, 0x49, 0x51, 0x04, 0x00, 0x38, 0x57, 0x05, 0xea];
+
+    // dynamic Huffman codes
+    static ZLIB_FOX: [u8, ..155] = [
+        0x78, 0xda, 0x95, 0xd4, 0xc9, 0x11, 0xc2, 0x50, 0x0c, 0x04, 0xd1, 0x3b, 0x51, 0x28, 0x04, 0x6b,
+        0x06, 0x30, 0x90, 0x0d, 0x8b, 0xc1, 0xac, 0x1f, 0x1b, 0xcc, 0x16, 0xbd, 0xab, 0xc8, 0xa0, 0xcf,
+        0xea, 0xdb, 0xab, 0x51, 0xb5, 0x8a, 0x67, 0xdb, 0x44, 0x37, 0x1c, 0xb7, 0xe7, 0xd8, 0xf4, 0xe5,
+        0x7d, 0x8b, 0x7d, 0xf9, 0xc4, 0x69, 0xb8, 0xde, 0x1f, 0x51, 0x5e, 0x4d, 0xff, 0x3f, 0x5f, 0xd6,
+        0xbf, 0x6f, 0xec, 0xca, 0x61, 0x92, 0x2c, 0x17, 0xcb, 0xcd, 0xf2, 0x29, 0xcb, 0x67, 0x2c, 0x9f,
+        0xb3, 0xbc, 0x66, 0xf9, 0x82, 0xe5, 0x4b, 0xc8, 0x54, 0xc1, 0x1e, 0xba, 0x26, 0x84, 0x4d, 0x28,
+        0x9b, 0x90, 0x36, 0xa1, 0x6d, 0x42, 0xdc, 0x84, 0xba, 0x09, 0x79, 0x13, 0xfa, 0x0a, 0xfa, 0x8a,
+        0xee, 0x16, 0xfa, 0x0a, 0xfa, 0x0a, 0xfa, 0x0a, 0xfa, 0x0a, 0xfa, 0x0a, 0xfa, 0x0a, 0xfa, 0x0a,
+        0xfa, 0x1a, 0xfa, 0x1a, 0xfa, 0x9a, 0x3e, 0x66, 0xe8, 0x6b, 0xe8, 0x6b, 0xe8, 0x6b, 0xe8, 0x6b,
+        0xe8, 0x6b, 0xe8, 0x6b, 0xe8, 0x3b, 0x02, 0x20, 0x36, 0x9c, 0xb7];
+
+    fn fox() -> Vec<u8> {
+        let mut text = Vec::new();
+        for i in range(0u, 40) {
+            text.push_all(format!("{}: the quick brown fox jumps over the lazy dog\n", i).as_bytes());
+        }
+        text
+    }
+
+    #[test]
+    fn test_decode() {
+        let hello = "Hello, HTTP world!".as_bytes().to_vec();
+        assert_eq!(decode(CodingGzip, GZIP_HELLO, 1024), Ok(hello.clone()));
+        assert_eq!(decode(CodingDeflate, ZLIB_HELLO, 1024), Ok(hello.clone()));
+        // raw deflate without zlib header
+        assert_eq!(decode(CodingDeflate, ZLIB_HELLO.slice(2, ZLIB_HELLO.len() - 4), 1024), Ok(hello.clone()));
+        assert_eq!(decode(CodingDeflate, ZLIB_FOX, 4096), Ok(fox()));
+        // multiple gzip members
+        let mut members = GZIP_HELLO.to_vec();
+        members.push_all(GZIP_HELLO);
+        assert_eq!(decode(CodingGzip, members.as_slice(), 1024), Ok("Hello, HTTP world!Hello, HTTP world!".as_bytes().to_vec()));
+        // stored block
+        assert_eq!(decode(CodingDeflate, [0x01, 0x03, 0x00, 0xfc, 0xff, 0x62, 0x65, 0x65], 1024), Ok("bee".as_bytes().to_vec()));
+        assert_eq!(decode(CodingBrotli, [0x0b, 0x01], 1024), Err(UnsupportedCoding));
+        assert_eq!(decode(CodingOther, [0x0b, 0x01], 1024), Err(UnsupportedCoding));
+    }
+
+    #[test]
+    fn test_decoder() {
+        let mut decoder = Decoder::new(CodingDeflate).unwrap();
+        let mut out = Vec::new();
+        for byte in ZLIB_FOX.chunks(1) {
+            decoder.feed(byte, &mut out).unwrap();
+        }
+        assert_eq!(decoder.finish(), Ok(()));
+        assert_eq!(decoder.decoded(), out.len());
+        assert_eq!(out, fox());
+
+        let mut decoder = Decoder::new(CodingGzip).unwrap();
+        let mut out = Vec::new();
+        decoder.feed(GZIP_HELLO.slice_to(30), &mut out).unwrap();
+        assert_eq!(out, "Hello, HTTP world!".as_bytes().to_vec());
+        assert_eq!(decoder.finish(), Err(InvalidCompressedData));
+        assert!(Decoder::new(CodingBrotli).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let mut broken = GZIP_HELLO.to_vec();
+        broken.as_mut_slice()[30] ^= 0xff;
+        assert_eq!(decode(CodingGzip, broken.as_slice(), 1024), Err(ChecksumMismatch));
+        assert_eq!(decode(CodingGzip, GZIP_HELLO.slice_to(20), 1024), Err(InvalidCompressedData));
+        assert_eq!(decode(CodingGzip, ZLIB_HELLO, 1024), Err(InvalidCompressedData));
+        assert_eq!(decode(CodingDeflate, ZLIB_FOX, 1024), Err(SizeLimitExceeded));
+    }
+
+    #[test]
+    fn test_content_coding() {
+        assert_eq!(ContentCoding::from_header("gzip"), CodingGzip);
+        assert_eq!(ContentCoding::from_header("X-GZIP"), CodingGzip);
+        assert_eq!(ContentCoding::from_header("identity, deflate"), CodingDeflate);
+        assert_eq!(ContentCoding::from_header("br"), CodingBrotli);
+        assert_eq!(ContentCoding::from_header("deflate, gzip"), CodingOther);
+        assert_eq!(ContentCoding::from_header(""), CodingIdentity);
+    }
+
+    #[test]
+    fn test_encode() {
+        let text = fox();
+        for &coding in [CodingGzip, CodingDeflate, CodingIdentity].iter() {
+            let encoded = encode(coding, text.as_slice()).unwrap();
+            assert_eq!(decode(coding, encoded.as_slice(), 4096), Ok(text.clone()));
+        }
+        assert!(encode(CodingGzip, text.as_slice()).unwrap().len() < text.len());
+        assert_eq!(decode(CodingGzip, encode(CodingGzip, []).unwrap().as_slice(), 0), Ok(vec!()));
+        assert_eq!(encode(CodingBrotli, text.as_slice()), Err(UnsupportedCoding));
+    }
+
+    // Decode with `flate`, independent of `Decoder`.
+    fn inflate(coding: ContentCoding, data: &[u8]) -> Vec<u8> {
+        let inflated = match coding {
+            CodingGzip => flate::inflate_bytes(data.slice(10, data.len() - 8)),
+            _ => flate::inflate_bytes_zlib(data),
+        };
+        inflated.unwrap().as_slice().to_vec()
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let invalid: [&[u8], ..4] = [
+            // over-subscribed code length codes
+            &[0x78, 0x01, 0x05, 0x00, 0x92, 0x04],
+            // back-reference before the start of data
+            &[0x78, 0x01, 0x03, 0x02, 0x00],
+            // stored block with wrong NLEN
+            &[0x78, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x61],
+            // truncated dynamic block header
+            &[0x78, 0x01, 0xed]];
+        for data in invalid.iter() {
+            assert_eq!(decode(CodingDeflate, *data, 4096), Err(InvalidCompressedData));
+        }
+        let stored = [0x78, 0x01, 0x01, 0x01, 0x00, 0xfe, 0xff, 0x61, 0x00, 0x62, 0x00, 0x62];
+        assert_eq!(decode(CodingDeflate, stored, 4096), Ok(vec![0x61]));
+    }
+
+    #[test]
+    fn test_encoder() {
+        let text = fox();
+        for &coding in [CodingGzip, CodingDeflate].iter() {
+            let mut encoder = Encoder::new(coding).unwrap();
+            let mut out = Vec::new();
+            for piece in text.as_slice().chunks(100) {
+                encoder.feed(piece, &mut out);
+            }
+            encoder.finish(&mut out);
+            assert!(out.len() < text.len());
+            assert_eq!(decode(coding, out.as_slice(), 4096), Ok(text.clone()));
+            assert_eq!(inflate(coding, out.as_slice()), text);
+        }
+
+        // longer than the window, with flushes in between.
+        let mut seed = 1u32;
+        let data: Vec<u8> = range(0, 100000u).map(|_| {
+            seed = seed * 1103515245 + 12345;
+            0x61 + (seed >> 16) as u8 % 8
+        }).collect();
+        for &coding in [CodingGzip, CodingDeflate].iter() {
+            let mut encoder = Encoder::new(coding).unwrap();
+            let mut out = Vec::new();
+            for (i, piece) in data.as_slice().chunks(7000).enumerate() {
+                encoder.feed(piece, &mut out);
+                if i % 3 == 0 { encoder.flush(&mut out) }
+            }
+            encoder.finish(&mut out);
+            assert_eq!(inflate(coding, out.as_slice()), data);
+        }
+
+        // data given so far can be decoded after flush.
+        let mut encoder = Encoder::new(CodingDeflate).unwrap();
+        let mut out = Vec::new();
+        encoder.feed(text.slice_to(100), &mut out);
+        encoder.flush(&mut out);
+        let mut decoder = Decoder::new(CodingDeflate).unwrap();
+        let mut decoded = Vec::new();
+        decoder.feed(out.as_slice(), &mut decoded).unwrap();
+        assert_eq!(decoded.as_slice(), text.slice_to(100));
+        assert!(Encoder::new(CodingBrotli).is_err());
+    }
+
+    #[test]
+    fn test_accept_encoding() {
+        let accept = AcceptEncoding::parse("gzip;q=0.5, deflate, br;Q=0.900, x-unknown");
+        assert_eq!(accept.quality(CodingGzip), 500);
+        assert_eq!(accept.quality(CodingDeflate), 1000);
+        assert_eq!(accept.quality(CodingBrotli), 900);
+        assert_eq!(accept.quality(CodingIdentity), 1000);
+        assert_eq!(accept.quality(CodingOther), 0);
+        assert_eq!(accept.negotiate([CodingGzip]), Some(CodingIdentity));
+        assert_eq!(accept.negotiate([CodingGzip, CodingDeflate]), Some(CodingDeflate));
+
+        let accept = AcceptEncoding::parse("*;q=0.1, gzip;q=0");
+        assert_eq!(accept.quality(CodingGzip), 0);
+        assert_eq!(accept.quality(CodingIdentity), 100);
+        assert_eq!(accept.negotiate([CodingGzip, CodingDeflate]), Some(CodingDeflate));
+
+        let accept = AcceptEncoding::parse("identity;q=0, *;q=0");
+        assert_eq!(accept.negotiate([CodingGzip]), None);
+
+        // invalid quality values are ignored.
+        let accept = AcceptEncoding::parse("gzip;q=1.5, deflate;q=0.0001, br;q=, identity;q=0.");
+        assert_eq!(accept.codings, vec!((Some(CodingIdentity), 0)));
+        assert_eq!(AcceptEncoding::parse("").negotiate([CodingGzip]), Some(CodingIdentity));
+    }
+
+    #[test]
+    fn test_decoding_handler() {
+        let mut data = format!("HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n", GZIP_HELLO.len()).into_bytes();
+        data.push_all(GZIP_HELLO);
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        assert_eq!(parser.parse(data.as_slice(), &mut handler), Ok(data.len()));
+        assert_eq!(handler.coding(), CodingGzip);
+        assert_eq!(handler.error(), None);
+        let handler = handler.unwrap();
+        assert!(handler.finished);
+        assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
+        assert_eq!(handler.headers.find(&"Content-Encoding".to_string()), Some(&"gzip".to_string()));
+    }
+
+    #[test]
+    fn test_decoding_handler_ratio_limit() {
+        let mut data = format!("HTTP/1.1 200 OK\r\nContent-Encoding: deflate\r\nContent-Length: {}\r\n\r\n", ZLIB_FOX.len()).into_bytes();
+        data.push_all(ZLIB_FOX);
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        handler.set_max_ratio(10);
+        assert_eq!(parser.parse(data.as_slice(), &mut handler), Ok(data.len()));
+        assert_eq!(handler.error(), Some(SizeLimitExceeded));
+        assert!(handler.get_ref().finished);
+        assert_eq!(handler.get_ref().body, None);
+    }
+
+    #[test]
+    fn test_decoding_handler_incremental() {
+        let mut data = format!("HTTP/1.1 200 OK\r\nContent-Encoding: deflate\r\nContent-Length: {}\r\n\r\n", ZLIB_FOX.len()).into_bytes();
+        data.push_all(ZLIB_FOX);
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        let (head, trailer) = (data.slice_to(data.len() - 4), data.slice_from(data.len() - 4));
+        for byte in head.chunks(1) {
+            assert_eq!(parser.parse(byte, &mut handler), Ok(1));
+        }
+        // decoded data is passed before the body completes.
+        assert_eq!(handler.get_ref().buffer, fox());
+        assert!(!handler.get_ref().finished);
+        assert_eq!(parser.parse(trailer, &mut handler), Ok(4));
+        assert_eq!(handler.error(), None);
+        assert_eq!(handler.unwrap().body, Some(String::from_utf8(fox()).unwrap()));
+    }
+
+    #[test]
+    fn test_decoding_handler_protocol() {
+        let data = "RTSP/1.0 200 OK\r\nCSeq: 2\r\nContent-Length: 5\r\n\r\nhello$\x00\x00\x02hi\
+                    RTSP/1.0 200 OK\r\nCSeq: 3\r\nContent-Length: 0\r\n\r\n";
+        let mut parser = Parser::with_protocol(ParseResponse, &RTSP);
+        let mut handler = DecodingHandler::new(Rewriter::new(MemWriter::new(), HeaderRules::new()));
+        let mut read = 0u;
+        while read < data.len() {
+            read += parser.parse(data.as_bytes().slice_from(read), &mut handler).unwrap();
+        }
+        let rewriter = handler.unwrap();
+        assert_eq!(rewriter.error(), None);
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(), data.to_string());
+    }
+
+    #[test]
+    fn test_decoding_handler_unsupported() {
+        let data = "HTTP/1.1 200 OK\r\nContent-Encoding: br\r\nContent-Length: 2\r\n\r\n\x0b\x01";
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert_eq!(handler.coding(), CodingBrotli);
+        assert_eq!(handler.error(), Some(UnsupportedCoding));
+        assert!(handler.get_ref().finished);
+        assert_eq!(handler.get_ref().body, None);
+    }
+}
+
+mod eventsource {
+    use http::eventsource::*;
+    use http::parser::*;
+
+    fn event(event: &str, data: &str, id: &str, retry: Option<u64>) -> Event {
+        Event { event: event.to_string(), data: data.to_string(), id: id.to_string(), retry: retry }
+    }
+
+    struct EventHandler {
+        parser: EventStreamParser,
+        events: Vec<Event>,
+        in_body: bool,
+    }
+
+    impl MessageHandler for EventHandler {
+        fn on_headers_complete(&mut self, _: &Parser) -> bool {
+            self.in_body = true;
+            false
+        }
+
+        fn write(&mut self, _: &Parser, data: &[u8]) {
+            if self.in_body {
+                let events = self.parser.feed(data).unwrap();
+                self.events.push_all(events.as_slice());
+            }
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let mut parser = EventStreamParser::new();
+        let stream = "\ufeff: comment\n\
+                      data: first\n\
+                      data:second\n\
+                      \n\
+                      event: update\r\n\
+                      id: 42\r\n\
+                      retry: 3000\r\n\
+                      data\r\n\r\n\
+                      field without colon\n\
+                      retry: 1.5\n\
+                      \n\
+                      id\n\
+                      data:  spaced\r\r";
+        assert_eq!(parser.feed(stream.as_bytes()),
+                   Ok(vec!(event("message", "first\nsecond", "", None),
+                           event("update", "", "42", Some(3000)),
+                           event("message", " spaced", "", None))));
+        assert_eq!(parser.last_event_id(), "");
+        assert_eq!(parser.reconnection_time(), Some(3000));
+        // incomplete event is not dispatched
+        assert_eq!(parser.feed("data: pending\n".as_bytes()), Ok(vec!()));
+    }
+
+    #[test]
+    fn test_pending_event_id() {
+        let mut parser = EventStreamParser::new();
+        assert_eq!(parser.feed("id: 1\ndata: a\n\nid: 2\ndata: b\n".as_bytes()),
+                   Ok(vec!(event("message", "a", "1", None))));
+        // the id of an incomplete event is not committed yet
+        assert_eq!(parser.last_event_id(), "1");
+        assert_eq!(parser.feed("\n".as_bytes()), Ok(vec!(event("message", "b", "2", None))));
+        assert_eq!(parser.last_event_id(), "2");
+        // an event without data still updates the last event ID
+        assert_eq!(parser.feed("id: 3\n\n".as_bytes()), Ok(vec!()));
+        assert_eq!(parser.last_event_id(), "3");
+    }
+
+    #[test]
+    fn test_split_lines() {
+        let mut parser = EventStreamParser::new();
+        let mut events = Vec::new();
+        for piece in "data: a\r\ndata: b\r\n\r\nid: 1\rdata: c\r\r".as_bytes().chunks(1) {
+            events.push_all(parser.feed(piece).unwrap().as_slice());
+        }
+        assert_eq!(events, vec!(event("message", "a\nb", "", None), event("message", "c", "1", None)));
+    }
+
+    #[test]
+    fn test_event_too_large() {
+        let mut parser = EventStreamParser::new();
+        parser.set_max_event_size(16);
+        assert_eq!(parser.feed("data: 1234\n".as_bytes()), Ok(vec!()));
+        assert_eq!(parser.feed("data: 123456".as_bytes()), Err(EventTooLarge));
+        assert_eq!(parser.feed("\n".as_bytes()), Err(EventTooLarge));
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let data = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    6\r\ndata: \r\n9\r\nhello\n\nda\r\n9\r\nta: bye\n\n\r\n0\r\n\r\n";
+        let mut handler = EventHandler { parser: EventStreamParser::new(), events: Vec::new(), in_body: false };
+        let mut parser = Parser::new(ParseResponse);
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert_eq!(handler.events, vec!(event("message", "hello", "", None), event("message", "bye", "", None)));
+    }
+}
+
 mod http_0_9 {
     use http::*;
     use http::parser::*;
@@ -104,7 +479,7 @@ mod http_1_0 {
         assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
         assert!(handler.started);
         assert!(handler.finished);
-        assert_eq!(handler.status_code, 304);
+        assert_eq!(handler.status_code, StatusCode(304));
         assert_eq!(handler.version, Some(HTTP_1_0));
     }
 
@@ -117,11 +492,35 @@ mod http_1_0 {
         assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
         assert!(handler.started);
         assert!(handler.finished);
-        assert_eq!(handler.status_code, 200);
+        assert_eq!(handler.status_code, StatusCode(200));
         assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
         assert_eq!(handler.version, Some(HTTP_1_0));
     }
 
+    #[test]
+    fn test_response_until_eof() {
+        let msg = "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nHello, HTTP world!";
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
+        assert!(!handler.finished);
+        assert_eq!(parser.finish(&mut handler), Ok(0));
+        assert!(handler.finished);
+        assert_eq!(parser.finish(&mut handler), Ok(0));
+    }
+
+    #[test]
+    fn test_unexpected_eof() {
+        let msg = create_request("POST", "/post", 0, None, Some("name=bee"));
+        let data = msg.as_bytes().slice_to(msg.len() - 1);
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
+        assert_eq!(parser.finish(&mut handler), Err(UnexpectedEof));
+        assert!(!handler.finished);
+    }
+
     #[bench]
     fn bench_request_get(b: &mut Bencher) {
         let msg = create_request("GET", "/path/to/some/contents", 0, None, None);
@@ -178,7 +577,7 @@ mod http_1_1 {
         assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
         assert!(handler.started);
         assert!(handler.finished);
-        assert_eq!(handler.status_code, 304);
+        assert_eq!(handler.status_code, StatusCode(304));
         assert_eq!(handler.version, Some(HTTP_1_1));
     }
 
@@ -191,7 +590,7 @@ mod http_1_1 {
         assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
         assert!(handler.started);
         assert!(handler.finished);
-        assert_eq!(handler.status_code, 200);
+        assert_eq!(handler.status_code, StatusCode(200));
         assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
         assert_eq!(handler.version, Some(HTTP_1_1));
     }
@@ -200,14 +599,14 @@ mod http_1_1 {
     fn test_response_chunked() {
         let msg = create_response(1, "200 OK",
                                   Some(vec!("Content-Type", "text/plain", "Transfer-Encoding", "chunked")),
-                                  Some("F\r\nHello, HTTP wor\r\n3;chunk-ext-name\r\nld!\r\n0\r\n"));
+                                  Some("F\r\nHello, HTTP wor\r\n3;chunk-ext-name\r\nld!\r\n0\r\n\r\n"));
         let data = msg.as_bytes();
         let mut parser = Parser::new(ParseResponse);
         let mut handler = TestHandler::new();
         assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
         assert!(handler.started);
         assert!(handler.finished);
-        assert_eq!(handler.status_code, 200);
+        assert_eq!(handler.status_code, StatusCode(200));
         assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
         assert_eq!(handler.version, Some(HTTP_1_1));
     }
@@ -231,19 +630,1824 @@ mod http_1_1 {
     fn bench_response_chunked(b: &mut Bencher) {
         let msg = create_response(1, "200 OK",
                                   Some(vec!("Content-Type", "text/plain", "Transfer-Encoding", "chunked")),
-                                  Some("10\r\nHello, HTTP worl\r\n2;chunk-ext-name\r\nd!\r\n0\r\n"));
+                                  Some("10\r\nHello, HTTP worl\r\n2;chunk-ext-name\r\nd!\r\n0\r\n\r\n"));
         let data = msg.as_bytes();
         b.iter(|| Parser::new(ParseResponse).parse(data, &mut BenchHandler) );
     }
 }
 
+mod http2 {
+    use http::parser::*;
+    use http::reader::MessageReader;
+    use std::io::MemReader;
+    use super::TestHandler;
+
+    static PREFACE: &'static str = "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+    #[test]
+    fn test_preface() {
+        let mut data = PREFACE.as_bytes().to_vec();
+        data.push_all([0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00]); // SETTINGS frame
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data.as_slice(), &mut handler), Ok(Some(PREFACE.len())));
+        assert_eq!(parser.http2_start(), Some(Http2Preface));
+        assert!(handler.finished);
+        assert_eq!(handler.method, None);
+        assert_eq!(parser.parse(data.slice_from(PREFACE.len()), &mut handler), Ok(0));
+        assert_eq!(parser.finish(&mut handler), Ok(0));
+
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        for byte in PREFACE.as_bytes().chunks(1) {
+            assert_eq!(parser.parse(byte, &mut handler), Ok(1));
+        }
+        assert_eq!(parser.http2_start(), Some(Http2Preface));
+
+        let mut reader = MessageReader::new(MemReader::new(data.clone()), ParseRequest);
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.http2, Some(Http2Preface));
+        assert_eq!(reader.unwrap().val1(), data.slice_from(PREFACE.len()).to_vec());
+    }
+
+    #[test]
+    fn test_invalid_preface() {
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse("PRI * HTTP/1.1\r\n\r\n".as_bytes(), &mut handler), Err(InvalidRequestLine));
+
+        let mut parser = Parser::new(ParseRequest);
+        let data = "PROPPATCH / HTTP/1.1\r\n\r\n";
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert_eq!(parser.http2_start(), None);
+    }
+
+    #[test]
+    fn test_h2c_upgrade() {
+        let data = "GET / HTTP/1.1\r\nHost: faultier.jp\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+                    Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAARAAAAAAAIAAAAA\r\n\r\n";
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert!(parser.should_upgrade());
+        assert_eq!(parser.http2_start(), Some(Http2Upgrade));
+
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), ParseRequest);
+        assert_eq!(reader.read_message().unwrap().http2, Some(Http2Upgrade));
+
+        // HTTP2-Settings is required
+        let data = "GET / HTTP/1.1\r\nConnection: Upgrade\r\nUpgrade: h2c\r\nHTTP2-Setting: x\r\n\r\n";
+        let mut parser = Parser::new(ParseRequest);
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert!(parser.should_upgrade());
+        assert_eq!(parser.http2_start(), None);
+
+        let data = "GET / HTTP/1.1\r\nConnection: Upgrade, HTTP2-Settings\r\nUpgrade: websocket, h2c2\r\n\
+                    HTTP2-Settings: \r\n\r\n";
+        let mut parser = Parser::new(ParseRequest);
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert_eq!(parser.http2_start(), None);
+
+        let data = "GET / HTTP/1.1\r\nConnection: HTTP2-Settings\r\nUpgrade: websocket, h2c\r\n\
+                    HTTP2-Settings: \r\n\r\n";
+        let mut parser = Parser::new(ParseRequest);
+        assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+        assert!(!parser.should_upgrade());
+        assert_eq!(parser.http2_start(), None);
+    }
+
+    #[test]
+    fn test_connection_tokens() {
+        let cases = [
+            ("Connection: keep-alive, Upgrade", true, true),
+            ("Connection: TE,close", false, false),
+            ("Connection: close , upgrade", false, true),
+            ("Connection: Upgraded, keep-alive-ish", true, false),
+            ("Connection: upgrade\r\nConnection: close", false, true),
+        ];
+        for &(header, keep_alive, upgrade) in cases.iter() {
+            let data = format!("GET / HTTP/1.1\r\n{}\r\n\r\n", header);
+            let mut parser = Parser::new(ParseRequest);
+            let mut handler = TestHandler::new();
+            assert_eq!(parser.parse(data.as_bytes(), &mut handler), Ok(data.len()));
+            assert_eq!((parser.should_keep_alive(), parser.should_upgrade()), (keep_alive, upgrade));
+        }
+    }
+}
+
+mod cache {
+    use http::cache::*;
+    use http::parser::{ParseType, ParseRequest, ParseResponse};
+    use http::reader::{Message, MessageReader};
+    use std::io::MemReader;
+    use time::Timespec;
+
+    // Sun, 06 Nov 1994 08:49:37 GMT
+    static DATE: i64 = 784111777;
+
+    fn message(data: &str, t: ParseType) -> Message {
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), t);
+        reader.read_message().unwrap()
+    }
+
+    fn request(headers: &str) -> Message {
+        message(format!("GET / HTTP/1.1\r\nHost: example.com\r\n{}\r\n", headers).as_slice(), ParseRequest)
+    }
+
+    fn response(status: &str, headers: &str) -> Message {
+        message(format!("HTTP/1.1 {}\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\n{}Content-Length: 0\r\n\r\n",
+                        status, headers).as_slice(), ParseResponse)
+    }
+
+    fn at(offset: i64) -> Timespec {
+        Timespec::new(DATE + offset, 0)
+    }
+
+    #[test]
+    fn test_cache_control() {
+        let cc = CacheControl::parse("max-age=60, S-MaxAge=\"120\", no-cache=\"Set-Cookie\", private, max-stale, \
+                                      max-age=10");
+        assert_eq!(cc.max_age, Some(60));
+        assert_eq!(cc.s_maxage, Some(120));
+        assert_eq!(cc.max_stale, Some(::std::i64::MAX));
+        assert!(cc.no_cache);
+        assert!(cc.private);
+        assert!(!cc.public);
+        assert!(!cc.no_store);
+
+        let cc = CacheControl::parse("max-age=-1, min-fresh=99999999999999999999, max-stale=5");
+        assert_eq!(cc.max_age, None);
+        assert_eq!(cc.min_fresh, Some(2147483648));
+        assert_eq!(cc.max_stale, Some(5));
+
+        let res = response("200 OK", "Cache-Control: public\r\nCache-Control: max-age=1\r\n");
+        let cc = CacheControl::from_message(&res);
+        assert!(cc.public);
+        assert_eq!(cc.max_age, Some(1));
+        assert_eq!(CacheControl::parse(""), CacheControl::new());
+    }
+
+    #[test]
+    fn test_is_cacheable() {
+        let get = request("");
+        assert!(is_cacheable(&get, &response("200 OK", ""), true));
+        assert!(!is_cacheable(&message("POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n", ParseRequest),
+                              &response("200 OK", ""), true));
+        assert!(!is_cacheable(&get, &response("200 OK", "Cache-Control: no-store\r\n"), false));
+        assert!(!is_cacheable(&request("Cache-Control: no-store\r\n"), &response("200 OK", ""), false));
+        assert!(!is_cacheable(&get, &response("200 OK", "Cache-Control: private\r\n"), true));
+        assert!(is_cacheable(&get, &response("200 OK", "Cache-Control: private\r\n"), false));
+        assert!(!is_cacheable(&get, &response("200 OK", "Vary: Accept, *\r\n"), false));
+        assert!(!is_cacheable(&get, &response("206 Partial Content", ""), false));
+        assert!(!is_cacheable(&get, &response("302 Found", ""), false));
+        assert!(is_cacheable(&get, &response("302 Found", "Cache-Control: max-age=60\r\n"), false));
+        assert!(is_cacheable(&get, &response("302 Found", "Expires: 0\r\n"), false));
+
+        let auth = request("Authorization: Basic YTpi\r\n");
+        assert!(!is_cacheable(&auth, &response("200 OK", ""), true));
+        assert!(is_cacheable(&auth, &response("200 OK", ""), false));
+        assert!(is_cacheable(&auth, &response("200 OK", "Cache-Control: public\r\n"), true));
+        assert!(is_cacheable(&auth, &response("200 OK", "Cache-Control: s-maxage=60\r\n"), true));
+    }
+
+    #[test]
+    fn test_freshness_lifetime() {
+        let both = response("200 OK", "Cache-Control: max-age=60, s-maxage=120\r\n");
+        assert_eq!(freshness_lifetime(&both, true, at(0)), Some(120));
+        assert_eq!(freshness_lifetime(&both, false, at(0)), Some(60));
+        let expires = response("200 OK", "Expires: Sun, 06 Nov 1994 08:51:17 GMT\r\n");
+        assert_eq!(freshness_lifetime(&expires, false, at(0)), Some(100));
+        assert_eq!(freshness_lifetime(&response("200 OK", "Expires: 0\r\n"), false, at(0)), Some(0));
+        let modified = response("200 OK", "Last-Modified: Sun, 06 Nov 1994 08:32:57 GMT\r\n");
+        assert_eq!(freshness_lifetime(&modified, false, at(0)), Some(100));
+        let modified = response("302 Found", "Last-Modified: Sun, 06 Nov 1994 08:32:57 GMT\r\n");
+        assert_eq!(freshness_lifetime(&modified, false, at(0)), None);
+        assert_eq!(freshness_lifetime(&response("200 OK", ""), false, at(0)), None);
+    }
+
+    #[test]
+    fn test_current_age() {
+        let res = response("200 OK", "Age: 10\r\n");
+        // response delay is 3 seconds.
+        assert_eq!(current_age(&res, at(-2), at(1), at(1)), 13);
+        assert_eq!(current_age(&res, at(-2), at(1), at(11)), 23);
+        // clock skew is corrected by apparent age.
+        let res = response("200 OK", "");
+        assert_eq!(current_age(&res, at(29), at(30), at(30)), 30);
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let get = request("");
+        let res = response("200 OK", "Cache-Control: max-age=60\r\n");
+        assert!(is_fresh(&get, &res, true, at(0), at(0), at(30)));
+        assert!(!is_fresh(&get, &res, true, at(0), at(0), at(61)));
+        assert!(is_fresh(&request("Cache-Control: max-stale=5\r\n"), &res, true, at(0), at(0), at(61)));
+        assert!(!is_fresh(&request("Cache-Control: max-stale=5\r\n"), &res, true, at(0), at(0), at(66)));
+        assert!(!is_fresh(&request("Cache-Control: max-age=10\r\n"), &res, true, at(0), at(0), at(30)));
+        assert!(!is_fresh(&request("Cache-Control: min-fresh=40\r\n"), &res, true, at(0), at(0), at(30)));
+        assert!(!is_fresh(&request("Cache-Control: no-cache\r\n"), &res, true, at(0), at(0), at(0)));
+        assert!(!is_fresh(&request("Pragma: no-cache\r\n"), &res, true, at(0), at(0), at(0)));
+
+        let revalidate = response("200 OK", "Cache-Control: max-age=60, proxy-revalidate\r\n");
+        let stale = request("Cache-Control: max-stale\r\n");
+        assert!(!is_fresh(&stale, &revalidate, true, at(0), at(0), at(61)));
+        assert!(is_fresh(&stale, &revalidate, false, at(0), at(0), at(61)));
+        assert!(!is_fresh(&get, &response("200 OK", "Cache-Control: no-cache\r\n"), true, at(0), at(0), at(0)));
+    }
+
+    #[test]
+    fn test_conditional_headers() {
+        let req = request("Accept: text/html\r\nIf-None-Match: \"old\"\r\nConnection: close\r\n");
+        let stored = response("200 OK", "ETag: \"abc\"\r\nLast-Modified: Sun, 06 Nov 1994 08:32:57 GMT\r\n");
+        let headers = conditional_headers(&req, &stored).unwrap();
+        let headers: Vec<(&str, &str)> = headers.iter().map(|&(ref n, ref v)| (n.as_slice(), v.as_slice())).collect();
+        assert_eq!(headers, vec![("Host", "example.com"), ("Accept", "text/html"), ("If-None-Match", "\"abc\""),
+                                 ("If-Modified-Since", "Sun, 06 Nov 1994 08:32:57 GMT")]);
+        assert_eq!(conditional_headers(&req, &response("200 OK", "")), None);
+    }
+
+    #[test]
+    fn test_update_headers() {
+        let mut stored = response("200 OK", "ETag: \"abc\"\r\nCache-Control: max-age=60\r\nX-Foo: 1\r\n");
+        let not_modified = message("HTTP/1.1 304 Not Modified\r\nCache-Control: max-age=120\r\n\
+                                    Connection: keep-alive\r\nContent-Length: 5\r\n\r\n", ParseResponse);
+        update_headers(&mut stored, &not_modified);
+        assert_eq!(stored.header("Cache-Control"), Some("max-age=120"));
+        assert_eq!(stored.headers_named("Cache-Control").len(), 1);
+        assert_eq!(stored.header("X-Foo"), Some("1"));
+        assert_eq!(stored.header("Content-Length"), Some("0"));
+        assert_eq!(stored.header("Connection"), None);
+    }
+}
+
+mod chunked {
+    use http::*;
+    use http::chunked::*;
+    use http::parser::*;
+    use http::serializer::MessageWriter;
+    use http::status;
+    use std::io::MemWriter;
+    use super::TestHandler;
+
+    #[test]
+    fn test_chunked_writer() {
+        let mut writer = ChunkedWriter::new(MemWriter::new());
+        writer.write("Hello, HTTP wor".as_bytes()).unwrap();
+        writer.write([]).unwrap();
+        writer.write_chunk("ld!".as_bytes(), [("name", "value"), ("flag", ""), ("quoted", "a \"b\"")]).unwrap();
+        let out = writer.finish_with_trailers([("Expires", "0")]).unwrap().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "f\r\nHello, HTTP wor\r\n3;name=value;flag;quoted=\"a \\\"b\\\"\"\r\nld!\r\n0\r\nExpires: 0\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_chunked_writer_invalid() {
+        // nothing is written on error.
+        let mut writer = ChunkedWriter::new(MemWriter::new());
+        assert!(writer.write_chunk("a".as_bytes(), [("name", "value"), ("bad name", "")]).is_err());
+        assert!(writer.get_ref().get_ref().is_empty());
+        assert!(writer.write_chunk("a".as_bytes(), [("name", "bad\r\nvalue")]).is_err());
+        assert!(writer.get_ref().get_ref().is_empty());
+        assert!(writer.finish_with_trailers([("Bad:Name", "0")]).is_err());
+
+        let mut out = MemWriter::new();
+        assert!(write_last_chunk(&mut out, [("Expires", "0"), ("Bad:Name", "0")]).is_err());
+        assert!(out.get_ref().is_empty());
+        assert!(write_last_chunk(&mut out, [("Expires", "bad\nvalue")]).is_err());
+        assert!(out.get_ref().is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.header("Trailer", "Expires").unwrap();
+        for piece in ["Hello, ", "HTTP ", "world!"].iter() {
+            writer.write(piece.as_bytes()).unwrap();
+        }
+        let out = writer.finish_with_trailers([("Expires", "0")]).unwrap().unwrap();
+
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse(out.as_slice(), &mut handler), Ok(out.len()));
+        assert!(handler.finished);
+        assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
+
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = TestHandler::new();
+        for byte in out.as_slice().chunks(1) {
+            assert_eq!(parser.parse(byte, &mut handler), Ok(1));
+        }
+        assert!(handler.finished);
+        assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_chunk() {
+        let msg = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabcX\r\n0\r\n\r\n";
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse(msg.as_bytes(), &mut handler), Err(InvalidChunk));
+    }
+}
+
+mod cookie {
+    use http::cookie::*;
+    use http::parser::{ParseRequest, ParseResponse};
+    use http::reader::MessageReader;
+    use std::io::MemReader;
+    use time::Timespec;
+
+    #[test]
+    fn test_parse_cookie() {
+        assert_eq!(parse_cookie("a=1; b=\"2\"; c; =x"),
+                   vec![("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())]);
+        assert_eq!(parse_cookie(""), vec![]);
+        assert_eq!(format_cookie([("a", "1"), ("b", "2")]), Some("a=1; b=2".to_string()));
+        assert_eq!(format_cookie([("a", "\"1\"")]), Some("a=\"1\"".to_string()));
+        assert_eq!(format_cookie([("a", "1; b=2")]), None);
+        assert_eq!(format_cookie([("a b", "1")]), None);
+    }
+
+    #[test]
+    fn test_set_cookie_validation() {
+        let mut cookie = SetCookie::new("id", "a3fWa");
+        cookie.domain = Some("example.com".to_string());
+        cookie.path = Some("/docs".to_string());
+        assert!(cookie.is_valid());
+        assert!(!SetCookie::new("id", "x; Domain=evil.com").is_valid());
+        assert!(!SetCookie::new("id", "a b").is_valid());
+        assert!(!SetCookie::new("i;d", "x").is_valid());
+        assert!(!SetCookie::new("", "x").is_valid());
+        cookie.path = Some("/; Domain=evil.com".to_string());
+        assert!(!cookie.is_valid());
+        cookie.path = None;
+        cookie.domain = Some("example.com\r\nX-Evil: 1".to_string());
+        assert!(!cookie.is_valid());
+    }
+
+    #[test]
+    fn test_set_cookie() {
+        let value = "id=a3fWa; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=3600; Domain=.Example.com; \
+                     Path=/docs; Secure; HttpOnly; SameSite=Lax";
+        let cookie = SetCookie::parse(value).unwrap();
+        assert_eq!(cookie.name.as_slice(), "id");
+        assert_eq!(cookie.value.as_slice(), "a3fWa");
+        assert_eq!(cookie.expires, Some(Timespec::new(1445412480, 0)));
+        assert_eq!(cookie.max_age, Some(3600));
+        assert_eq!(cookie.domain, Some("example.com".to_string()));
+        assert_eq!(cookie.path, Some("/docs".to_string()));
+        assert!(cookie.secure);
+        assert!(cookie.http_only);
+        assert_eq!(cookie.same_site, Some(SameSiteLax));
+        assert_eq!(format!("{}", cookie).as_slice(),
+                   "id=a3fWa; Expires=Wed, 21 Oct 2015 07:28:00 GMT; Max-Age=3600; Domain=example.com; \
+                    Path=/docs; Secure; HttpOnly; SameSite=Lax");
+        assert_eq!(SetCookie::parse(format!("{}", cookie).as_slice()), Some(cookie));
+    }
+
+    #[test]
+    fn test_set_cookie_invalid_attributes() {
+        let cookie = SetCookie::parse("a=b; Max-Age=1x; Path=docs; Expires=never; SameSite=Any; Foo").unwrap();
+        assert_eq!(cookie, SetCookie::new("a", "b"));
+        assert_eq!(SetCookie::parse("Max-Age=-1").unwrap().max_age, None);
+        assert_eq!(SetCookie::parse("a=b; Max-Age=-1").unwrap().max_age, Some(-1));
+        assert_eq!(SetCookie::parse("noequal"), None);
+        assert_eq!(SetCookie::parse("=b"), None);
+    }
+
+    #[test]
+    fn test_message() {
+        let data = "GET / HTTP/1.1\r\nCookie: a=1; b=2\r\nCookie: c=3\r\n\r\n";
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), ParseRequest);
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.cookies().len(), 3);
+        assert_eq!(message.cookie("c"), Some("3".to_string()));
+        assert_eq!(message.cookie("A"), None);
+
+        let data = "HTTP/1.1 200 OK\r\nSet-Cookie: a=1; Path=/\r\nSet-Cookie: invalid\r\n\
+                    Set-Cookie: b=2; HttpOnly\r\nContent-Length: 0\r\n\r\n";
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), ParseResponse);
+        let cookies = reader.read_message().unwrap().set_cookies();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].path, Some("/".to_string()));
+        assert!(cookies[1].http_only);
+    }
+}
+
+mod date {
+    use http::date::*;
+    use time::Timespec;
+
+    #[test]
+    fn test_date() {
+        assert_eq!(parse_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(Timespec::new(784111777, 0)));
+        assert_eq!(parse_date("Sunday, 06-Nov-94 08:49:37 GMT"), Some(Timespec::new(784111777, 0)));
+        assert_eq!(parse_date("Sun Nov  6 08:49:37 1994"), Some(Timespec::new(784111777, 0)));
+        assert_eq!(parse_date("Thu, 29 Feb 2024 00:00:00 GMT"), Some(Timespec::new(1709164800, 0)));
+        assert_eq!(parse_date("Fri, 30 Feb 2024 00:00:00 GMT"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 24:00:00 GMT"), None);
+        assert_eq!(parse_date("Sun, 06 Nov 1994 GMT"), None);
+        assert_eq!(format_date(Timespec::new(0, 0)).as_slice(), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_date(Timespec::new(784111777, 0)).as_slice(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_date(Timespec::new(-1, 0)).as_slice(), "Wed, 31 Dec 1969 23:59:59 GMT");
+    }
+}
+
+mod limits {
+    use http::parser::*;
+    use super::{TestHandler, create_request};
+
+    #[test]
+    fn test_max_header_size() {
+        let msg = create_request("GET", "/get", 1, None, None);
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        parser.set_max_header_size(data.len());
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Ok(data.len()));
+        parser.set_max_header_size(data.len() - 1);
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Err(HeaderTooLarge));
+    }
+
+    #[test]
+    fn test_max_body_size() {
+        let msg = create_request("POST", "/post", 1, None, Some("name=bee"));
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        parser.set_max_body_size(8);
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Ok(data.len()));
+        let mut parser = Parser::new(ParseRequest);
+        parser.set_max_body_size(7);
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Err(BodyTooLarge));
+    }
+
+    #[test]
+    fn test_max_chunked_body_size() {
+        let data = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nbee\r\n3\r\nbee\r\n0\r\n\r\n".as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        parser.set_max_body_size(6);
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Ok(data.len()));
+        let mut parser = Parser::new(ParseRequest);
+        parser.set_max_body_size(5);
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Err(BodyTooLarge));
+    }
+
+    #[test]
+    fn test_chunk_size_overflow() {
+        let data = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffffff\r\n".as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        assert_eq!(parser.parse(data, &mut TestHandler::new()), Err(InvalidChunk));
+    }
+}
+
+mod method {
+    use http::*;
+
+    #[test]
+    fn test_from_str() {
+        let methods = [HttpCheckout, HttpConnect, HttpCopy, HttpDelete, HttpGet, HttpHead, HttpLink,
+                       HttpLock, HttpMerge, HttpMkActivity, HttpMkCalendar, HttpMkCol, HttpMove,
+                       HttpMsearch, HttpNotify, HttpOptions, HttpPatch, HttpPost, HttpPropFind,
+                       HttpPropPatch, HttpPurge, HttpPut, HttpReport, HttpSearch, HttpSubscribe,
+                       HttpTrace, HttpUnlink, HttpUnlock, HttpUnsubscribe];
+        for method in methods.iter() {
+            assert_eq!(from_str::<HttpMethod>(method.name()), Some(*method));
+        }
+        assert_eq!(from_str::<HttpMethod>("get"), None);
+        assert_eq!(from_str::<HttpMethod>("BREW"), None);
+    }
+
+    #[test]
+    fn test_metadata() {
+        assert!(HttpGet.is_safe() && HttpGet.is_idempotent() && HttpGet.is_cacheable());
+        assert!(!HttpPut.is_safe() && HttpPut.is_idempotent() && !HttpPut.is_cacheable());
+        assert!(!HttpPost.is_safe() && !HttpPost.is_idempotent() && HttpPost.is_cacheable());
+        assert!(HttpPropFind.is_safe());
+        assert!(!HttpLock.is_idempotent());
+        assert!(HttpPost.allows_request_body());
+        assert!(!HttpTrace.allows_request_body());
+    }
+}
+
+mod multipart {
+    use http::multipart::*;
+    use std::str::from_utf8;
+
+    struct PartHandler {
+        events: Vec<String>,
+        buffer: Vec<u8>,
+        data: Vec<u8>,
+    }
+
+    impl PartHandler {
+        fn new() -> PartHandler {
+            PartHandler { events: Vec::new(), buffer: Vec::new(), data: Vec::new() }
+        }
+    }
+
+    impl MultipartHandler for PartHandler {
+        fn on_part_begin(&mut self, _: &MultipartParser) {
+            self.events.push("begin".to_string());
+        }
+
+        fn on_header_value(&mut self, _: &MultipartParser, length: uint) {
+            let len = self.buffer.len();
+            let header = format!("{}={}",
+                                 from_utf8(self.buffer.slice_to(len-length)).unwrap(),
+                                 from_utf8(self.buffer.slice_from(len-length)).unwrap());
+            self.events.push(header);
+            self.buffer.clear();
+        }
+
+        fn on_part_data(&mut self, _: &MultipartParser, data: &[u8]) {
+            self.data.push_all(data);
+        }
+
+        fn on_part_end(&mut self, _: &MultipartParser) {
+            let data = String::from_utf8(self.data.clone()).unwrap();
+            self.events.push(format!("data:{}", data));
+            self.data.clear();
+        }
+
+        fn on_body_end(&mut self, _: &MultipartParser) {
+            self.events.push("end".to_string());
+        }
+
+        fn write(&mut self, _: &MultipartParser, data: &[u8]) {
+            self.buffer.push_all(data);
+        }
+    }
+
+    static BODY: &'static str = "preamble\r\n\
+                                 --AaB03x\r\n\
+                                 Content-Disposition: form-data; name=\"name\"\r\n\
+                                 \r\n\
+                                 bee\r\n\
+                                 --AaB03x\r\n\
+                                 Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+                                 Content-Type: text/plain\r\n\
+                                 \r\n\
+                                 line1\r\n--AaB\r\nline2\r\n\
+                                 --AaB03x--\r\n\
+                                 epilogue";
+
+    fn expected() -> Vec<String> {
+        vec!("begin".to_string(),
+             "Content-Disposition=form-data; name=\"name\"".to_string(),
+             "data:bee".to_string(),
+             "begin".to_string(),
+             "Content-Disposition=form-data; name=\"file\"; filename=\"a.txt\"".to_string(),
+             "Content-Type=text/plain".to_string(),
+             "data:line1\r\n--AaB\r\nline2".to_string(),
+             "end".to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = BODY.as_bytes();
+        let mut parser = MultipartParser::new("AaB03x");
+        let mut handler = PartHandler::new();
+        assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
+        assert!(parser.is_finished());
+        assert_eq!(handler.events, expected());
+    }
+
+    #[test]
+    fn test_parse_byte_by_byte() {
+        let mut parser = MultipartParser::new("AaB03x");
+        let mut handler = PartHandler::new();
+        for byte in BODY.as_bytes().chunks(1) {
+            assert_eq!(parser.parse(byte, &mut handler), Ok(1));
+        }
+        assert!(parser.is_finished());
+        assert_eq!(handler.events, expected());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let mut parser = MultipartParser::new("AaB03x");
+        let mut handler = PartHandler::new();
+        assert_eq!(parser.parse("--AaB03xZ\r\n".as_bytes(), &mut handler), Err(InvalidBoundary));
+        assert_eq!(parser.parse("\r\n".as_bytes(), &mut handler), Err(OtherMultipartError));
+    }
+
+    #[test]
+    fn test_boundary() {
+        assert_eq!(boundary("multipart/form-data; boundary=AaB03x"), Some("AaB03x".to_string()));
+        assert_eq!(boundary("Multipart/Form-Data; charset=utf-8; Boundary=\"a b:c\""), Some("a b:c".to_string()));
+        assert_eq!(boundary("multipart/form-data"), None);
+        assert_eq!(boundary("text/plain; boundary=AaB03x"), None);
+    }
+}
+
+mod pipeline {
+    use http::*;
+    use http::parser::*;
+    use super::{TestHandler, create_request};
+
+    #[test]
+    fn test_next_message() {
+        let first = create_request("POST", "/post", 1, None, Some("name=bee"));
+        let second = "GET /chunked HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nbee\r\n0\r\n\r\n";
+        let third = create_request("GET", "/last", 1, Some(vec!("Connection", "close")), None);
+        let msg = format!("{}{}{}", first, second, third);
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data, &mut handler), Ok(Some(first.len())));
+        assert_eq!(handler.url, Some("/post".to_string()));
+        assert_eq!(handler.body, Some("name=bee".to_string()));
+        let offset = first.len();
+
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data.slice_from(offset), &mut handler), Ok(Some(second.len())));
+        assert_eq!(handler.url, Some("/chunked".to_string()));
+        assert_eq!(handler.body, Some("bee".to_string()));
+        let offset = offset + second.len();
+
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data.slice_from(offset), &mut handler), Ok(Some(third.len())));
+        assert_eq!(handler.url, Some("/last".to_string()));
+        assert!(!parser.should_keep_alive());
+        assert_eq!(offset + third.len(), data.len());
+    }
+
+    #[test]
+    fn test_next_message_partial() {
+        let msg = create_request("GET", "/get", 1, None, None);
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data.slice_to(10), &mut handler), Ok(None));
+        assert!(!handler.finished);
+        assert_eq!(parser.next_message(data.slice_from(10), &mut handler), Ok(Some(data.len() - 10)));
+        assert!(handler.finished);
+        assert_eq!(handler.url, Some("/get".to_string()));
+    }
+
+    #[test]
+    fn test_next_message_without_body() {
+        let msg = "HEAD / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\n\r\n";
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data, &mut handler), Ok(Some(19)));
+        assert_eq!(handler.method, Some(HttpHead));
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data.slice_from(19), &mut handler), Ok(Some(18)));
+        assert_eq!(handler.method, Some(HttpGet));
+    }
+
+    #[test]
+    fn test_next_message_invalid() {
+        let msg = "GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nBad Header\r\n\r\n";
+        let data = msg.as_bytes();
+        let mut parser = Parser::new(ParseRequest);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.next_message(data, &mut handler), Ok(Some(18)));
+        assert_eq!(parser.next_message(data.slice_from(18), &mut handler), Err(InvalidHeaderField));
+    }
+}
+
+mod protocol {
+    use http::parser::*;
+
+    struct ProtocolHandler {
+        events: Vec<String>,
+        buffer: Vec<u8>,
+    }
+
+    impl ProtocolHandler {
+        fn new() -> ProtocolHandler {
+            ProtocolHandler {
+                events: Vec::new(),
+                buffer: Vec::new(),
+            }
+        }
+
+        fn take(&mut self) -> String {
+            let s = String::from_utf8_lossy(self.buffer.as_slice()).into_string();
+            self.buffer.clear();
+            s
+        }
+    }
+
+    impl MessageHandler for ProtocolHandler {
+        fn on_url(&mut self, _: &Parser, _: uint) {
+            let url = self.take();
+            self.events.push(format!("url {}", url));
+        }
+
+        fn on_status(&mut self, _: &Parser, status: ::http::StatusCode) {
+            self.events.push(format!("status {}", status.code()));
+        }
+
+        fn on_header_value(&mut self, _: &Parser, _: uint) {
+            self.buffer.clear();
+        }
+
+        fn on_body(&mut self, _: &Parser, _: uint) {
+            let body = self.take();
+            self.events.push(format!("body {}", body));
+        }
+
+        fn on_message_complete(&mut self, _: &Parser) {
+            self.events.push("complete".to_string());
+        }
+
+        fn on_protocol_method(&mut self, _: &Parser, method: &'static str) {
+            self.events.push(format!("method {}", method));
+        }
+
+        fn on_protocol_version(&mut self, _: &Parser, major: uint, minor: uint) {
+            self.events.push(format!("version {}.{}", major, minor));
+        }
+
+        fn on_interleaved_frame(&mut self, _: &Parser, channel: u8, length: uint) {
+            let data = self.take();
+            self.events.push(format!("frame {} {} {}", channel, length, data));
+        }
+
+        fn write(&mut self, _: &Parser, data: &[u8]) {
+            self.buffer.push_all(data);
+        }
+    }
+
+    fn parse(parser: &mut Parser, data: &str) -> (ParseResult, Vec<String>) {
+        let mut handler = ProtocolHandler::new();
+        let mut read = 0u;
+        loop {
+            match parser.parse(data.as_bytes().slice_from(read), &mut handler) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) => return (Err(e), handler.events),
+            }
+        }
+        (Ok(read), handler.events)
+    }
+
+    #[test]
+    fn test_rtsp_request() {
+        let data = "SET_PARAMETER rtsp://example.com/media.mp4 RTSP/1.0\r\nCSeq: 2\r\n\r\n\
+                    PLAY rtsp://example.com/media.mp4 RTSP/1.0\r\nCSeq: 3\r\nConnection: close\r\n\r\n";
+        let mut parser = Parser::with_protocol(ParseRequest, &RTSP);
+        let (result, events) = parse(&mut parser, data);
+        assert_eq!(result, Ok(data.len()));
+        assert_eq!(events, vec!("method SET_PARAMETER".to_string(), "url rtsp://example.com/media.mp4".to_string(),
+                                "version 1.0".to_string(), "complete".to_string(),
+                                "method PLAY".to_string(), "url rtsp://example.com/media.mp4".to_string(),
+                                "version 1.0".to_string(), "complete".to_string()));
+        assert!(!parser.should_keep_alive());
+        assert_eq!(parser.protocol().map(|p| p.name), Some("RTSP"));
+
+        let mut parser = Parser::with_protocol(ParseRequest, &RTSP);
+        let (_, events) = parse(&mut parser, "SETUP rtsp://example.com/media.mp4/track1 RTSP/1.0\r\n\r\n");
+        assert_eq!(events.get(0), &"method SETUP".to_string());
+        assert!(parser.should_keep_alive());
+    }
+
+    #[test]
+    fn test_rtsp_response() {
+        let data = "RTSP/1.0 200 OK\r\nCSeq: 2\r\nContent-Length: 5\r\n\r\nhello\
+                    RTSP/1.0 200 OK\r\nCSeq: 3\r\n\r\n";
+        let mut parser = Parser::with_protocol(ParseResponse, &RTSP);
+        let (result, events) = parse(&mut parser, data);
+        assert_eq!(result, Ok(data.len()));
+        assert_eq!(events, vec!("version 1.0".to_string(), "status 200".to_string(), "body hello".to_string(),
+                                "complete".to_string(),
+                                "version 1.0".to_string(), "status 200".to_string(), "complete".to_string()));
+        assert_eq!(parser.finish(&mut ProtocolHandler::new()), Ok(0));
+    }
+
+    #[test]
+    fn test_interleaved_frames() {
+        let data = "$\x00\x00\x04abcd$\x01\x00\x00RTSP/1.0 200 OK\r\nCSeq: 4\r\n\r\n$\x00\x00\x02ef";
+        let expected = vec!("frame 0 4 abcd".to_string(), "frame 1 0 ".to_string(), "version 1.0".to_string(),
+                            "status 200".to_string(), "complete".to_string(), "frame 0 2 ef".to_string());
+        let mut parser = Parser::with_protocol(ParseResponse, &RTSP);
+        let (result, events) = parse(&mut parser, data);
+        assert_eq!(result, Ok(data.len()));
+        assert_eq!(events, expected);
+
+        let mut parser = Parser::with_protocol(ParseResponse, &RTSP);
+        let mut handler = ProtocolHandler::new();
+        for byte in data.as_bytes().chunks(1) {
+            assert_eq!(parser.parse(byte, &mut handler), Ok(1));
+        }
+        assert_eq!(handler.events, expected);
+
+        let mut parser = Parser::with_protocol(ParseRequest, &RTSP);
+        assert_eq!(parse(&mut parser, "$\x00\x00\x04ab").val0(), Ok(6));
+        assert_eq!(parser.finish(&mut ProtocolHandler::new()), Err(UnexpectedEof));
+
+        let mut parser = Parser::with_protocol(ParseRequest, &RTSP);
+        parser.set_max_body_size(3);
+        assert_eq!(parse(&mut parser, "$\x00\x00\x04abcd").val0(), Err(BodyTooLarge));
+    }
+
+    #[test]
+    fn test_other_protocols() {
+        let data = "INVITE sip:bob@biloxi.com SIP/2.0\r\nContent-Length: 0\r\n\r\n";
+        let mut parser = Parser::with_protocol(ParseRequest, &SIP);
+        let (result, events) = parse(&mut parser, data);
+        assert_eq!(result, Ok(data.len()));
+        assert_eq!(events.get(0), &"method INVITE".to_string());
+        assert_eq!(events.get(2), &"version 2.0".to_string());
+
+        let data = "ICAP/1.0 204 No Content\r\nISTag: \"W3E4R7U9-L2E4-2\"\r\n\r\n";
+        let mut parser = Parser::with_protocol(ParseResponse, &ICAP);
+        let (result, events) = parse(&mut parser, data);
+        assert_eq!(result, Ok(data.len()));
+        assert_eq!(events, vec!("version 1.0".to_string(), "status 204".to_string(), "complete".to_string()));
+
+        let mut parser = Parser::with_protocol(ParseRequest, &SIP);
+        assert_eq!(parse(&mut parser, "$\x00\x00\x00").val0(), Err(InvalidMethod));
+
+        // a method which is a prefix of another one listed before.
+        let mut parser = Parser::with_protocol(ParseRequest, &PREFIXED);
+        let data = "FOO / TEST/1.0\r\n\r\n";
+        let (result, events) = parse(&mut parser, data);
+        assert_eq!(result, Ok(data.len()));
+        assert_eq!(events.get(0), &"method FOO".to_string());
+        let mut parser = Parser::with_protocol(ParseRequest, &PREFIXED);
+        assert_eq!(parse(&mut parser, "FOOBA / TEST/1.0\r\n\r\n").val0(), Err(InvalidMethod));
+    }
+
+    static PREFIXED: Protocol = Protocol {
+        name: "TEST",
+        versions: &[(1, 0)],
+        methods: &["FOOBAR", "FOO"],
+        interleaved: false,
+    };
+
+    #[test]
+    fn test_invalid_protocol() {
+        let cases = [
+            (&RTSP, ParseRequest, "GET / HTTP/1.1\r\n\r\n", InvalidMethod),
+            (&RTSP, ParseRequest, "play rtsp://example.com/ RTSP/1.0\r\n\r\n", InvalidMethod),
+            (&RTSP, ParseRequest, "PLAY rtsp://example.com/ HTTP/1.1\r\n\r\n", InvalidVersion),
+            (&RTSP, ParseRequest, "PLAY rtsp://example.com/ RTSP/2.0\r\n\r\n", InvalidVersion),
+            (&RTSP, ParseRequest, "PLAY rtsp://example.com/\r\n", InvalidRequestLine),
+            (&RTSP, ParseResponse, "HTTP/1.1 200 OK\r\n\r\n", InvalidMethod),
+            (&SIP, ParseResponse, "SIP/1.0 200 OK\r\n\r\n", InvalidVersion),
+        ];
+        for &(protocol, t, data, error) in cases.iter() {
+            let mut parser = Parser::with_protocol(t, protocol);
+            assert_eq!(parse(&mut parser, data).val0(), Err(error));
+        }
+    }
+}
+
+mod proxy {
+    use http::parser::*;
+    use http::proxy::*;
+    use http::reader::{Message, MessageReader};
+    use std::io::{MemReader, MemWriter};
+    use std::io::net::ip::{Ipv4Addr, Ipv6Addr};
+
+    fn read_message(data: &str, t: ParseType) -> (Message, MessageReader<MemReader>) {
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), t);
+        let message = reader.read_message().unwrap();
+        (message, reader)
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|&(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_forward_request() {
+        let (request, _) = read_message("GET http://user@example.com:8080/path?q=1#top HTTP/1.1\r\n\
+                                         Host: example.com:8080\r\nConnection: keep-alive, X-Hop\r\n\
+                                         X-Hop: 1\r\nProxy-Authorization: Basic Zm9vOmJhcg==\r\n\
+                                         Accept: */*\r\nTE: trailers\r\n\r\n", ParseRequest);
+        assert!(is_hop_by_hop(&request, "x-hop"));
+        assert!(is_hop_by_hop(&request, "Keep-Alive"));
+        assert!(!is_hop_by_hop(&request, "Accept"));
+
+        let forward = forward_request(&request, "bee", Some(Ipv4Addr(192, 0, 2, 60))).unwrap();
+        assert_eq!(forward.host, "example.com".to_string());
+        assert_eq!(forward.port, 8080);
+        assert_eq!(forward.target, "/path?q=1".to_string());
+        assert_eq!(forward.headers, headers([("Host", "example.com:8080"), ("Accept", "*/*"), ("Via", "1.1 bee"),
+                                             ("Forwarded", "for=192.0.2.60;host=\"example.com:8080\";proto=http")]));
+        assert!(!forward.has_body);
+
+        let (request, _) = read_message("GET http://example.com HTTP/1.0\r\n\r\n", ParseRequest);
+        let forward = forward_request(&request, "bee", Some(Ipv6Addr(0, 0, 0, 0, 0, 0, 0, 1))).unwrap();
+        assert_eq!(forward.target, "/".to_string());
+        assert_eq!(forward.headers.get(1), &("Via".to_string(), "1.0 bee".to_string()));
+        assert!(forward.headers.get(2).ref1().as_slice().starts_with("for=\"["));
+
+        let (request, _) = read_message("GET /path HTTP/1.1\r\nHost: example.com\r\n\r\n", ParseRequest);
+        assert_eq!(forward_request(&request, "bee", None), None);
+        let (request, _) = read_message("CONNECT example.com:443 HTTP/1.1\r\n\r\n", ParseRequest);
+        assert_eq!(forward_request(&request, "bee", None), None);
+    }
+
+    #[test]
+    fn test_write_request() {
+        let (request, mut reader) = read_message("POST http://example.com/upload HTTP/1.1\r\n\
+                                                  Transfer-Encoding: chunked\r\n\r\n3\r\nbee\r\n0\r\n\r\n",
+                                                 ParseRequest);
+        let forward = forward_request(&request, "bee", None).unwrap();
+        assert!(forward.has_body);
+        let out = write_request(MemWriter::new(), &forward, &mut reader.body()).unwrap().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "POST /upload HTTP/1.1\r\nHost: example.com\r\nVia: 1.1 bee\r\n\
+                    Forwarded: for=unknown;host=example.com;proto=http\r\nTransfer-Encoding: chunked\r\n\r\n\
+                    3\r\nbee\r\n0\r\n\r\n".to_string());
+
+        let (request, mut reader) = read_message("PUT http://example.com/ HTTP/1.1\r\nContent-Length: 3\r\n\r\nbee",
+                                                 ParseRequest);
+        let forward = forward_request(&request, "bee", None).unwrap();
+        let out = write_request(MemWriter::new(), &forward, &mut reader.body()).unwrap().unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.as_slice().contains("\r\nContent-Length: 3\r\n"));
+        assert!(out.as_slice().ends_with("\r\n\r\nbee"));
+    }
+
+    #[test]
+    fn test_write_response() {
+        let (request, _) = read_message("GET http://example.com/ HTTP/1.1\r\n\r\n", ParseRequest);
+        let (response, mut reader) = read_message("HTTP/1.0 200 OK\r\nConnection: close\r\n\
+                                                   Content-Type: text/plain\r\n\r\nhello", ParseResponse);
+        assert_eq!(forward_response_headers(&response, "bee"),
+                   headers([("Content-Type", "text/plain"), ("Via", "1.0 bee")]));
+        let out = write_response(MemWriter::new(), &request, &response, "bee", &mut reader.body()).unwrap().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nVia: 1.0 bee\r\n\
+                    Transfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n".to_string());
+
+        let (request, _) = read_message("HEAD http://example.com/ HTTP/1.0\r\n\r\n", ParseRequest);
+        let (response, mut reader) = read_message("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", ParseResponse);
+        let out = write_response(MemWriter::new(), &request, &response, "bee", &mut reader.body()).unwrap().unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "HTTP/1.0 200 OK\r\nContent-Length: 5\r\nVia: 1.1 bee\r\n\r\n".to_string());
+    }
+}
+
+mod proxy_protocol {
+    use http::parser::*;
+    use http::proxy_protocol::*;
+    use http::reader::MessageReader;
+    use std::io::MemReader;
+    use std::io::net::ip::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    fn v2_header(ver_cmd: u8, family: u8, body: &[u8]) -> Vec<u8> {
+        let mut data = vec!(0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a,
+                            ver_cmd, family, (body.len() >> 8) as u8, body.len() as u8);
+        data.push_all(body);
+        data
+    }
+
+    #[test]
+    fn test_v1() {
+        let data = "PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n\r\n".as_bytes();
+        let (header, len) = parse_proxy_header(data).unwrap().unwrap();
+        assert_eq!(len, 45);
+        assert_eq!(header.version, 1);
+        assert_eq!(header.command, CommandProxy);
+        assert_eq!(header.source, Some(InetAddress(SocketAddr { ip: Ipv4Addr(192, 0, 2, 1), port: 56324 })));
+        assert_eq!(header.destination, Some(InetAddress(SocketAddr { ip: Ipv4Addr(198, 51, 100, 2), port: 443 })));
+
+        let data = "PROXY TCP6 2001:db8::1 ::1 80 8080\r\n".as_bytes();
+        let (header, _) = parse_proxy_header(data).unwrap().unwrap();
+        assert_eq!(header.source, Some(InetAddress(SocketAddr { ip: Ipv6Addr(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), port: 80 })));
+
+        let (header, len) = parse_proxy_header("PROXY UNKNOWN ffff::1\r\n".as_bytes()).unwrap().unwrap();
+        assert_eq!(len, 23);
+        assert_eq!(header.command, CommandLocal);
+        assert_eq!(header.source, None);
+
+        // incomplete
+        assert_eq!(parse_proxy_header("PROX".as_bytes()), Ok(None));
+        assert_eq!(parse_proxy_header("PROXY TCP4 192.0.2.1".as_bytes()), Ok(None));
+    }
+
+    #[test]
+    fn test_invalid_v1() {
+        let cases = [
+            ("GET / HTTP/1.1\r\n\r\n", NotProxyHeader),
+            ("PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\n", InvalidProxyHeader),
+            ("PROXY TCP4 2001:db8::1 198.51.100.2 56324 443\r\n", InvalidProxyHeader),
+            ("PROXY TCP6 192.0.2.1 ::1 56324 443\r\n", InvalidProxyHeader),
+            ("PROXY TCP4 192.0.2.1 198.51.100.2 56324 0443\r\n", InvalidProxyHeader),
+            ("PROXY TCP4 192.0.2.1 198.51.100.2 65536 443\r\n", InvalidProxyHeader),
+            ("PROXY TCP4  192.0.2.1 198.51.100.2 56324 443\r\n", InvalidProxyHeader),
+            ("PROXY UDP4 192.0.2.1 198.51.100.2 56324 443\r\n", InvalidProxyHeader),
+        ];
+        for &(data, error) in cases.iter() {
+            assert_eq!(parse_proxy_header(data.as_bytes()), Err(error));
+        }
+        let mut long = "PROXY UNKNOWN ".as_bytes().to_vec();
+        long.grow(100, &0x61u8);
+        assert_eq!(parse_proxy_header(long.as_slice()), Err(InvalidProxyHeader));
+    }
+
+    #[test]
+    fn test_v2() {
+        let body = [192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb,
+                    PP2_TYPE_AUTHORITY, 0x00, 0x0b, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x63, 0x6f, 0x6d,
+                    PP2_TYPE_NOOP, 0x00, 0x00];
+        let mut data = v2_header(0x21, 0x11, body);
+        let (header, len) = parse_proxy_header(data.as_slice()).unwrap().unwrap();
+        assert_eq!(len, data.len());
+        assert_eq!(header.version, 2);
+        assert_eq!(header.command, CommandProxy);
+        assert_eq!(header.source, Some(InetAddress(SocketAddr { ip: Ipv4Addr(192, 0, 2, 1), port: 56324 })));
+        assert_eq!(header.destination, Some(InetAddress(SocketAddr { ip: Ipv4Addr(198, 51, 100, 2), port: 443 })));
+        assert_eq!(header.tlv(PP2_TYPE_AUTHORITY), Some("example.com".as_bytes()));
+        assert_eq!(header.tlv(PP2_TYPE_NOOP).map(|v| v.len()), Some(0));
+        assert_eq!(header.tlv(PP2_TYPE_ALPN), None);
+        assert_eq!(parse_proxy_header(data.slice_to(data.len() - 1)), Ok(None));
+        assert_eq!(parse_proxy_header(data.slice_to(10)), Ok(None));
+
+        // addresses of LOCAL command are ignored
+        let (header, _) = parse_proxy_header(v2_header(0x20, 0x11, body).as_slice()).unwrap().unwrap();
+        assert_eq!(header.command, CommandLocal);
+        assert_eq!(header.source, None);
+
+        let mut body = Vec::from_elem(36, 0u8);
+        *body.get_mut(15) = 1;
+        *body.get_mut(31) = 2;
+        *body.get_mut(33) = 80;
+        let (header, _) = parse_proxy_header(v2_header(0x21, 0x21, body.as_slice()).as_slice()).unwrap().unwrap();
+        assert_eq!(header.source, Some(InetAddress(SocketAddr { ip: Ipv6Addr(0, 0, 0, 0, 0, 0, 0, 1), port: 80 })));
+        assert_eq!(header.destination, Some(InetAddress(SocketAddr { ip: Ipv6Addr(0, 0, 0, 0, 0, 0, 0, 2), port: 0 })));
+
+        let mut body = Vec::from_elem(216, 0u8);
+        body.as_mut_slice().mut_slice_to(4).copy_from("/src".as_bytes());
+        let (header, _) = parse_proxy_header(v2_header(0x21, 0x31, body.as_slice()).as_slice()).unwrap().unwrap();
+        assert_eq!(header.source, Some(UnixAddress("/src".as_bytes().to_vec())));
+        assert_eq!(header.destination, Some(UnixAddress(Vec::new())));
+
+        let (header, _) = parse_proxy_header(v2_header(0x21, 0x00, []).as_slice()).unwrap().unwrap();
+        assert_eq!(header.source, None);
+
+        data.push_all("GET / HTTP/1.1\r\n\r\n".as_bytes());
+        assert_eq!(parse_proxy_header(data.as_slice()).unwrap().unwrap().val1(), len);
+    }
+
+    #[test]
+    fn test_invalid_v2() {
+        let body = [192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb];
+        assert_eq!(parse_proxy_header(v2_header(0x11, 0x11, body).as_slice()), Err(UnsupportedProxyVersion));
+        assert_eq!(parse_proxy_header(v2_header(0x22, 0x11, body).as_slice()), Err(UnsupportedProxyVersion));
+        assert_eq!(parse_proxy_header(v2_header(0x21, 0x21, body).as_slice()), Err(InvalidProxyHeader));
+        assert_eq!(parse_proxy_header(v2_header(0x21, 0x41, body).as_slice()), Err(InvalidProxyHeader));
+        let mut truncated_tlv = body.to_vec();
+        truncated_tlv.push_all([PP2_TYPE_ALPN, 0x00, 0x02, 0x68]);
+        assert_eq!(parse_proxy_header(v2_header(0x21, 0x11, truncated_tlv.as_slice()).as_slice()),
+                   Err(InvalidProxyHeader));
+    }
+
+    #[test]
+    fn test_read_proxy_header() {
+        let body = [192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb];
+        let request = "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        for proxy in [v2_header(0x21, 0x11, body), "PROXY UNKNOWN\r\n".as_bytes().to_vec()].iter() {
+            let mut data = proxy.clone();
+            data.push_all(request.as_bytes());
+            let mut stream = MemReader::new(data);
+            let header = read_proxy_header(&mut stream).unwrap().unwrap();
+            assert_eq!(header.version, if proxy.len() == 28 { 2 } else { 1 });
+            let mut reader = MessageReader::new(stream, ParseRequest);
+            assert_eq!(reader.read_message().unwrap().url, Some("/".to_string()));
+        }
+
+        // bytes read without the header are the start of the request.
+        for &(data, read) in [(request, "G"), ("PROPFIND / HTTP/1.1\r\n\r\n", "PROP")].iter() {
+            let mut stream = MemReader::new(data.as_bytes().to_vec());
+            let bytes = read_proxy_header(&mut stream).unwrap().unwrap_err();
+            assert_eq!(bytes.as_slice(), read.as_bytes());
+            let mut reader = MessageReader::with_buffer(stream, Parser::new(ParseRequest), bytes);
+            assert_eq!(reader.read_message().unwrap().url, Some("/".to_string()));
+        }
+        let mut stream = MemReader::new("PROXY TCP4 192.0.2.1".as_bytes().to_vec());
+        assert!(read_proxy_header(&mut stream).is_err());
+    }
+}
+
+mod range {
+    use http::HTTP_1_1;
+    use http::range::*;
+    use http::serializer::MessageWriter;
+    use http::status;
+    use std::io::{MemReader, MemWriter};
+    use std::str::from_utf8;
+    use time::Timespec;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-499, -500,9500-"),
+                   Some(vec![ByteRangeFromTo(0, 499), ByteRangeSuffix(500), ByteRangeFrom(9500)]));
+        assert_eq!(parse_range("Bytes = ,5-5,"), Some(vec![ByteRangeFromTo(5, 5)]));
+        assert_eq!(parse_range("bytes=5-4"), None);
+        assert_eq!(parse_range("bytes=-"), None);
+        assert_eq!(parse_range("bytes=1-2-3"), None);
+        assert_eq!(parse_range("bytes=+1-2"), None);
+        assert_eq!(parse_range("bytes="), None);
+        assert_eq!(parse_range("items=0-1"), None);
+        assert_eq!(parse_range("0-1"), None);
+    }
+
+    #[test]
+    fn test_satisfiable_ranges() {
+        let ranges = [ByteRangeFromTo(0, 499), ByteRangeFromTo(900, 2000), ByteRangeFrom(1000),
+                      ByteRangeSuffix(300), ByteRangeSuffix(0), ByteRangeSuffix(5000)];
+        assert_eq!(satisfiable_ranges(ranges, 1000), vec![(0, 999)]);
+        assert_eq!(satisfiable_ranges([ByteRangeFrom(0), ByteRangeSuffix(1)], 0), vec![]);
+
+        // overlapping and adjacent ranges are coalesced in order.
+        let ranges = [ByteRangeFromTo(8, 9), ByteRangeFromTo(0, 1), ByteRangeFromTo(2, 3), ByteRangeFromTo(1, 2)];
+        assert_eq!(satisfiable_ranges(ranges, 10), vec![(0, 3), (8, 9)]);
+        let ranges = [ByteRangeSuffix(2), ByteRangeFromTo(5, 6)];
+        assert_eq!(satisfiable_ranges(ranges, 10), vec![(5, 6), (8, 9)]);
+
+        // too many ranges are sent as a single range.
+        let ranges: Vec<ByteRange> = range(0, MAX_RANGES + 1).map(|i| ByteRangeFromTo(i * 4 + 2, i * 4 + 2)).collect();
+        assert_eq!(satisfiable_ranges(ranges.as_slice(), 1000), vec![(2, MAX_RANGES * 4 + 2)]);
+        let ranges: Vec<ByteRange> = range(0, MAX_RANGES).map(|i| ByteRangeFromTo(i * 4, i * 4)).collect();
+        assert_eq!(satisfiable_ranges(ranges.as_slice(), 1000).len(), MAX_RANGES);
+    }
+
+    #[test]
+    fn test_content_range() {
+        let content_range = ContentRange { range: Some((0, 499)), length: Some(1234) };
+        assert_eq!(format!("{}", content_range).as_slice(), "bytes 0-499/1234");
+        assert_eq!(ContentRange::parse("bytes 0-499/1234"), Some(content_range));
+        assert_eq!(ContentRange::parse("bytes 0-499/*"), Some(ContentRange { range: Some((0, 499)), length: None }));
+        let unsatisfied = ContentRange { range: None, length: Some(1234) };
+        assert_eq!(format!("{}", unsatisfied).as_slice(), "bytes */1234");
+        assert_eq!(ContentRange::parse("bytes */1234"), Some(unsatisfied));
+        assert_eq!(ContentRange::parse("bytes */*"), None);
+        assert_eq!(ContentRange::parse("bytes 0-1234/1234"), None);
+        assert_eq!(ContentRange::parse("bytes 5-4/10"), None);
+        assert_eq!(ContentRange::parse("items 0-1/10"), None);
+    }
+
+    #[test]
+    fn test_if_range() {
+        let modified = Timespec::new(784111777, 0);
+        assert!(if_range_matches("\"abc\"", Some("\"abc\""), None));
+        assert!(!if_range_matches("\"abc\"", Some("\"xyz\""), None));
+        assert!(!if_range_matches("W/\"abc\"", Some("W/\"abc\""), None));
+        assert!(!if_range_matches("\"abc\"", None, Some(modified)));
+        assert!(if_range_matches("Sun, 06 Nov 1994 08:49:37 GMT", None, Some(modified)));
+        assert!(!if_range_matches("Sun, 06 Nov 1994 08:49:38 GMT", None, Some(modified)));
+        assert!(!if_range_matches("Sun, 06 Nov 1994 08:49:37 GMT", Some("\"abc\""), None));
+    }
+
+    fn write(ranges: &[(uint, uint)]) -> String {
+        let mut w = MessageWriter::new(MemWriter::new());
+        w.status_line(HTTP_1_1, status::PARTIAL_CONTENT).unwrap();
+        let mut body = MemReader::new("0123456789".as_bytes().to_vec());
+        let writer = write_partial_content(w, ranges, 10, "text/plain", "B", &mut body).unwrap();
+        String::from_utf8(writer.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_write_single_range() {
+        assert_eq!(write([(2, 5)]).as_slice(),
+                   "HTTP/1.1 206 Partial Content\r\n\
+                    Content-Range: bytes 2-5/10\r\n\
+                    Content-Type: text/plain\r\n\
+                    Content-Length: 4\r\n\
+                    \r\n\
+                    2345");
+    }
+
+    #[test]
+    fn test_write_invalid_ranges() {
+        for ranges in [vec![], vec![(0, 3), (2, 5)], vec![(4, 5), (0, 1)], vec![(8, 10)],
+                       range(0, MAX_RANGES + 1).map(|i| (i, i)).collect()].iter() {
+            let mut w = MessageWriter::new(MemWriter::new());
+            w.status_line(HTTP_1_1, status::PARTIAL_CONTENT).unwrap();
+            let mut body = MemReader::new("0123456789".as_bytes().to_vec());
+            assert!(write_partial_content(w, ranges.as_slice(), 10, "text/plain", "B", &mut body).is_err());
+        }
+    }
+
+    #[test]
+    fn test_byteranges() {
+        let body = "--B\r\n\
+                    Content-Type: text/plain\r\n\
+                    Content-Range: bytes 0-1/10\r\n\
+                    \r\n\
+                    01\r\n\
+                    --B\r\n\
+                    Content-Type: text/plain\r\n\
+                    Content-Range: bytes 8-9/10\r\n\
+                    \r\n\
+                    89\r\n\
+                    --B--\r\n";
+        let expected = format!("HTTP/1.1 206 Partial Content\r\n\
+                                Content-Type: multipart/byteranges; boundary=B\r\n\
+                                Content-Length: {}\r\n\
+                                \r\n\
+                                {}", body.len(), body);
+        assert_eq!(write([(0, 1), (8, 9)]), expected);
+
+        let parts = parse_byteranges("multipart/byteranges; boundary=B", body.as_bytes()).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].range, ContentRange { range: Some((0, 1)), length: Some(10) });
+        assert_eq!(parts[0].content_type, Some("text/plain".to_string()));
+        assert_eq!(from_utf8(parts[1].data.as_slice()), Some("89"));
+
+        assert_eq!(parse_byteranges("multipart/form-data; boundary=B", body.as_bytes()), None);
+        assert_eq!(parse_byteranges("multipart/byteranges; boundary=B", body.slice_to(40).as_bytes()), None);
+        let no_range = "--B\r\nContent-Type: text/plain\r\n\r\n01\r\n--B--\r\n";
+        assert_eq!(parse_byteranges("multipart/byteranges; boundary=B", no_range.as_bytes()), None);
+    }
+}
+
+mod reader {
+    use http::*;
+    use http::parser::*;
+    use http::reader::*;
+    use std::io::{EndOfFile, IoResult, MemReader};
+
+    pub struct OneByteReader {
+        inner: MemReader,
+    }
+
+    impl OneByteReader {
+        pub fn new(data: &str) -> OneByteReader {
+            OneByteReader { inner: MemReader::new(data.as_bytes().to_vec()) }
+        }
+    }
+
+    impl Reader for OneByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> IoResult<uint> {
+            self.inner.read(buf.mut_slice_to(1))
+        }
+    }
+
+    static PIPELINED: &'static str = "POST /post HTTP/1.1\r\nHost: faultier.jp\r\nContent-Length: 8\r\n\r\nname=bee\
+                                      GET /chunked HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nbee\r\n0\r\n\r\n\
+                                      GET /last HTTP/1.1\r\nConnection: close\r\n\r\n";
+
+    fn assert_pipelined<R: Reader>(mut reader: MessageReader<R>) {
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.method, Some(HttpPost));
+        assert_eq!(message.url, Some("/post".to_string()));
+        assert_eq!(message.version, Some(HTTP_1_1));
+        assert_eq!(message.header("host"), Some("faultier.jp"));
+        assert!(message.keep_alive);
+        assert_eq!(reader.body().read_to_end().unwrap(), "name=bee".as_bytes().to_vec());
+
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.url, Some("/chunked".to_string()));
+        assert!(message.chunked);
+        assert_eq!(reader.body().read_to_end().unwrap(), "bee".as_bytes().to_vec());
+
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.url, Some("/last".to_string()));
+        assert!(!message.keep_alive);
+        assert_eq!(reader.body().read_to_end().unwrap(), vec!());
+
+        match reader.read_message() {
+            Err(ReadIoError(ref e)) if e.kind == EndOfFile => (),
+            other => fail!("unexpected result: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_pipelined() {
+        assert_pipelined(MessageReader::new(MemReader::new(PIPELINED.as_bytes().to_vec()), ParseRequest));
+    }
+
+    #[test]
+    fn test_read_pipelined_byte_by_byte() {
+        assert_pipelined(MessageReader::new(OneByteReader::new(PIPELINED), ParseRequest));
+    }
+
+    #[test]
+    fn test_skip_unread_body() {
+        let mut reader = MessageReader::new(MemReader::new(PIPELINED.as_bytes().to_vec()), ParseRequest);
+        assert_eq!(reader.read_message().unwrap().url, Some("/post".to_string()));
+        assert_eq!(reader.read_message().unwrap().url, Some("/chunked".to_string()));
+        assert_eq!(reader.read_message().unwrap().url, Some("/last".to_string()));
+    }
+
+    #[test]
+    fn test_read_response_headers() {
+        let data = "HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 0\r\n\r\n";
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), ParseResponse);
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.status, Some(StatusCode(200)));
+        assert_eq!(message.headers_named("set-cookie"), vec!("a=1", "b=2"));
+    }
+
+    #[test]
+    fn test_read_body_until_eof() {
+        let data = "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nHello, HTTP world!";
+        let mut reader = MessageReader::new(OneByteReader::new(data), ParseResponse);
+        let message = reader.read_message().unwrap();
+        assert!(!message.keep_alive);
+        assert_eq!(reader.body().read_to_end().unwrap(), "Hello, HTTP world!".as_bytes().to_vec());
+        match reader.read_message() {
+            Err(ReadIoError(ref e)) if e.kind == EndOfFile => (),
+            other => fail!("unexpected result: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_body_boundary() {
+        let data = "POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nbeeGET";
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), ParseRequest);
+        reader.read_message().unwrap();
+        let mut buf = [0u8, ..16];
+        assert_eq!(reader.body().read(buf.as_mut_slice()), Ok(3));
+        assert_eq!(buf.slice_to(3), "bee".as_bytes());
+        assert!(reader.body().read(buf.as_mut_slice()).is_err());
+        let (_, rest) = reader.unwrap();
+        assert_eq!(rest, "GET".as_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_read_protocol() {
+        let data = "RTSP/1.0 200 OK\r\nCSeq: 2\r\nContent-Length: 5\r\n\r\nhello$\x01\x00\x03bee\
+                    RTSP/1.0 454 Session Not Found\r\nCSeq: 3\r\n\r\n";
+        let parser = Parser::with_protocol(ParseResponse, &RTSP);
+        let mut reader = MessageReader::with_parser(OneByteReader::new(data), parser);
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.status, Some(StatusCode(200)));
+        assert_eq!(message.version, None);
+        assert_eq!(message.protocol_version, Some((1, 0)));
+        assert_eq!(reader.body().read_to_end().unwrap(), "hello".as_bytes().to_vec());
+
+        // interleaved frame is skipped.
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.status, Some(StatusCode(454)));
+        assert_eq!(message.headers, vec!(("CSeq".to_string(), "3".to_string())));
+
+        let data = "PLAY rtsp://example.com/media.mp4 RTSP/1.0\r\nCSeq: 4\r\n\r\n";
+        let parser = Parser::with_protocol(ParseRequest, &RTSP);
+        let mut reader = MessageReader::with_parser(MemReader::new(data.as_bytes().to_vec()), parser);
+        let message = reader.read_message().unwrap();
+        assert_eq!(message.method, None);
+        assert_eq!(message.protocol_method, Some("PLAY"));
+        assert_eq!(message.url, Some("rtsp://example.com/media.mp4".to_string()));
+        assert_eq!(message.protocol_version, Some((1, 0)));
+        assert!(message.keep_alive);
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let mut reader = MessageReader::new(MemReader::new("GET / HTTP/1.1\r\nHost".as_bytes().to_vec()), ParseRequest);
+        assert_eq!(reader.read_message(), Err(ReadParseError(UnexpectedEof)));
+    }
+
+    #[test]
+    fn test_read_invalid() {
+        let mut reader = MessageReader::new(MemReader::new("ZAP /pot HTTP/1.1\r\n\r\n".as_bytes().to_vec()), ParseRequest);
+        assert_eq!(reader.read_message(), Err(ReadParseError(InvalidMethod)));
+
+        let data = "POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort";
+        let mut reader = MessageReader::new(MemReader::new(data.as_bytes().to_vec()), ParseRequest);
+        assert!(reader.read_message().is_ok());
+        assert!(reader.body().read_to_end().is_err());
+    }
+}
+
+mod rewriter {
+    use http;
+    use http::parser::*;
+    use http::rewriter::*;
+    use std::io::MemWriter;
+
+    struct Mount;
+
+    impl RewriteFilter for Mount {
+        fn request_line(&mut self, _: &mut http::HttpMethod, url: &mut String, version: &mut http::HttpVersion) {
+            *url = format!("/app{}", url);
+            *version = http::HTTP_1_1;
+        }
+
+        fn status_line(&mut self, _: &mut http::HttpVersion, status: &mut http::StatusCode) {
+            if *status == http::status::OK { *status = http::status::NON_AUTHORITATIVE_INFORMATION }
+        }
+    }
+
+    struct Track;
+
+    impl RewriteFilter for Track {
+        fn protocol_request_line(&mut self, _: &mut &'static str, url: &mut String, _: &mut (uint, uint)) {
+            url.push_str("/track1");
+        }
+
+        fn protocol_status_line(&mut self, _: &mut (uint, uint), status: &mut http::StatusCode) {
+            if *status == http::status::OK { *status = http::status::CREATED }
+        }
+    }
+
+    fn rewrite<F: RewriteFilter>(rewriter: &mut Rewriter<MemWriter, F>, t: ParseType, data: &str) {
+        rewrite_with(Parser::new(t), rewriter, data);
+    }
+
+    fn rewrite_with<F: RewriteFilter>(mut parser: Parser, rewriter: &mut Rewriter<MemWriter, F>, data: &str) {
+        let data = data.as_bytes();
+        let mut read = 0u;
+        while read < data.len() {
+            read += parser.parse(data.slice_from(read), &mut *rewriter).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_header_rules() {
+        let mut rules = HeaderRules::new();
+        rules.remove("x-secret");
+        rules.rename("X-Old", "X-New");
+        rules.add("Via", "1.1 bee");
+        let mut rewriter = Rewriter::new(MemWriter::new(), rules);
+        rewrite(&mut rewriter, ParseRequest,
+                "POST /upload HTTP/1.1\r\nHost: example.com\r\nX-Secret: s\r\nX-Old: v\r\n\
+                 Transfer-Encoding: chunked\r\n\r\n3\r\nbee\r\n0\r\n\r\n\
+                 GET / HTTP/1.1\r\nHost: example.com\r\nX-Old: w\r\n\r\n");
+        assert_eq!(rewriter.error(), None);
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(),
+                   "POST /upload HTTP/1.1\r\nHost: example.com\r\nX-New: v\r\nTransfer-Encoding: chunked\r\n\
+                    Via: 1.1 bee\r\n\r\n3\r\nbee\r\n0\r\n\r\n\
+                    GET / HTTP/1.1\r\nHost: example.com\r\nX-New: w\r\nVia: 1.1 bee\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_start_line() {
+        let mut rewriter = Rewriter::new(MemWriter::new(), Mount);
+        rewrite(&mut rewriter, ParseRequest, "PUT /file HTTP/1.0\r\nContent-Length: 3\r\n\r\nbee");
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(),
+                   "PUT /app/file HTTP/1.1\r\nContent-Length: 3\r\n\r\nbee".to_string());
+
+        let mut rewriter = Rewriter::new(MemWriter::new(), Mount);
+        rewrite(&mut rewriter, ParseResponse, "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello");
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(),
+                   "HTTP/1.1 203 Non-Authoritative Information\r\nContent-Length: 5\r\n\r\nhello".to_string());
+
+        // response to HEAD request
+        let mut rewriter = Rewriter::new(MemWriter::new(), HeaderRules::new());
+        rewriter.set_skip_body(true);
+        rewrite(&mut rewriter, ParseResponse, "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n");
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(),
+                   "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_protocol() {
+        let mut rewriter = Rewriter::new(MemWriter::new(), Track);
+        rewrite_with(Parser::with_protocol(ParseRequest, &RTSP), &mut rewriter,
+                     "SETUP rtsp://example.com/media.mp4 RTSP/1.0\r\nCSeq: 3\r\n\r\n");
+        assert_eq!(rewriter.error(), None);
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(),
+                   "SETUP rtsp://example.com/media.mp4/track1 RTSP/1.0\r\nCSeq: 3\r\n\r\n".to_string());
+
+        // interleaved frames are written as is.
+        let mut rewriter = Rewriter::new(MemWriter::new(), Track);
+        rewrite_with(Parser::with_protocol(ParseResponse, &RTSP), &mut rewriter,
+                     "RTSP/1.0 200 OK\r\nCSeq: 3\r\nContent-Length: 5\r\n\r\nhello$\x01\x00\x03bee\
+                      RTSP/1.0 200 OK\r\nCSeq: 4\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(rewriter.error(), None);
+        assert_eq!(String::from_utf8(rewriter.unwrap().unwrap().unwrap()).unwrap(),
+                   "RTSP/1.0 201 Created\r\nCSeq: 3\r\nContent-Length: 5\r\n\r\nhello$\x01\x00\x03bee\
+                    RTSP/1.0 201 Created\r\nCSeq: 4\r\nContent-Length: 0\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_rewrite_error() {
+        let mut rules = HeaderRules::new();
+        rules.rename("Host", "Bad Header");
+        let mut rewriter = Rewriter::new(MemWriter::new(), rules);
+        rewrite(&mut rewriter, ParseRequest, "GET / HTTP/1.1\r\nHost: example.com\r\n\r\n\
+                                              GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        assert!(rewriter.error().is_some());
+        assert!(rewriter.unwrap().is_none());
+
+        let mut parser = Parser::new(ParseRequest);
+        let mut rewriter = Rewriter::new(MemWriter::new(), HeaderRules::new());
+        assert!(parser.parse("GET /\r\n".as_bytes(), &mut rewriter).is_ok());
+        assert!(rewriter.error().is_some());
+    }
+}
+
+mod serializer {
+    use http::*;
+    use http::encoding::*;
+    use http::parser::*;
+    use http::serializer::*;
+    use http::status;
+    use std::io::{InvalidInput, MemWriter};
+    use super::TestHandler;
+
+    fn written(writer: MemWriter) -> String {
+        String::from_utf8(writer.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_request_with_body() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.request_line(HttpPost, "/post", HTTP_1_1).unwrap();
+        writer.header("Host", "faultier.jp").unwrap();
+        let out = writer.body("name=bee".as_bytes()).unwrap();
+        assert_eq!(written(out),
+                   "POST /post HTTP/1.1\r\nHost: faultier.jp\r\nContent-Length: 8\r\n\r\nname=bee".to_string());
+    }
+
+    #[test]
+    fn test_request_without_body() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.request_line(HttpGet, "/", HTTP_1_0).unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(written(out), "GET / HTTP/1.0\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_protocol_start_line() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.protocol_request_line("ANNOUNCE", "rtsp://example.com/media.mp4", "RTSP", 1, 0).unwrap();
+        writer.header("CSeq", "2").unwrap();
+        let out = writer.body("v=0".as_bytes()).unwrap();
+        assert_eq!(written(out), "ANNOUNCE rtsp://example.com/media.mp4 RTSP/1.0\r\nCSeq: 2\r\n\
+                                  Content-Length: 3\r\n\r\nv=0".to_string());
+
+        // streamed body is never chunked.
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.protocol_status_line("RTSP", 1, 0, status::OK).unwrap();
+        assert_eq!(writer.write("v=0".as_bytes()).unwrap_err().kind, InvalidInput);
+
+        let mut writer = MessageWriter::new(MemWriter::new());
+        assert_eq!(writer.protocol_request_line("PLAY", "/", "RTSP/1.0", 1, 0).unwrap_err().kind, InvalidInput);
+        assert_eq!(writer.protocol_request_line("PL AY", "/", "RTSP", 1, 0).unwrap_err().kind, InvalidInput);
+    }
+
+    #[test]
+    fn test_response_chunked() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.header("Content-Type", "text/plain").unwrap();
+        writer.write("Hello, ".as_bytes()).unwrap();
+        writer.write("HTTP world!".as_bytes()).unwrap();
+        let out = written(writer.finish().unwrap());
+        assert_eq!(out, "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n\
+                         7\r\nHello, \r\nb\r\nHTTP world!\r\n0\r\n\r\n".to_string());
+
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = TestHandler::new();
+        assert_eq!(parser.parse(out.as_bytes(), &mut handler), Ok(out.len()));
+        assert!(handler.finished);
+        assert_eq!(handler.body, Some("Hello, HTTP world!".to_string()));
+    }
+
+    #[test]
+    fn test_response_http_1_0() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_0, status::OK).unwrap();
+        writer.write("streamed".as_bytes()).unwrap();
+        assert_eq!(written(writer.finish().unwrap()), "HTTP/1.0 200 OK\r\n\r\nstreamed".to_string());
+    }
+
+    #[test]
+    fn test_response_without_body() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::NOT_MODIFIED).unwrap();
+        assert!(writer.write("body".as_bytes()).is_err());
+
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.header("Content-Length", "42").unwrap();
+        writer.no_body();
+        assert_eq!(written(writer.finish().unwrap()), "HTTP/1.1 200 OK\r\nContent-Length: 42\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_response_compressed() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.content_coding(CodingGzip).unwrap();
+        let out = writer.body("Hello, HTTP world!".as_bytes()).unwrap().unwrap();
+        let head = "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\nContent-Length: ";
+        assert!(out.as_slice().starts_with(head.as_bytes()));
+
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        assert_eq!(parser.parse(out.as_slice(), &mut handler), Ok(out.len()));
+        assert_eq!(handler.error(), None);
+        assert_eq!(handler.unwrap().body, Some("Hello, HTTP world!".to_string()));
+    }
+
+    #[test]
+    fn test_response_compressed_chunked() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.content_coding(CodingDeflate).unwrap();
+        writer.write("Hello, ".as_bytes()).unwrap();
+        writer.write("HTTP world!".as_bytes()).unwrap();
+        let out = writer.finish().unwrap().unwrap();
+        let head = "HTTP/1.1 200 OK\r\nContent-Encoding: deflate\r\nVary: Accept-Encoding\r\n\
+                    Transfer-Encoding: chunked\r\n\r\n";
+        assert!(out.as_slice().starts_with(head.as_bytes()));
+
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        assert_eq!(parser.parse(out.as_slice(), &mut handler), Ok(out.len()));
+        assert!(handler.get_ref().finished);
+        assert_eq!(handler.unwrap().body, Some("Hello, HTTP world!".to_string()));
+
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.content_coding(CodingGzip).unwrap();
+        writer.no_body();
+        assert_eq!(written(writer.finish().unwrap()),
+                   "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\n\r\n".to_string());
+    }
+
+    #[test]
+    fn test_response_compressed_flush() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.content_coding(CodingGzip).unwrap();
+        writer.write("Hello, ".as_bytes()).unwrap();
+        writer.flush().unwrap();
+        // body written so far is decoded before the message is finished.
+        let head = writer.get_ref().get_ref().to_vec();
+        let mut parser = Parser::new(ParseResponse);
+        let mut handler = DecodingHandler::new(TestHandler::new());
+        assert_eq!(parser.parse(head.as_slice(), &mut handler), Ok(head.len()));
+        assert_eq!(handler.get_ref().buffer, "Hello, ".as_bytes().to_vec());
+
+        writer.write("HTTP world!".as_bytes()).unwrap();
+        let out = writer.finish().unwrap().unwrap();
+        let rest = out.slice_from(head.len());
+        assert_eq!(parser.parse(rest, &mut handler), Ok(rest.len()));
+        assert!(handler.get_ref().finished);
+        assert_eq!(handler.unwrap().body, Some("Hello, HTTP world!".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_content_coding() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        assert_eq!(writer.content_coding(CodingBrotli).unwrap_err().kind, InvalidInput);
+        writer.content_coding(CodingGzip).unwrap();
+        assert_eq!(writer.content_coding(CodingDeflate).unwrap_err().kind, InvalidInput);
+        assert_eq!(writer.header("Content-Length", "3").unwrap_err().kind, InvalidInput);
+
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.header("Content-Length", "3").unwrap();
+        assert_eq!(writer.content_coding(CodingGzip).unwrap_err().kind, InvalidInput);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut writer = MessageWriter::new(MemWriter::new());
+        assert!(writer.header("Host", "faultier.jp").is_err());
+        assert_eq!(writer.request_line(HttpGet, "/a b", HTTP_1_1).unwrap_err().kind, InvalidInput);
+        writer.request_line(HttpGet, "/", HTTP_1_1).unwrap();
+        assert_eq!(writer.header("X Name", "value").unwrap_err().kind, InvalidInput);
+        assert_eq!(writer.header("X-Name:", "value").unwrap_err().kind, InvalidInput);
+        assert_eq!(writer.header("X-Name", "value\r\nX-Injected: 1").unwrap_err().kind, InvalidInput);
+        assert_eq!(writer.header("Content-Length", "abc").unwrap_err().kind, InvalidInput);
+
+        let mut writer = MessageWriter::new(MemWriter::new());
+        writer.status_line(HTTP_1_1, status::OK).unwrap();
+        writer.header("Content-Length", "2").unwrap();
+        assert!(writer.write("abc".as_bytes()).is_err());
+    }
+}
+
+mod status {
+    use http::*;
+    use http::parser::*;
+    use http::status::*;
+    use super::TestHandler;
+
+    #[test]
+    fn test_status_code() {
+        assert_eq!(NOT_FOUND, StatusCode(404));
+        assert_eq!(NOT_FOUND.canonical_reason(), Some("Not Found"));
+        assert_eq!(StatusCode(299).canonical_reason(), None);
+        assert_eq!(format!("{}", OK), "200 OK".to_string());
+        assert_eq!(format!("{}", StatusCode(299)), "299".to_string());
+    }
+
+    #[test]
+    fn test_status_class() {
+        assert!(CONTINUE.is_informational());
+        assert!(NO_CONTENT.is_success());
+        assert!(PERMANENT_REDIRECT.is_redirection());
+        assert!(REQUEST_HEADER_FIELDS_TOO_LARGE.is_client_error());
+        assert!(NETWORK_AUTHENTICATION_REQUIRED.is_server_error());
+        assert!(!StatusCode(600).is_server_error());
+    }
+
+    #[test]
+    fn test_response_without_body() {
+        for msg in ["HTTP/1.1 100 Continue\r\n\r\n", "HTTP/1.1 204 No Content\r\n\r\n"].iter() {
+            let data = msg.as_bytes();
+            let mut parser = Parser::new(ParseResponse);
+            let mut handler = TestHandler::new();
+            assert_eq!(parser.parse(data, &mut handler), Ok(data.len()));
+            assert!(handler.finished);
+        }
+    }
+}
+
+mod url {
+    use http::{authority, split_url};
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(split_url("http://example.com"), Some(("example.com".to_string(), 80, "/".to_string())));
+        assert_eq!(split_url("HTTP://user@example.com:8080/a?b#c"),
+                   Some(("example.com".to_string(), 8080, "/a?b".to_string())));
+        assert_eq!(split_url("http://example.com?q"), Some(("example.com".to_string(), 80, "/?q".to_string())));
+        assert_eq!(split_url("https://example.com/"), None);
+        assert_eq!(split_url("http://example.com:port/"), None);
+        assert_eq!(split_url("http:///"), None);
+        assert_eq!(split_url("http://[::1]:8080/a"), Some(("::1".to_string(), 8080, "/a".to_string())));
+        assert_eq!(split_url("http://[2001:db8::1]"), Some(("2001:db8::1".to_string(), 80, "/".to_string())));
+        assert_eq!(split_url("http://[::1/"), None);
+        assert_eq!(split_url("http://[]/"), None);
+    }
+
+    #[test]
+    fn test_authority() {
+        assert_eq!(authority("example.com", 80).as_slice(), "example.com");
+        assert_eq!(authority("example.com", 8080).as_slice(), "example.com:8080");
+        assert_eq!(authority("::1", 8080).as_slice(), "[::1]:8080");
+    }
+}
+
+mod urlencoded {
+    use http::urlencoded::*;
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode("name=bee&password=p%40ss+word".as_bytes()),
+                   Ok(vec!(pair("name", "bee"), pair("password", "p@ss word"))));
+        assert_eq!(decode("a=1&&flag&b=&=c".as_bytes()),
+                   Ok(vec!(pair("a", "1"), pair("flag", ""), pair("b", ""), pair("", "c"))));
+        assert_eq!(decode("x=%zz%4&y=a=b".as_bytes()),
+                   Ok(vec!(pair("x", "%zz%4"), pair("y", "a=b"))));
+    }
+
+    #[test]
+    fn test_decode_streaming() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed("na%".as_bytes()), Ok(vec!()));
+        assert_eq!(decoder.feed("6De=b".as_bytes()), Ok(vec!()));
+        assert_eq!(decoder.feed("e%2".as_bytes()), Ok(vec!()));
+        assert_eq!(decoder.feed("1&x".as_bytes()), Ok(vec!(pair("name", "be!"))));
+        assert_eq!(decoder.finish(), Ok(Some(pair("x", ""))));
+        assert_eq!(decoder.finish(), Ok(None));
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        let limits = Limits { max_pairs: 2, max_key_length: 4, max_value_length: 4 };
+        assert_eq!(decode_with_limits("a=1&b=2&c=3".as_bytes(), limits.clone()), Err(TooManyPairs));
+        assert_eq!(decode_with_limits("abcde=1".as_bytes(), limits.clone()), Err(KeyTooLong));
+        assert_eq!(decode_with_limits("a=%41%41%41%41%41".as_bytes(), limits.clone()), Err(ValueTooLong));
+
+        let mut decoder = Decoder::with_limits(limits);
+        assert_eq!(decoder.feed("abcde".as_bytes()), Err(KeyTooLong));
+        assert_eq!(decoder.feed("=1".as_bytes()), Err(KeyTooLong));
+        assert_eq!(decoder.finish(), Err(KeyTooLong));
+
+        // the decoder is reset by finish.
+        assert_eq!(decoder.feed("a=1&b".as_bytes()), Ok(vec!(pair("a", "1"))));
+        assert_eq!(decoder.finish(), Ok(Some(pair("b", ""))));
+
+        // pairs completed before the error are returned.
+        assert_eq!(decoder.feed("a=1&b=2&c=3&".as_bytes()), Ok(vec!(pair("a", "1"), pair("b", "2"))));
+        assert_eq!(decoder.feed("d=4".as_bytes()), Err(TooManyPairs));
+        assert_eq!(decoder.finish(), Err(TooManyPairs));
+        assert_eq!(decoder.finish(), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(parse_query("/search?q=rust+http&page=2#top"),
+                   Ok(vec!(pair("q", "rust http"), pair("page", "2"))));
+        assert_eq!(parse_query("/search"), Ok(vec!()));
+        assert_eq!(parse_query("/search?"), Ok(vec!()));
+    }
+}
+
 pub struct TestHandler {
     started: bool,
     finished: bool,
     version: Option<HttpVersion>,
     method: Option<HttpMethod>,
     url: Option<String>,
-    status_code: uint,
+    status_code: StatusCode,
     headers_finished: bool,
     headers: HashMap<String, String>,
     body: Option<String>,
@@ -258,7 +2462,7 @@ impl TestHandler {
             version: None,
             method: None,
             url: None,
-            status_code: 0,
+            status_code: StatusCode(0),
             headers_finished: false,
             headers: HashMap::new(),
             buffer: Vec::new(),
@@ -288,7 +2492,7 @@ impl MessageHandler for TestHandler {
         self.version = Some(version);
     }
 
-    fn on_status(&mut self, _: &Parser, status: uint) {
+    fn on_status(&mut self, _: &Parser, status: StatusCode) {
         self.status_code = status;
     }
 
@@ -0,0 +1,241 @@
+//! Decoder for `application/x-www-form-urlencoded` data and URL query strings.
+
+#![experimental]
+
+use std::default::Default;
+
+/// Limits applied while decoding.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct Limits {
+    /// Maximum number of pairs.
+    pub max_pairs: uint,
+    /// Maximum length of a decoded key in bytes.
+    pub max_key_length: uint,
+    /// Maximum length of a decoded value in bytes.
+    pub max_value_length: uint,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_pairs: 1000,
+            max_key_length: 1024,
+            max_value_length: 1024 * 1024,
+        }
+    }
+}
+
+/// A list specifying categories of decode errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum DecodeError {
+    /// Number of pairs exceeded `Limits::max_pairs`.
+    TooManyPairs,
+    /// Length of a key exceeded `Limits::max_key_length`.
+    KeyTooLong,
+    /// Length of a value exceeded `Limits::max_value_length`.
+    ValueTooLong,
+}
+
+/// Result of decoding.
+pub type DecodeResult<T> = Result<T, DecodeError>;
+
+/// Streaming urlencoded decoder.
+///
+/// Data can be fed in arbitrary pieces, e.g. from `MessageHandler::write`.
+/// `+` is decoded as a space, and `%XX` as a byte. Malformed percent sequences are kept as is,
+/// and invalid UTF-8 sequences are replaced with U+FFFD.
+pub struct Decoder {
+    limits: Limits,
+    key: Vec<u8>,
+    value: Vec<u8>,
+    in_value: bool,
+    percent: PercentState,
+    pairs: uint,
+    error: Option<DecodeError>,
+}
+
+impl Decoder {
+    /// Create a new `Decoder` with default limits.
+    pub fn new() -> Decoder {
+        Decoder::with_limits(Default::default())
+    }
+
+    /// Create a new `Decoder` with the given limits.
+    pub fn with_limits(limits: Limits) -> Decoder {
+        Decoder {
+            limits: limits,
+            key: Vec::new(),
+            value: Vec::new(),
+            in_value: false,
+            percent: PercentNone,
+            pairs: 0,
+            error: None,
+        }
+    }
+
+    /// Decode a piece of data, and returns pairs completed in it.
+    ///
+    /// If an error occurs after some pairs are completed, the pairs are returned, and the error
+    /// is returned by following calls until `finish`.
+    pub fn feed(&mut self, data: &[u8]) -> DecodeResult<Vec<(String, String)>> {
+        match self.error {
+            Some(e) => return Err(e),
+            None => (),
+        }
+        let mut pairs = Vec::new();
+        for &byte in data.iter() {
+            match self.consume(byte, &mut pairs) {
+                Ok(()) => (),
+                Err(e) => {
+                    self.error = Some(e);
+                    return if pairs.is_empty() { Err(e) } else { Ok(pairs) };
+                }
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Finish decoding, and returns the last pair if exists.
+    ///
+    /// The decoder is reset even on error, and can be used for another data.
+    pub fn finish(&mut self) -> DecodeResult<Option<(String, String)>> {
+        let result = match self.error.take() {
+            Some(e) => Err(e),
+            None => {
+                let mut pairs = Vec::new();
+                self.end_pair(&mut pairs).map(|()| pairs.pop())
+            }
+        };
+        self.key.clear();
+        self.value.clear();
+        self.in_value = false;
+        self.percent = PercentNone;
+        self.pairs = 0;
+        result
+    }
+
+    fn consume(&mut self, byte: u8, pairs: &mut Vec<(String, String)>) -> DecodeResult<()> {
+        match self.percent {
+            PercentStart => {
+                if unhex(byte).is_some() {
+                    self.percent = PercentHigh(byte);
+                    return Ok(());
+                }
+                self.percent = PercentNone;
+                try!(self.push(PERCENT));
+            }
+            PercentHigh(high) => {
+                self.percent = PercentNone;
+                match (unhex(high), unhex(byte)) {
+                    (Some(h), Some(l)) => return self.push((h << 4) | l),
+                    _ => {
+                        try!(self.push(PERCENT));
+                        try!(self.push(high));
+                    }
+                }
+            }
+            PercentNone => (),
+        }
+        match byte {
+            AMPERSAND => self.end_pair(pairs),
+            EQUAL if !self.in_value => { self.in_value = true; Ok(()) }
+            PLUS => self.push(SPACE),
+            PERCENT => { self.percent = PercentStart; Ok(()) }
+            _ => self.push(byte),
+        }
+    }
+
+    fn push(&mut self, byte: u8) -> DecodeResult<()> {
+        if self.in_value {
+            if self.value.len() >= self.limits.max_value_length { return Err(ValueTooLong) }
+            self.value.push(byte);
+        } else {
+            if self.key.len() >= self.limits.max_key_length { return Err(KeyTooLong) }
+            self.key.push(byte);
+        }
+        Ok(())
+    }
+
+    fn end_pair(&mut self, pairs: &mut Vec<(String, String)>) -> DecodeResult<()> {
+        match self.percent {
+            PercentStart => try!(self.push(PERCENT)),
+            PercentHigh(high) => {
+                try!(self.push(PERCENT));
+                try!(self.push(high));
+            }
+            PercentNone => (),
+        }
+        self.percent = PercentNone;
+        if self.key.is_empty() && self.value.is_empty() && !self.in_value {
+            // empty sequence, e.g. "a=1&&b=2"
+            return Ok(());
+        }
+        if self.pairs >= self.limits.max_pairs { return Err(TooManyPairs) }
+        self.pairs += 1;
+        let key = String::from_utf8_lossy(self.key.as_slice()).into_string();
+        let value = String::from_utf8_lossy(self.value.as_slice()).into_string();
+        pairs.push((key, value));
+        self.key.clear();
+        self.value.clear();
+        self.in_value = false;
+        Ok(())
+    }
+}
+
+/// Decode whole urlencoded data with default limits.
+pub fn decode(data: &[u8]) -> DecodeResult<Vec<(String, String)>> {
+    decode_with_limits(data, Default::default())
+}
+
+/// Decode whole urlencoded data with the given limits.
+pub fn decode_with_limits(data: &[u8], limits: Limits) -> DecodeResult<Vec<(String, String)>> {
+    let mut decoder = Decoder::with_limits(limits);
+    let mut pairs = try!(decoder.feed(data));
+    match try!(decoder.finish()) {
+        Some(pair) => pairs.push(pair),
+        None => (),
+    }
+    Ok(pairs)
+}
+
+/// Decode the query part of request URL with default limits.
+///
+/// Returns an empty list if URL has no query.
+pub fn parse_query(url: &str) -> DecodeResult<Vec<(String, String)>> {
+    parse_query_with_limits(url, Default::default())
+}
+
+/// Decode the query part of request URL with the given limits.
+pub fn parse_query_with_limits(url: &str, limits: Limits) -> DecodeResult<Vec<(String, String)>> {
+    let url = match url.find('#') {
+        Some(pos) => url.slice_to(pos),
+        None => url,
+    };
+    match url.find('?') {
+        Some(pos) => decode_with_limits(url.slice_from(pos + 1).as_bytes(), limits),
+        None => Ok(Vec::new()),
+    }
+}
+
+static SPACE: u8     = 0x20;
+static PERCENT: u8   = 0x25;
+static AMPERSAND: u8 = 0x26;
+static PLUS: u8      = 0x2b;
+static EQUAL: u8     = 0x3d;
+
+#[inline]
+fn unhex(b: u8) -> Option<u8> {
+    match b {
+        0x30..0x39 => Some(b - 0x30),
+        0x41..0x46 => Some(b - 0x41 + 10),
+        0x61..0x66 => Some(b - 0x61 + 10),
+        _ => None,
+    }
+}
+
+#[deriving(PartialEq, Eq, Clone, Show)]
+enum PercentState {
+    PercentNone,
+    PercentStart,
+    PercentHigh(u8),
+}
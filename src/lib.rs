@@ -97,6 +97,9 @@ fn main() {
 #![feature(globs, macro_rules)]
 #![experimental]
 
+extern crate flate;
+extern crate serialize;
+extern crate time;
 #[cfg(test)] extern crate test;
 
 pub use self::version::version;
@@ -116,4 +119,7 @@ pub mod version {
     }
 }
 
+pub mod client;
 pub mod http;
+pub mod server;
+pub mod websocket;
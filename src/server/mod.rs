@@ -0,0 +1,276 @@
+//! Minimal HTTP/1.1 server.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::io::{Acceptor, Listener, IoResult, TcpListener, TcpStream, EndOfFile};
+
+use http;
+use http::cookie::SetCookie;
+use http::encoding::{AcceptEncoding, ContentCoding, CodingIdentity, CodingGzip, CodingDeflate};
+use http::parser::{Parser, ParseRequest, ParseError, HeaderTooLarge, BodyTooLarge, Http2Preface};
+use http::reader::{Message, MessageReader, ReadIoError, ReadParseError};
+use http::serializer::MessageWriter;
+use http::status;
+
+/// Request handler.
+///
+/// A handler is cloned for each connection, and called for each request on the connection.
+pub trait Handler: Clone + Send {
+    /// Handle a request, and fill the response.
+    ///
+    /// Unread request body is skipped after this call.
+    fn handle(&mut self, request: &Message, body: &mut Reader, response: &mut Response);
+}
+
+/// Response built by `Handler`.
+///
+/// `Content-Length` and `Connection` headers are written by the server. Body is compressed by the
+/// server unless `Content-Encoding` is set.
+pub struct Response {
+    /// Status code, `200 OK` by default.
+    pub status: http::StatusCode,
+    /// Header fields.
+    pub headers: Vec<(String, String)>,
+    /// Message body.
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Create an empty `200 OK` response.
+    pub fn new() -> Response {
+        Response {
+            status: status::OK,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Set a header field, replacing fields with the same name.
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|&(ref n, _)| !n.as_slice().eq_ignore_ascii_case(name));
+        self.headers.push((name.to_string(), value.to_string()));
+    }
+
+    /// Append a `Set-Cookie` header. Unlike `set_header`, existing cookies are kept.
+    ///
+    /// Returns false and adds nothing if the cookie is invalid, see `SetCookie::is_valid`.
+    pub fn add_cookie(&mut self, cookie: &SetCookie) -> bool {
+        if !cookie.is_valid() { return false }
+        self.headers.push(("Set-Cookie".to_string(), format!("{}", cookie)));
+        true
+    }
+
+    /// Find the first header value with the name.
+    pub fn header<'a>(&'a self, name: &str) -> Option<&'a str> {
+        self.headers.iter()
+            .find(|&&(ref n, _)| n.as_slice().eq_ignore_ascii_case(name))
+            .map(|&(_, ref v)| v.as_slice())
+    }
+}
+
+impl Writer for Response {
+    fn write(&mut self, buf: &[u8]) -> IoResult<()> {
+        self.body.push_all(buf);
+        Ok(())
+    }
+}
+
+/// HTTP/1.1 server which handles each connection in a new task.
+#[deriving(Clone)]
+pub struct Server<H> {
+    handler: H,
+    timeout: Option<u64>,
+    max_header_size: uint,
+    max_body_size: uint,
+    compress_min_size: Option<uint>,
+}
+
+impl<H: Handler> Server<H> {
+    /// Create a new `Server`.
+    pub fn new(handler: H) -> Server<H> {
+        Server {
+            handler: handler,
+            timeout: None,
+            max_header_size: 8192,
+            max_body_size: ::std::uint::MAX,
+            compress_min_size: Some(256),
+        }
+    }
+
+    /// Set read timeout in milliseconds, applied to each request including idle keep-alive connections.
+    pub fn set_timeout(&mut self, timeout_ms: Option<u64>) {
+        self.timeout = timeout_ms;
+    }
+
+    /// Set the maximum size of request line and header section. Exceeding it results in 431.
+    pub fn set_max_header_size(&mut self, size: uint) {
+        self.max_header_size = size;
+    }
+
+    /// Set the maximum size of request body. Exceeding it results in 413.
+    pub fn set_max_body_size(&mut self, size: uint) {
+        self.max_body_size = size;
+    }
+
+    /// Set the minimum size of response body to compress, 256 bytes by default. `None` disables
+    /// compression.
+    ///
+    /// `gzip` or `deflate` is negotiated from `Accept-Encoding` of the request.
+    pub fn set_compression(&mut self, min_size: Option<uint>) {
+        self.compress_min_size = min_size;
+    }
+
+    /// Listen on the address, and serve forever.
+    pub fn listen(self, host: &str, port: u16) -> IoResult<()> {
+        let listener = try!(TcpListener::bind(host, port));
+        self.serve(try!(listener.listen()))
+    }
+
+    /// Accept connections from the acceptor, and serve each connection in a new task.
+    pub fn serve<A: Acceptor<TcpStream>>(self, mut acceptor: A) -> IoResult<()> {
+        loop {
+            let stream = try!(acceptor.accept());
+            let server = self.clone();
+            spawn(proc() {
+                server.handle_connection(stream)
+            });
+        }
+    }
+
+    #[allow(unused_must_use)]
+    /// Serve requests on the connection until it is closed.
+    pub fn handle_connection(&self, mut stream: TcpStream) {
+        let mut handler = self.handler.clone();
+        let mut parser = Parser::new(ParseRequest);
+        parser.set_max_header_size(self.max_header_size);
+        parser.set_max_body_size(self.max_body_size);
+        let mut reader = MessageReader::with_parser(stream.clone(), parser);
+        loop {
+            // timeouts are deadlines, and are not shared by cloned streams.
+            reader.get_mut().set_read_timeout(self.timeout);
+            let request = match reader.read_message() {
+                Ok(request) => request,
+                Err(ReadParseError(e)) => {
+                    write_error(stream.clone(), error_status(e));
+                    break;
+                }
+                Err(ReadIoError(ref e)) if e.kind == EndOfFile => break,
+                Err(ReadIoError(_)) => break,
+            };
+            // HTTP/2 is not supported, `Upgrade: h2c` is ignored.
+            if request.http2 == Some(Http2Preface) { break }
+            let version = match request.version {
+                Some(http::HTTP_0_9) | None => {
+                    write_error(stream.clone(), status::HTTP_VERSION_NOT_SUPPORTED);
+                    break;
+                }
+                Some(version) => version,
+            };
+
+            let mut response = Response::new();
+            handler.handle(&request, &mut reader.body(), &mut response);
+
+            // drain unread body to find the next request, or an invalid body.
+            let mut buf = Vec::from_elem(4096, 0u8);
+            loop {
+                match reader.body().read(buf.as_mut_slice()) {
+                    Ok(_) => (),
+                    Err(_) => break,
+                }
+            }
+            match reader.error() {
+                Some(e) => {
+                    write_error(stream.clone(), error_status(e));
+                    break;
+                }
+                None => (),
+            }
+
+            let keep_alive = request.keep_alive && !request.upgrade && match response.header("Connection") {
+                Some(value) => !value.eq_ignore_ascii_case("close"),
+                None => true,
+            };
+            let head = request.method == Some(http::HttpHead);
+            let coding = self.negotiate_coding(&request, &response);
+            if write_response(stream.clone(), version, &response, keep_alive, head, coding).is_err() || !keep_alive {
+                break;
+            }
+        }
+        stream.close_read();
+        stream.close_write();
+    }
+
+    fn negotiate_coding(&self, request: &Message, response: &Response) -> Option<ContentCoding> {
+        let min_size = match self.compress_min_size {
+            Some(size) => size,
+            None => return None,
+        };
+        if response.body.len() < min_size || response.header("Content-Encoding").is_some() { return None }
+        match request.header("Accept-Encoding") {
+            Some(value) => AcceptEncoding::parse(value).negotiate([CodingGzip, CodingDeflate]),
+            None => None,
+        }
+    }
+}
+
+/// Returns response status for the parse error.
+///
+/// - `HeaderTooLarge` is `431 Request Header Fields Too Large`
+/// - `BodyTooLarge` is `413 Content Too Large`
+/// - Others are `400 Bad Request`
+pub fn error_status(e: ParseError) -> http::StatusCode {
+    match e {
+        HeaderTooLarge => status::REQUEST_HEADER_FIELDS_TOO_LARGE,
+        BodyTooLarge => status::CONTENT_TOO_LARGE,
+        _ => status::BAD_REQUEST,
+    }
+}
+
+fn write_response<W: Writer>(writer: W, version: http::HttpVersion, response: &Response, keep_alive: bool,
+                             head: bool, coding: Option<ContentCoding>) -> IoResult<W> {
+    let mut w = MessageWriter::new(writer);
+    try!(w.status_line(version, response.status));
+    for &(ref name, ref value) in response.headers.iter() {
+        if name.as_slice().eq_ignore_ascii_case("Connection") ||
+            name.as_slice().eq_ignore_ascii_case("Content-Length") {
+            continue;
+        }
+        try!(w.header(name.as_slice(), value.as_slice()));
+    }
+    try!(w.header("Connection", if keep_alive { "keep-alive" } else { "close" }));
+    let compresses = match coding {
+        // identity is chosen, but the response still depends on Accept-Encoding.
+        Some(CodingIdentity) => {
+            try!(w.header("Vary", "Accept-Encoding"));
+            false
+        }
+        Some(coding) => {
+            try!(w.content_coding(coding));
+            true
+        }
+        None => false,
+    };
+    if head {
+        // compressed length is unknown without compressing the body.
+        if !compresses {
+            try!(w.header("Content-Length", response.body.len().to_string().as_slice()));
+        }
+        w.no_body();
+        w.finish()
+    } else {
+        w.body(response.body.as_slice())
+    }
+}
+
+#[allow(unused_must_use)]
+fn write_error<W: Writer>(writer: W, status: http::StatusCode) {
+    let mut response = Response::new();
+    response.status = status;
+    response.set_header("Content-Type", "text/plain");
+    write!(response, "{}", status);
+    write_response(writer, http::HTTP_1_1, &response, false, false, None);
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,190 @@
+use http::*;
+use http::cookie::SetCookie;
+use http::encoding::{CodingGzip, decode};
+use http::parser::ParseResponse;
+use http::reader::{Message, MessageReader, ReadIoError};
+use server::*;
+
+use std::io::{Acceptor, Listener, EndOfFile, TcpListener, TcpStream};
+use std::io::timer::sleep;
+
+#[deriving(Clone)]
+struct EchoHandler;
+
+impl Handler for EchoHandler {
+    #[allow(unused_must_use)]
+    fn handle(&mut self, request: &Message, body: &mut Reader, response: &mut Response) {
+        let body = match body.read_to_end() {
+            Ok(body) => body,
+            Err(_) => return,
+        };
+        response.set_header("Content-Type", "text/plain");
+        write!(response, "{} {} ", request.method.unwrap(), request.url.clone().unwrap());
+        response.write(body.as_slice());
+    }
+}
+
+fn serve_once<H: Handler>(server: Server<H>) -> TcpStream {
+    let mut listener = TcpListener::bind("127.0.0.1", 0).unwrap();
+    let port = listener.socket_name().unwrap().port;
+    let mut acceptor = listener.listen().unwrap();
+    spawn(proc() {
+        let stream = acceptor.accept().unwrap();
+        server.handle_connection(stream);
+    });
+    TcpStream::connect("127.0.0.1", port).unwrap()
+}
+
+fn assert_closed(reader: &mut MessageReader<TcpStream>) {
+    match reader.read_message() {
+        Err(ReadIoError(ref e)) if e.kind == EndOfFile => (),
+        other => fail!("unexpected result: {}", other),
+    }
+}
+
+#[test]
+fn test_keep_alive() {
+    let mut stream = serve_once(Server::new(EchoHandler));
+    stream.write("POST /echo HTTP/1.1\r\nContent-Length: 3\r\n\r\nbee\
+                  GET /next HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.status, Some(status::OK));
+    assert_eq!(response.header("Connection"), Some("keep-alive"));
+    assert_eq!(reader.body().read_to_end().unwrap(), "POST /echo bee".as_bytes().to_vec());
+
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.header("Connection"), Some("close"));
+    assert_eq!(reader.body().read_to_end().unwrap(), "GET /next ".as_bytes().to_vec());
+    assert_closed(&mut reader);
+}
+
+#[test]
+fn test_head() {
+    let mut stream = serve_once(Server::new(EchoHandler));
+    stream.write("HEAD /head HTTP/1.0\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+    reader.set_skip_body(true);
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.version, Some(HTTP_1_0));
+    assert_eq!(response.header("Content-Length"), Some("11"));
+    reader.set_skip_body(false);
+    assert_closed(&mut reader);
+}
+
+#[test]
+fn test_bad_request() {
+    let mut stream = serve_once(Server::new(EchoHandler));
+    stream.write("GET / HTTP/1.1\r\nBad Header\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.status, Some(status::BAD_REQUEST));
+    assert_eq!(response.header("Connection"), Some("close"));
+}
+
+#[test]
+fn test_header_too_large() {
+    let mut server = Server::new(EchoHandler);
+    server.set_max_header_size(32);
+    let mut stream = serve_once(server);
+    stream.write("GET / HTTP/1.1\r\nUser-Agent: a very long user agent\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.status, Some(status::REQUEST_HEADER_FIELDS_TOO_LARGE));
+}
+
+#[test]
+fn test_body_too_large() {
+    let mut server = Server::new(EchoHandler);
+    server.set_max_body_size(4);
+    let mut stream = serve_once(server);
+    stream.write("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\n0123456789".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.status, Some(status::CONTENT_TOO_LARGE));
+}
+
+#[test]
+fn test_chunked_body_too_large() {
+    let mut server = Server::new(EchoHandler);
+    server.set_max_body_size(4);
+    let mut stream = serve_once(server);
+    stream.write("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nbee\r\n3\r\nbee\r\n0\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.status, Some(status::CONTENT_TOO_LARGE));
+}
+
+#[test]
+fn test_timeout() {
+    let mut server = Server::new(EchoHandler);
+    server.set_timeout(Some(50));
+    let stream = serve_once(server);
+    let mut reader = MessageReader::new(stream, ParseResponse);
+    assert_closed(&mut reader);
+}
+
+#[test]
+fn test_timeout_per_request() {
+    let mut server = Server::new(EchoHandler);
+    server.set_timeout(Some(1000));
+    let mut stream = serve_once(server);
+    stream.write("GET /first HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream.clone(), ParseResponse);
+    assert_eq!(reader.read_message().unwrap().status, Some(status::OK));
+    assert_eq!(reader.body().read_to_end().unwrap(), "GET /first ".as_bytes().to_vec());
+
+    // the idle time of each request is well within the timeout, but the total is not.
+    for _ in range(0u, 3) {
+        sleep(400);
+        stream.write("GET /next HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+        assert_eq!(reader.read_message().unwrap().status, Some(status::OK));
+        assert_eq!(reader.body().read_to_end().unwrap(), "GET /next ".as_bytes().to_vec());
+    }
+    assert_closed(&mut reader);
+}
+
+#[test]
+fn test_compression() {
+    let mut server = Server::new(EchoHandler);
+    server.set_compression(Some(0));
+    let mut stream = serve_once(server);
+    stream.write("POST /echo HTTP/1.1\r\nAccept-Encoding: deflate;q=0.5, gzip\r\nContent-Length: 3\r\n\r\nbee\
+                  HEAD /head HTTP/1.1\r\nAccept-Encoding: gzip;q=0\r\n\r\n\
+                  GET /next HTTP/1.1\r\nConnection: close\r\n\r\n".as_bytes()).unwrap();
+    let mut reader = MessageReader::new(stream, ParseResponse);
+
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    let body = reader.body().read_to_end().unwrap();
+    assert_eq!(decode(CodingGzip, body.as_slice(), 1024), Ok("POST /echo bee".as_bytes().to_vec()));
+
+    reader.set_skip_body(true);
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), Some("Accept-Encoding"));
+    assert_eq!(response.header("Content-Length"), Some("11"));
+    reader.set_skip_body(false);
+
+    let response = reader.read_message().unwrap();
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(response.header("Vary"), None);
+    assert_eq!(reader.body().read_to_end().unwrap(), "GET /next ".as_bytes().to_vec());
+}
+
+#[test]
+fn test_error_status() {
+    assert_eq!(error_status(parser::HeaderTooLarge), status::REQUEST_HEADER_FIELDS_TOO_LARGE);
+    assert_eq!(error_status(parser::BodyTooLarge), status::CONTENT_TOO_LARGE);
+    assert_eq!(error_status(parser::InvalidMethod), status::BAD_REQUEST);
+}
+
+#[test]
+fn test_add_cookie() {
+    let mut response = Response::new();
+    assert!(response.add_cookie(&SetCookie::new("id", "a3fWa")));
+    assert!(!response.add_cookie(&SetCookie::new("id", "x; Domain=evil.com")));
+    assert_eq!(response.headers, vec!(("Set-Cookie".to_string(), "id=a3fWa".to_string())));
+}
@@ -0,0 +1,595 @@
+//! WebSocket opening handshake and frame codec, see RFC 6455.
+//!
+//! Validate an upgrade request with `validate_request`, reply with `write_handshake`, and then
+//! feed bytes of the connection to `FrameParser::parse`. Payload of text frames is not validated
+//! as UTF-8.
+
+#![experimental]
+
+use std::ascii::StrAsciiExt;
+use std::io::{IoError, IoResult, InvalidInput};
+use serialize::base64::{ToBase64, FromBase64, STANDARD};
+
+use http;
+use http::reader::Message;
+use http::serializer::MessageWriter;
+use http::status;
+
+/// Normal closure.
+pub static NORMAL_CLOSURE: u16      = 1000;
+/// The endpoint is going away, e.g. server shutdown.
+pub static GOING_AWAY: u16          = 1001;
+/// Protocol error.
+pub static PROTOCOL_ERROR: u16      = 1002;
+/// Received data type cannot be accepted.
+pub static UNSUPPORTED_DATA: u16    = 1003;
+/// Text message is not valid UTF-8.
+pub static INVALID_PAYLOAD: u16     = 1007;
+/// Message violates the policy.
+pub static POLICY_VIOLATION: u16    = 1008;
+/// Message is too big to process.
+pub static MESSAGE_TOO_BIG: u16     = 1009;
+/// Server did not negotiate the extensions the client expected.
+pub static MANDATORY_EXTENSION: u16 = 1010;
+/// Unexpected condition on the server.
+pub static INTERNAL_ERROR: u16      = 1011;
+
+static GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// A list specifying categories of handshake errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum HandshakeError {
+    /// Not a WebSocket upgrade request or response.
+    InvalidUpgrade,
+    /// `Sec-WebSocket-Key` is missing or not 16 bytes base64.
+    InvalidKey,
+    /// `Sec-WebSocket-Accept` does not match the key.
+    InvalidAccept,
+    /// `Sec-WebSocket-Version` is not 13.
+    UnsupportedVersion,
+}
+
+impl HandshakeError {
+    /// Returns response status for the error.
+    ///
+    /// `426 Upgrade Required` should be sent with `Sec-WebSocket-Version: 13` header.
+    pub fn status(&self) -> http::StatusCode {
+        match *self {
+            UnsupportedVersion => status::UPGRADE_REQUIRED,
+            _ => status::BAD_REQUEST,
+        }
+    }
+}
+
+/// Result of handshake validation.
+pub type HandshakeResult<T> = Result<T, HandshakeError>;
+
+/// Validate a WebSocket upgrade request, and returns `Sec-WebSocket-Accept` value.
+pub fn validate_request(request: &Message) -> HandshakeResult<String> {
+    if request.method != Some(http::HttpGet) || request.version != Some(http::HTTP_1_1) {
+        return Err(InvalidUpgrade);
+    }
+    if !has_token(request.headers_named("Upgrade"), "websocket") ||
+        !has_token(request.headers_named("Connection"), "upgrade") {
+        return Err(InvalidUpgrade);
+    }
+    match request.header("Sec-WebSocket-Version") {
+        Some(version) if version.trim() == "13" => (),
+        _ => return Err(UnsupportedVersion),
+    }
+    let key = match request.header("Sec-WebSocket-Key") {
+        Some(key) => key.trim(),
+        None => return Err(InvalidKey),
+    };
+    match key.from_base64() {
+        Ok(ref nonce) if nonce.len() == 16 => Ok(accept_key(key)),
+        _ => Err(InvalidKey),
+    }
+}
+
+/// Validate a response to the upgrade request sent with the key.
+pub fn validate_response(response: &Message, key: &str) -> HandshakeResult<()> {
+    if response.status != Some(status::SWITCHING_PROTOCOLS) ||
+        !has_token(response.headers_named("Upgrade"), "websocket") ||
+        !has_token(response.headers_named("Connection"), "upgrade") {
+        return Err(InvalidUpgrade);
+    }
+    match response.header("Sec-WebSocket-Accept") {
+        Some(accept) if accept.trim() == accept_key(key).as_slice() => Ok(()),
+        _ => Err(InvalidAccept),
+    }
+}
+
+/// Compute `Sec-WebSocket-Accept` value for the key.
+pub fn accept_key(key: &str) -> String {
+    let mut data = key.as_bytes().to_vec();
+    data.push_all(GUID.as_bytes());
+    sha1(data.as_slice()).to_base64(STANDARD)
+}
+
+/// Write `101 Switching Protocols` response to accept the upgrade.
+pub fn write_handshake<W: Writer>(writer: W, accept: &str) -> IoResult<W> {
+    let mut w = MessageWriter::new(writer);
+    try!(w.status_line(http::HTTP_1_1, status::SWITCHING_PROTOCOLS));
+    try!(w.header("Upgrade", "websocket"));
+    try!(w.header("Connection", "Upgrade"));
+    try!(w.header("Sec-WebSocket-Accept", accept));
+    w.finish()
+}
+
+/// A list of frame opcodes.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum Opcode {
+    /// Continuation of a fragmented message.
+    OpContinuation,
+    /// Text message.
+    OpText,
+    /// Binary message.
+    OpBinary,
+    /// Close control frame.
+    OpClose,
+    /// Ping control frame.
+    OpPing,
+    /// Pong control frame.
+    OpPong,
+}
+
+impl Opcode {
+    /// Detect opcode with the 4 bits value.
+    pub fn find(code: u8) -> Option<Opcode> {
+        match code {
+            0x0 => Some(OpContinuation),
+            0x1 => Some(OpText),
+            0x2 => Some(OpBinary),
+            0x8 => Some(OpClose),
+            0x9 => Some(OpPing),
+            0xa => Some(OpPong),
+            _ => None,
+        }
+    }
+
+    /// Returns the 4 bits value.
+    pub fn code(&self) -> u8 {
+        match *self {
+            OpContinuation => 0x0,
+            OpText         => 0x1,
+            OpBinary       => 0x2,
+            OpClose        => 0x8,
+            OpPing         => 0x9,
+            OpPong         => 0xa,
+        }
+    }
+
+    /// Control frames can be interleaved with fragments of a message.
+    pub fn is_control(&self) -> bool {
+        self.code() & 0x8 != 0
+    }
+}
+
+/// Frame header.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub struct FrameHeader {
+    /// The final fragment of a message.
+    pub fin: bool,
+    /// Frame opcode.
+    pub opcode: Opcode,
+    /// Payload is masked.
+    pub masked: bool,
+    /// Payload length.
+    pub length: u64,
+}
+
+/// Frame parser event handler.
+pub trait FrameHandler {
+    #[allow(unused_variable)]
+    /// Called when a frame header parsed.
+    fn on_frame_begin(&mut self, parser: &FrameParser, header: &FrameHeader) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when a close frame parsed the status code. `None` means no status code.
+    fn on_close(&mut self, parser: &FrameParser, code: Option<u16>) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when a piece of unmasked payload parsed. Close reason is passed without status code.
+    fn on_payload(&mut self, parser: &FrameParser, data: &[u8]) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when completed to parsing of a frame.
+    fn on_frame_end(&mut self, parser: &FrameParser) {
+    }
+
+    #[allow(unused_variable)]
+    /// Called when completed to parsing of the final fragment of a text or binary message.
+    fn on_message_complete(&mut self, parser: &FrameParser) {
+    }
+}
+
+/// A list specifying categories of frame parse errors.
+#[deriving(PartialEq, Eq, Clone, Show)]
+pub enum FrameError {
+    /// Any parse error not part of this list.
+    OtherFrameError,
+    /// Reserved bits are set without extensions.
+    ReservedBits,
+    /// Unknown opcode.
+    InvalidOpcode,
+    /// Client frame is not masked, or server frame is masked.
+    InvalidMask,
+    /// Payload length is not encoded in the minimal number of bytes, or exceeds 63 bits.
+    InvalidPayloadLength,
+    /// Control frame is fragmented, or payload is longer than 125 bytes.
+    InvalidControlFrame,
+    /// Continuation frame without a fragmented message.
+    UnexpectedContinuation,
+    /// New message before the final fragment of a fragmented message.
+    ExpectedContinuation,
+    /// Close frame with an invalid status code.
+    InvalidCloseCode,
+    /// Payload exceeds the limit.
+    PayloadTooLarge,
+}
+
+impl FrameError {
+    /// Returns the status code to close the connection with.
+    pub fn close_code(&self) -> u16 {
+        match *self {
+            PayloadTooLarge => MESSAGE_TOO_BIG,
+            _ => PROTOCOL_ERROR,
+        }
+    }
+}
+
+/// Result of frame parsing.
+pub type FrameResult = Result<uint, FrameError>;
+
+/// Incremental WebSocket frame parser.
+pub struct FrameParser {
+    state: FrameState,
+    masked: bool,
+    max_payload_size: u64,
+    header: FrameHeader,
+    mask: [u8, ..4],
+    index: uint,
+    offset: u64,
+    close_code: u16,
+    message: Option<Opcode>,
+}
+
+impl FrameParser {
+    /// Create a new `FrameParser`.
+    ///
+    /// `masked` must be true on servers to parse client frames, and false on clients.
+    pub fn new(masked: bool) -> FrameParser {
+        FrameParser {
+            state: FrStart,
+            masked: masked,
+            max_payload_size: ::std::u64::MAX,
+            header: FrameHeader { fin: false, opcode: OpContinuation, masked: false, length: 0 },
+            mask: [0, ..4],
+            index: 0,
+            offset: 0,
+            close_code: 0,
+            message: None,
+        }
+    }
+
+    /// Set the maximum payload length of a frame. Exceeding it results in `PayloadTooLarge`.
+    pub fn set_max_payload_size(&mut self, size: u64) {
+        self.max_payload_size = size;
+    }
+
+    /// Parse frames, and returns parsed bytes length.
+    pub fn parse<H: FrameHandler>(&mut self, data: &[u8], handler: &mut H) -> FrameResult {
+        if self.state == FrCrashed { return Err(OtherFrameError) }
+
+        let mut pos = 0u;
+        while pos < data.len() {
+            let byte = data[pos];
+            match self.state {
+                FrStart => {
+                    if byte & 0x70 != 0 { return self.crash(ReservedBits) }
+                    let fin = byte & 0x80 != 0;
+                    let opcode = match Opcode::find(byte & 0x0f) {
+                        Some(opcode) => opcode,
+                        None => return self.crash(InvalidOpcode),
+                    };
+                    if opcode.is_control() {
+                        if !fin { return self.crash(InvalidControlFrame) }
+                    } else if opcode == OpContinuation {
+                        if self.message.is_none() { return self.crash(UnexpectedContinuation) }
+                    } else if self.message.is_some() {
+                        return self.crash(ExpectedContinuation);
+                    }
+                    self.header = FrameHeader { fin: fin, opcode: opcode, masked: false, length: 0 };
+                    self.state = FrLength;
+                }
+                FrLength => {
+                    self.header.masked = byte & 0x80 != 0;
+                    if self.header.masked != self.masked { return self.crash(InvalidMask) }
+                    let length = byte & 0x7f;
+                    if self.header.opcode.is_control() && length > 125 { return self.crash(InvalidControlFrame) }
+                    match length {
+                        126 => {
+                            self.state = FrLength16;
+                            self.index = 2;
+                        }
+                        127 => {
+                            self.state = FrLength64;
+                            self.index = 8;
+                        }
+                        _ => {
+                            self.header.length = length as u64;
+                            try!(self.length_complete(handler));
+                        }
+                    }
+                }
+                FrLength16 | FrLength64 => {
+                    if self.index == 8 && byte & 0x80 != 0 { return self.crash(InvalidPayloadLength) }
+                    self.header.length = self.header.length << 8 | byte as u64;
+                    self.index -= 1;
+                    if self.index == 0 {
+                        // the minimal number of bytes must be used.
+                        let min = if self.state == FrLength64 { 0x10000 } else { 126 };
+                        if self.header.length < min { return self.crash(InvalidPayloadLength) }
+                        try!(self.length_complete(handler));
+                    }
+                }
+                FrMaskKey => {
+                    self.mask[self.index] = byte;
+                    self.index += 1;
+                    if self.index == 4 {
+                        try!(self.header_complete(handler));
+                    }
+                }
+                FrCloseCode => {
+                    let byte = self.unmask(byte);
+                    self.close_code = self.close_code << 8 | byte as u16;
+                    self.offset += 1;
+                    if self.offset == 2 {
+                        if !is_valid_close_code(self.close_code) { return self.crash(InvalidCloseCode) }
+                        handler.on_close(self, Some(self.close_code));
+                        if self.offset == self.header.length {
+                            self.frame_complete(handler);
+                        } else {
+                            self.state = FrPayload;
+                        }
+                    }
+                }
+                FrPayload => {
+                    let rest = self.header.length - self.offset;
+                    let end = if rest < (data.len() - pos) as u64 { pos + rest as uint } else { data.len() };
+                    let payload = data.slice(pos, end);
+                    if self.header.masked {
+                        let mut unmasked = Vec::with_capacity(payload.len());
+                        for &byte in payload.iter() {
+                            unmasked.push(self.unmask(byte));
+                            self.offset += 1;
+                        }
+                        handler.on_payload(self, unmasked.as_slice());
+                    } else {
+                        self.offset += payload.len() as u64;
+                        handler.on_payload(self, payload);
+                    }
+                    if self.offset == self.header.length {
+                        self.frame_complete(handler);
+                    }
+                    pos = end;
+                    continue;
+                }
+                FrCrashed => unreachable!(),
+            }
+            pos += 1;
+        }
+        Ok(pos)
+    }
+
+    #[inline]
+    /// Returns the header of the current frame.
+    pub fn header(&self) -> FrameHeader {
+        self.header.clone()
+    }
+
+    #[inline]
+    /// Returns the opcode of the current text or binary message, including its continuation frames.
+    pub fn message_opcode(&self) -> Option<Opcode> {
+        self.message
+    }
+
+    #[inline]
+    /// Returns true if the parser is between frames.
+    pub fn is_idle(&self) -> bool {
+        self.state == FrStart
+    }
+
+    fn length_complete<H: FrameHandler>(&mut self, handler: &mut H) -> Result<(), FrameError> {
+        if self.header.masked {
+            self.state = FrMaskKey;
+            self.index = 0;
+            Ok(())
+        } else {
+            self.header_complete(handler)
+        }
+    }
+
+    fn header_complete<H: FrameHandler>(&mut self, handler: &mut H) -> Result<(), FrameError> {
+        if self.header.length > self.max_payload_size { return self.crash(PayloadTooLarge) }
+        if self.header.opcode == OpClose && self.header.length == 1 { return self.crash(InvalidCloseCode) }
+        match self.header.opcode {
+            OpText | OpBinary => self.message = Some(self.header.opcode),
+            _ => (),
+        }
+        self.offset = 0;
+        self.close_code = 0;
+        handler.on_frame_begin(self, &self.header);
+        if self.header.opcode == OpClose {
+            if self.header.length == 0 {
+                handler.on_close(self, None);
+                self.frame_complete(handler);
+            } else {
+                self.state = FrCloseCode;
+            }
+        } else if self.header.length == 0 {
+            self.frame_complete(handler);
+        } else {
+            self.state = FrPayload;
+        }
+        Ok(())
+    }
+
+    fn frame_complete<H: FrameHandler>(&mut self, handler: &mut H) {
+        handler.on_frame_end(self);
+        if !self.header.opcode.is_control() && self.header.fin {
+            handler.on_message_complete(self);
+            self.message = None;
+        }
+        self.state = FrStart;
+    }
+
+    #[inline]
+    fn unmask(&self, byte: u8) -> u8 {
+        if self.header.masked { byte ^ self.mask[(self.offset % 4) as uint] } else { byte }
+    }
+
+    fn crash<T>(&mut self, e: FrameError) -> Result<T, FrameError> {
+        self.state = FrCrashed;
+        Err(e)
+    }
+}
+
+/// Write a frame. Frames sent by clients must be masked with a random key.
+pub fn write_frame<W: Writer>(writer: &mut W, fin: bool, opcode: Opcode, mask: Option<[u8, ..4]>,
+                              payload: &[u8]) -> IoResult<()> {
+    if opcode.is_control() && (!fin || payload.len() > 125) {
+        return Err(IoError {
+            kind: InvalidInput,
+            desc: "control frame must not be fragmented, or longer than 125 bytes",
+            detail: None,
+        });
+    }
+    let mut head = Vec::with_capacity(14);
+    head.push(if fin { 0x80 } else { 0 } | opcode.code());
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    let length = payload.len() as u64;
+    if length < 126 {
+        head.push(mask_bit | length as u8);
+    } else if length <= 0xffff {
+        head.push(mask_bit | 126);
+        head.push_all([(length >> 8) as u8, length as u8]);
+    } else {
+        head.push(mask_bit | 127);
+        for i in range(0u, 8).rev() {
+            head.push((length >> (i * 8)) as u8);
+        }
+    }
+    match mask {
+        Some(key) => {
+            head.push_all(key);
+            try!(writer.write(head.as_slice()));
+            let masked: Vec<u8> = payload.iter().enumerate().map(|(i, &byte)| byte ^ key[i % 4]).collect();
+            writer.write(masked.as_slice())
+        }
+        None => {
+            try!(writer.write(head.as_slice()));
+            writer.write(payload)
+        }
+    }
+}
+
+/// Write a close frame with the status code and reason.
+pub fn write_close<W: Writer>(writer: &mut W, code: Option<u16>, reason: &str,
+                              mask: Option<[u8, ..4]>) -> IoResult<()> {
+    let mut payload = Vec::new();
+    match code {
+        Some(code) => {
+            payload.push_all([(code >> 8) as u8, code as u8]);
+            payload.push_all(reason.as_bytes());
+        }
+        None => (),
+    }
+    write_frame(writer, true, OpClose, mask, payload.as_slice())
+}
+
+/// Returns true if the status code can be sent in a close frame.
+pub fn is_valid_close_code(code: u16) -> bool {
+    match code {
+        1000..1003 | 1007..1014 | 3000..4999 => true,
+        _ => false,
+    }
+}
+
+fn has_token(values: Vec<&str>, token: &str) -> bool {
+    values.iter().any(|value| value.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+}
+
+fn sha1(data: &[u8]) -> [u8, ..20] {
+    let mut h = [0x67452301u32, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = data.to_vec();
+    let bit_length = data.len() as u64 * 8;
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    for i in range(0u, 8).rev() {
+        message.push((bit_length >> (i * 8)) as u8);
+    }
+
+    for block in message.as_slice().chunks(64) {
+        let mut w = [0u32, ..80];
+        for i in range(0u, 16) {
+            w[i] = block[i * 4] as u32 << 24 | block[i * 4 + 1] as u32 << 16 |
+                block[i * 4 + 2] as u32 << 8 | block[i * 4 + 3] as u32;
+        }
+        for i in range(16u, 80) {
+            w[i] = rotate_left(w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16], 1);
+        }
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for i in range(0u, 80) {
+            let (f, k) = match i {
+                0..19  => ((b & c) | (!b & d), 0x5a827999u32),
+                20..39 => (b ^ c ^ d, 0x6ed9eba1u32),
+                40..59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdcu32),
+                _      => (b ^ c ^ d, 0xca62c1d6u32),
+            };
+            let temp = rotate_left(a, 5) + f + e + k + w[i];
+            e = d;
+            d = c;
+            c = rotate_left(b, 30);
+            b = a;
+            a = temp;
+        }
+        h[0] += a;
+        h[1] += b;
+        h[2] += c;
+        h[3] += d;
+        h[4] += e;
+    }
+
+    let mut digest = [0u8, ..20];
+    for i in range(0u, 20) {
+        digest[i] = (h[i / 4] >> (24 - (i % 4) * 8)) as u8;
+    }
+    digest
+}
+
+#[inline]
+fn rotate_left(x: u32, n: uint) -> u32 {
+    (x << n) | (x >> (32 - n))
+}
+
+#[deriving(PartialEq, Eq, Clone, Show)]
+enum FrameState {
+    FrStart,
+    FrLength,
+    FrLength16,
+    FrLength64,
+    FrMaskKey,
+    FrCloseCode,
+    FrPayload,
+    FrCrashed,
+}
+
+#[cfg(test)]
+mod tests;
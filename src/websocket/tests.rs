@@ -0,0 +1,220 @@
+use http;
+use http::parser::ParseResponse;
+use http::reader::{Message, MessageReader};
+use websocket::*;
+
+use std::io::{MemReader, MemWriter};
+
+struct TestHandler {
+    events: Vec<String>,
+    payload: Vec<u8>,
+}
+
+impl TestHandler {
+    fn new() -> TestHandler {
+        TestHandler {
+            events: Vec::new(),
+            payload: Vec::new(),
+        }
+    }
+}
+
+impl FrameHandler for TestHandler {
+    fn on_frame_begin(&mut self, _: &FrameParser, header: &FrameHeader) {
+        self.events.push(format!("begin {} {} {}", header.opcode, header.fin, header.length));
+    }
+
+    fn on_close(&mut self, _: &FrameParser, code: Option<u16>) {
+        self.events.push(format!("close {}", code));
+    }
+
+    fn on_payload(&mut self, _: &FrameParser, data: &[u8]) {
+        self.payload.push_all(data);
+    }
+
+    fn on_frame_end(&mut self, _: &FrameParser) {
+        let payload = String::from_utf8_lossy(self.payload.as_slice()).into_string();
+        self.events.push(format!("end {}", payload));
+        self.payload.clear();
+    }
+
+    fn on_message_complete(&mut self, parser: &FrameParser) {
+        self.events.push(format!("message {}", parser.message_opcode()));
+    }
+}
+
+fn upgrade_request() -> Message {
+    let mut request = Message::new();
+    request.method = Some(http::HttpGet);
+    request.url = Some("/chat".to_string());
+    request.version = Some(http::HTTP_1_1);
+    request.headers = vec!(("Host".to_string(), "server.example.com".to_string()),
+                           ("Upgrade".to_string(), "websocket".to_string()),
+                           ("Connection".to_string(), "keep-alive, Upgrade".to_string()),
+                           ("Sec-WebSocket-Key".to_string(), "dGhlIHNhbXBsZSBub25jZQ==".to_string()),
+                           ("Sec-WebSocket-Version".to_string(), "13".to_string()));
+    request
+}
+
+fn set_header(message: &mut Message, name: &str, value: &str) {
+    message.headers.retain(|&(ref n, _)| n.as_slice() != name);
+    message.headers.push((name.to_string(), value.to_string()));
+}
+
+fn parse_all(parser: &mut FrameParser, data: &[u8]) -> (FrameResult, Vec<String>) {
+    let mut handler = TestHandler::new();
+    let result = parser.parse(data, &mut handler);
+    (result, handler.events)
+}
+
+#[test]
+fn test_handshake() {
+    let accept = validate_request(&upgrade_request()).unwrap();
+    assert_eq!(accept, "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=".to_string());
+
+    let out = write_handshake(MemWriter::new(), accept.as_slice()).unwrap().unwrap();
+    assert_eq!(String::from_utf8(out.clone()).unwrap(),
+               "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n".to_string());
+    let mut reader = MessageReader::new(MemReader::new(out), ParseResponse);
+    let response = reader.read_message().unwrap();
+    assert_eq!(validate_response(&response, "dGhlIHNhbXBsZSBub25jZQ=="), Ok(()));
+    assert_eq!(validate_response(&response, "AQIDBAUGBwgJCgsMDQ4PEA=="), Err(InvalidAccept));
+}
+
+#[test]
+fn test_invalid_handshake() {
+    let mut request = upgrade_request();
+    set_header(&mut request, "Sec-WebSocket-Version", "8");
+    assert_eq!(validate_request(&request), Err(UnsupportedVersion));
+    assert_eq!(UnsupportedVersion.status(), http::status::UPGRADE_REQUIRED);
+
+    let mut request = upgrade_request();
+    set_header(&mut request, "Sec-WebSocket-Key", "c2hvcnQ=");
+    assert_eq!(validate_request(&request), Err(InvalidKey));
+    assert_eq!(InvalidKey.status(), http::status::BAD_REQUEST);
+
+    let mut request = upgrade_request();
+    set_header(&mut request, "Upgrade", "h2c");
+    assert_eq!(validate_request(&request), Err(InvalidUpgrade));
+
+    let mut request = upgrade_request();
+    request.method = Some(http::HttpPost);
+    assert_eq!(validate_request(&request), Err(InvalidUpgrade));
+}
+
+#[test]
+fn test_frames() {
+    // examples of RFC 6455 section 5.7
+    let mut parser = FrameParser::new(false);
+    let data = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+                0x01, 0x03, 0x48, 0x65, 0x6c,
+                0x89, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f,
+                0x80, 0x02, 0x6c, 0x6f];
+    let (result, events) = parse_all(&mut parser, data);
+    assert_eq!(result, Ok(data.len()));
+    assert_eq!(events, vec!("begin OpText true 5".to_string(), "end Hello".to_string(),
+                            "message Some(OpText)".to_string(),
+                            "begin OpText false 3".to_string(), "end Hel".to_string(),
+                            "begin OpPing true 5".to_string(), "end Hello".to_string(),
+                            "begin OpContinuation true 2".to_string(), "end lo".to_string(),
+                            "message Some(OpText)".to_string()));
+    assert!(parser.is_idle());
+
+    // byte by byte
+    let mut parser = FrameParser::new(false);
+    let mut handler = TestHandler::new();
+    for byte in data.chunks(1) {
+        assert_eq!(parser.parse(byte, &mut handler), Ok(1));
+    }
+    assert_eq!(handler.events, events);
+}
+
+#[test]
+fn test_masked_frame() {
+    let mut parser = FrameParser::new(true);
+    let data = [0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58];
+    let (result, events) = parse_all(&mut parser, data);
+    assert_eq!(result, Ok(data.len()));
+    assert_eq!(events, vec!("begin OpText true 5".to_string(), "end Hello".to_string(),
+                            "message Some(OpText)".to_string()));
+
+    let mut parser = FrameParser::new(true);
+    assert_eq!(parse_all(&mut parser, [0x81, 0x05, 0x48]).val0(), Err(InvalidMask));
+    let mut parser = FrameParser::new(false);
+    assert_eq!(parse_all(&mut parser, data).val0(), Err(InvalidMask));
+}
+
+#[test]
+fn test_close_frame() {
+    let mut parser = FrameParser::new(false);
+    let (result, events) = parse_all(&mut parser, [0x88, 0x05, 0x03, 0xe8, 0x62, 0x79, 0x65, 0x88, 0x00]);
+    assert_eq!(result, Ok(9));
+    assert_eq!(events, vec!("begin OpClose true 5".to_string(), "close Some(1000)".to_string(),
+                            "end bye".to_string(),
+                            "begin OpClose true 0".to_string(), "close None".to_string(),
+                            "end ".to_string()));
+
+    let mut parser = FrameParser::new(false);
+    assert_eq!(parse_all(&mut parser, [0x88, 0x02, 0x03, 0xed]).val0(), Err(InvalidCloseCode)); // 1005
+    let mut parser = FrameParser::new(false);
+    assert_eq!(parse_all(&mut parser, [0x88, 0x01, 0x03]).val0(), Err(InvalidCloseCode));
+    assert_eq!(InvalidCloseCode.close_code(), PROTOCOL_ERROR);
+
+    assert!(is_valid_close_code(NORMAL_CLOSURE));
+    assert!(is_valid_close_code(4000));
+    assert!(!is_valid_close_code(1006));
+    assert!(!is_valid_close_code(2000));
+}
+
+#[test]
+fn test_invalid_frames() {
+    let cases = [
+        (vec!(0xc1, 0x00), ReservedBits),
+        (vec!(0x83, 0x00), InvalidOpcode),
+        (vec!(0x80, 0x00), UnexpectedContinuation),
+        (vec!(0x01, 0x00, 0x81, 0x00), ExpectedContinuation),
+        (vec!(0x09, 0x00), InvalidControlFrame),
+        (vec!(0x89, 0x7e, 0x00, 0x7e), InvalidControlFrame),
+        (vec!(0x82, 0x7e, 0x00, 0x7d), InvalidPayloadLength),
+        (vec!(0x82, 0x7f, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff), InvalidPayloadLength),
+        (vec!(0x82, 0x7f, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00), InvalidPayloadLength),
+    ];
+    for &(ref data, error) in cases.iter() {
+        let mut parser = FrameParser::new(false);
+        assert_eq!(parse_all(&mut parser, data.as_slice()).val0(), Err(error));
+        assert_eq!(parse_all(&mut parser, [0x81, 0x00]).val0(), Err(OtherFrameError));
+    }
+
+    let mut parser = FrameParser::new(false);
+    parser.set_max_payload_size(4);
+    assert_eq!(parse_all(&mut parser, [0x82, 0x05]).val0(), Err(PayloadTooLarge));
+    assert_eq!(PayloadTooLarge.close_code(), MESSAGE_TOO_BIG);
+}
+
+#[test]
+fn test_write_frame() {
+    let key = [0x37, 0xfa, 0x21, 0x3d];
+    let mut w = MemWriter::new();
+    write_frame(&mut w, true, OpText, Some(key), "Hello".as_bytes()).unwrap();
+    assert_eq!(w.unwrap(), vec!(0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58));
+
+    for &length in [125u, 126, 65535, 65536].iter() {
+        let payload = Vec::from_elem(length, 0x61u8);
+        let mut w = MemWriter::new();
+        write_frame(&mut w, true, OpBinary, Some(key), payload.as_slice()).unwrap();
+        let mut parser = FrameParser::new(true);
+        let mut handler = TestHandler::new();
+        let data = w.unwrap();
+        assert_eq!(parser.parse(data.as_slice(), &mut handler), Ok(data.len()));
+        assert_eq!(handler.events.get(0), &format!("begin OpBinary true {}", length));
+        assert_eq!(handler.events.get(1).len(), length + 4);
+    }
+
+    let mut w = MemWriter::new();
+    write_close(&mut w, Some(GOING_AWAY), "bye", None).unwrap();
+    assert_eq!(w.unwrap(), vec!(0x88, 0x05, 0x03, 0xe9, 0x62, 0x79, 0x65));
+    let mut w = MemWriter::new();
+    assert!(write_frame(&mut w, false, OpPing, None, []).is_err());
+    assert!(write_frame(&mut w, true, OpPong, None, Vec::from_elem(126, 0u8).as_slice()).is_err());
+}